@@ -0,0 +1,72 @@
+//! An opt-in way to create nodes without passing the `Forest` around.
+
+use std::any::Any;
+use std::cell::RefCell;
+
+use crate::tree::{Forest, Tree};
+
+
+thread_local! {
+    // The forests installed by `Forest::with`, innermost last.
+    static AMBIENT: RefCell<Vec<Box<dyn Any>>> = RefCell::new(vec!());
+}
+
+// Uninstalls the innermost ambient forest when dropped, even if the scope
+// panics.
+struct ScopeGuard;
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        AMBIENT.with(|ambient| ambient.borrow_mut().pop());
+    }
+}
+
+impl<D: 'static, L: 'static> Forest<D, L> {
+    /// Call `f` with this forest installed as the _ambient_ forest of
+    /// this thread, so that `Tree::ambient_leaf`, `Tree::ambient_branch`
+    /// and `Forest::ambient` can be used inside of it without a
+    /// reference to the forest. Scopes may be nested: the innermost
+    /// forest of the right type wins.
+    pub fn with<R, F>(&self, f: F) -> R
+        where F: FnOnce() -> R
+    {
+        AMBIENT.with(|ambient| ambient.borrow_mut().push(Box::new(self.clone())));
+        let _guard = ScopeGuard;
+        f()
+    }
+
+    /// The innermost forest installed by `Forest::with` on this thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no forest of this type is in scope.
+    pub fn ambient() -> Forest<D, L> {
+        AMBIENT.with(|ambient| {
+            ambient.borrow().iter().rev()
+                .find_map(|forest| forest.downcast_ref::<Forest<D, L>>())
+                .cloned()
+                .expect("Forest::ambient - no forest is in scope (see Forest::with)")
+        })
+    }
+}
+
+impl<D: 'static, L: 'static> Tree<D, L> {
+    /// Like `Forest::new_leaf`, on the ambient forest (see `Forest::with`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if no forest of this type is in scope.
+    pub fn ambient_leaf(leaf: L) -> Tree<D, L> {
+        Forest::ambient().new_leaf(leaf)
+    }
+
+    /// Like `Forest::new_branch`, on the ambient forest (see
+    /// `Forest::with`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if no forest of this type is in scope.
+    pub fn ambient_branch(data: D, children: Vec<Tree<D, L>>) -> Tree<D, L> {
+        Forest::ambient().new_branch(data, children)
+    }
+}
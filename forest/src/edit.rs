@@ -0,0 +1,161 @@
+use crate::error::EditError;
+use crate::forest::Id;
+use crate::tree::{Tree, Bookmark};
+
+
+/// A single edit to perform at a [Tree](struct.Tree.html)'s current node.
+pub enum Edit<D, L> {
+    /// Insert the tree as the `i`th child.
+    InsertChild(usize, Tree<D, L>),
+    /// Remove (and delete) the `i`th child.
+    RemoveChild(usize),
+    /// Replace the `i`th child with the tree, deleting the original.
+    ReplaceChild(usize, Tree<D, L>),
+    /// Set the leaf value.
+    SetLeaf(L),
+    /// Set the data value.
+    SetData(D)
+}
+
+/// Several cursor positions within a tree, at which to perform the same
+/// edit at once. (See
+/// [`Tree::apply_at_cursors`](struct.Tree.html#method.apply_at_cursors).)
+#[derive(Clone, Default)]
+pub struct MultiCursor {
+    marks: Vec<Bookmark>
+}
+
+impl MultiCursor {
+    /// Construct a MultiCursor with no cursors.
+    pub fn new() -> MultiCursor {
+        MultiCursor {
+            marks: vec!()
+        }
+    }
+
+    /// Add a cursor.
+    pub fn push(&mut self, mark: Bookmark) {
+        self.marks.push(mark);
+    }
+
+    /// The number of cursors.
+    pub fn len(&self) -> usize {
+        self.marks.len()
+    }
+
+    /// Returns `true` if there are no cursors.
+    pub fn is_empty(&self) -> bool {
+        self.marks.is_empty()
+    }
+}
+
+impl<D, L> Tree<D, L> {
+    /// Perform an edit at this node. Unlike the individual editing
+    /// methods, this returns an error instead of panicking if the edit
+    /// is invalid. If it fails, the tree is left unchanged (and any tree
+    /// contained in the edit is deleted).
+    pub fn apply(&mut self, edit: Edit<D, L>) -> Result<(), EditError> {
+        match edit {
+            Edit::InsertChild(i, tree) => {
+                let count = self.checked_num_children()?;
+                if i > count {
+                    return Err(EditError::IndexOutOfBounds);
+                }
+                if !self.forest.read_lock().allows_children(count + 1) {
+                    return Err(EditError::TooManyChildren);
+                }
+                self.insert_child(i, tree);
+            }
+            Edit::RemoveChild(i) => {
+                if i >= self.checked_num_children()? {
+                    return Err(EditError::IndexOutOfBounds);
+                }
+                self.remove_child(i);
+            }
+            Edit::ReplaceChild(i, tree) => {
+                if i >= self.checked_num_children()? {
+                    return Err(EditError::IndexOutOfBounds);
+                }
+                self.replace_child(i, tree);
+            }
+            Edit::SetLeaf(leaf) => {
+                if !self.is_leaf() {
+                    return Err(EditError::NotALeaf);
+                }
+                self.set_leaf(leaf);
+            }
+            Edit::SetData(data) => {
+                if self.is_leaf() {
+                    return Err(EditError::NotABranch);
+                }
+                *self.data_mut() = data;
+            }
+        }
+        Ok(())
+    }
+
+    /// Perform each edit in turn, at whichever node this Tree is at. An
+    /// edit that fails does not stop the rest from being applied. Returns
+    /// the result of each edit.
+    pub fn apply_all(&mut self, edits: Vec<Edit<D, L>>) -> Vec<Result<(), EditError>> {
+        edits.into_iter().map(|edit| self.apply(edit)).collect()
+    }
+
+    /// Perform an edit at each cursor, calling `make_edit` once per
+    /// cursor to construct it. Returns the result of each edit, in the
+    /// same order as the cursors. Cursors whose nodes have been deleted
+    /// or are in a different tree fail with `InvalidBookmark`, without
+    /// calling `make_edit`.
+    ///
+    /// The edits are performed in reverse pre-order, so that an edit
+    /// that deletes nodes never deletes another cursor's node before its
+    /// edit has been made. Afterwards, this Tree is back at its original
+    /// node, or if that was deleted, at its nearest surviving ancestor.
+    pub fn apply_at_cursors<F>(&mut self, cursors: &MultiCursor, mut make_edit: F)
+                               -> Vec<Result<(), EditError>>
+        where F: FnMut() -> Edit<D, L>
+    {
+        let mut results: Vec<Result<(), EditError>> =
+            vec!(Err(EditError::InvalidBookmark); cursors.len());
+        let mut targets: Vec<(Vec<usize>, usize)> = vec!();
+        let mut ancestors: Vec<Id> = vec!();
+        {
+            let forest = self.forest.read_lock();
+            for (i, mark) in cursors.marks.iter().enumerate() {
+                if forest.is_valid(mark.id) && forest.root(mark.id) == self.root {
+                    let mut path = vec!();
+                    let mut id = mark.id;
+                    while let Some(parent) = forest.parent(id) {
+                        path.push(forest.index_of(parent, id));
+                        id = parent;
+                    }
+                    path.reverse();
+                    targets.push((path, i));
+                }
+            }
+            let mut id = Some(self.id);
+            while let Some(ancestor) = id {
+                ancestors.push(ancestor);
+                id = forest.parent(ancestor);
+            }
+        }
+        targets.sort_by(|a, b| b.cmp(a));
+        for (_, i) in targets {
+            self.goto_bookmark(cursors.marks[i]);
+            results[i] = self.apply(make_edit());
+        }
+        let original = ancestors.into_iter()
+            .find(|id| self.forest.read_lock().is_valid(*id))
+            .expect("Forest - root was deleted!");
+        self.goto_bookmark(Bookmark { id: original });
+        results
+    }
+
+    fn checked_num_children(&self) -> Result<usize, EditError> {
+        if self.is_leaf() {
+            Err(EditError::NotABranch)
+        } else {
+            Ok(self.num_children())
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use std::fmt;
+
+
+/// The ways in which an edit to a [Tree](struct.Tree.html) can fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditError {
+    /// The edit needs a parent, but the node is the root of its tree.
+    AtRoot,
+    /// The edit needs a branch node, but the node is a leaf.
+    NotABranch,
+    /// The edit needs a leaf node, but the node is a branch.
+    NotALeaf,
+    /// The edit refers to a child that does not exist.
+    IndexOutOfBounds,
+    /// The edit is at a bookmark whose node has been deleted, or is in a
+    /// different tree.
+    InvalidBookmark,
+    /// The edit would give a branch more children than the forest allows
+    /// (see `Forest::set_max_children`).
+    TooManyChildren
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EditError::AtRoot     => write!(f, "root node has no parent"),
+            EditError::NotABranch => write!(f, "leaf node has no children"),
+            EditError::NotALeaf   => write!(f, "branch node has no leaf"),
+            EditError::IndexOutOfBounds => write!(f, "child index out of bounds"),
+            EditError::InvalidBookmark  => write!(f, "bookmark is not in this tree"),
+            EditError::TooManyChildren  => write!(f, "branch would have too many children")
+        }
+    }
+}
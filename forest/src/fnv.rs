@@ -0,0 +1,70 @@
+use std::hash::Hasher;
+
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// A 64-bit FNV-1a hasher. Unlike `DefaultHasher`, its algorithm is fixed,
+/// so hashes can be saved and compared across runs and Rust versions.
+/// Integers are hashed as little-endian, and `usize`s as `u64`s, so they
+/// don't depend on the platform either.
+pub(crate) struct FnvHasher(u64);
+
+impl FnvHasher {
+    pub(crate) fn new() -> FnvHasher {
+        FnvHasher(FNV_OFFSET_BASIS)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn write_u16(&mut self, n: u16) {
+        self.write(&n.to_le_bytes())
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.write(&n.to_le_bytes())
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.write(&n.to_le_bytes())
+    }
+
+    fn write_u128(&mut self, n: u128) {
+        self.write(&n.to_le_bytes())
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64)
+    }
+
+    fn write_i16(&mut self, n: i16) {
+        self.write_u16(n as u16)
+    }
+
+    fn write_i32(&mut self, n: i32) {
+        self.write_u32(n as u32)
+    }
+
+    fn write_i64(&mut self, n: i64) {
+        self.write_u64(n as u64)
+    }
+
+    fn write_i128(&mut self, n: i128) {
+        self.write_u128(n as u128)
+    }
+
+    fn write_isize(&mut self, n: isize) {
+        self.write_u64(n as u64)
+    }
+}
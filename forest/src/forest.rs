@@ -1,20 +1,41 @@
 use uuid::Uuid;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::mem;
 
+use crate::journal::Journal;
 use self::NodeContents::*;
 
 
 // INVARIANTS:
 // - children and parents agree
 
-pub type Id = Uuid;
-fn fresh() -> Uuid {
-    Uuid::new_v4()
+/// A unique, stable identifier for a node in a forest. It stays the same
+/// as long as the node exists, no matter where the node moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Id(Uuid);
+
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Whether a node is a leaf or a branch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Leaf,
+    Branch
+}
+
+fn fresh() -> Id {
+    Id(Uuid::new_v4())
 }
 
 pub struct RawForest<Data, Leaf>{
     map: HashMap<Id, Node<Data, Leaf>>,
+    pub (super) journal: Journal<Leaf>,
+    max_children: Option<usize>,
     #[cfg(test)]
     refcount: usize
 }
@@ -34,10 +55,31 @@ impl<D, L> RawForest<D, L> {
     pub fn new() -> RawForest<D, L> {
         RawForest {
             map: HashMap::new(),
+            journal: Journal::new(),
+            max_children: None,
             #[cfg(test)]
             refcount: 0
         }
     }
+
+    /// Limit the number of children any one branch may have. Creating or
+    /// inserting into a branch past the limit panics. `None` (the
+    /// default) means no limit.
+    pub fn set_max_children(&mut self, max: Option<usize>) {
+        self.max_children = max;
+    }
+
+    /// Could a branch have `count` children, under the `max_children`
+    /// limit?
+    pub fn allows_children(&self, count: usize) -> bool {
+        self.max_children.is_none_or(|max| count <= max)
+    }
+
+    pub fn check_children(&self, count: usize) {
+        if !self.allows_children(count) {
+            panic!("Forest - too many children! count={}, max={:?}", count, self.max_children);
+        }
+    }
     
     // Navigation //
 
@@ -70,6 +112,133 @@ impl<D, L> RawForest<D, L> {
         }
     }
 
+    /// The number of ancestors this node has. (The root has depth 0.)
+    pub fn depth(&self, mut id: Id) -> usize {
+        let mut depth = 0;
+        while let Some(parent) = self.get(id).parent {
+            id = parent;
+            depth += 1;
+        }
+        depth
+    }
+
+    /// The number of nodes in the subtree rooted at `id` (including itself).
+    pub fn subtree_size(&self, id: Id) -> usize {
+        match &self.get(id).contents {
+            Leaf(_) => 1,
+            Branch(_, children) => {
+                1 + children.iter().map(|&child| self.subtree_size(child)).sum::<usize>()
+            }
+        }
+    }
+
+    /// The node after `id` in a pre-order walk of its tree, if any.
+    pub fn preorder_next(&self, mut id: Id) -> Option<Id> {
+        if let Branch(_, children) = &self.get(id).contents {
+            if let Some(&first) = children.first() {
+                return Some(first);
+            }
+        }
+        while let Some(parent) = self.get(id).parent {
+            let siblings = self.children(parent);
+            let index = self.index_of(parent, id);
+            if index + 1 < siblings.len() {
+                return Some(siblings[index + 1]);
+            }
+            id = parent;
+        }
+        None
+    }
+
+    /// The node before `id` in a pre-order walk of its tree, if any.
+    pub fn preorder_prev(&self, id: Id) -> Option<Id> {
+        let parent = self.get(id).parent?;
+        let index = self.index_of(parent, id);
+        if index == 0 {
+            return Some(parent);
+        }
+        let mut id = self.children(parent)[index - 1];
+        while let Branch(_, children) = &self.get(id).contents {
+            match children.last() {
+                None => break,
+                Some(&last) => id = last
+            }
+        }
+        Some(id)
+    }
+
+    /// Every node in the subtree rooted at `id`, in pre-order, with its
+    /// depth below `id` (which has depth 0).
+    pub fn outline(&self, id: Id) -> Vec<(usize, Id, NodeKind)> {
+        let mut outline = vec!();
+        let mut stack = vec!((0, id));
+        while let Some((depth, id)) = stack.pop() {
+            match &self.get(id).contents {
+                Leaf(_) => outline.push((depth, id, NodeKind::Leaf)),
+                Branch(_, children) => {
+                    outline.push((depth, id, NodeKind::Branch));
+                    stack.extend(children.iter().rev().map(|&child| (depth + 1, child)));
+                }
+            }
+        }
+        outline
+    }
+
+    /// Nodes that claim to have a parent, but that no branch has as a
+    /// child. (Detached trees do not count: their roots have no parent.)
+    /// These can only arise from a bug. Sorted by id.
+    pub fn find_orphans(&self) -> Vec<Id> {
+        let mut children = HashSet::new();
+        for node in self.map.values() {
+            if let Branch(_, kids) = &node.contents {
+                children.extend(kids.iter().cloned());
+            }
+        }
+        let mut orphans: Vec<Id> = self.map.iter()
+            .filter(|(id, node)| node.parent.is_some() && !children.contains(*id))
+            .map(|(id, _)| *id)
+            .collect();
+        orphans.sort();
+        orphans
+    }
+
+    /// Roughly how many bytes the nodes take up: the size of each map
+    /// entry, plus its children vector, plus whatever `data_bytes` and
+    /// `leaf_bytes` say is owned by its data or leaf value.
+    pub fn estimated_bytes<F, G>(&self, data_bytes: F, leaf_bytes: G) -> usize
+        where F: Fn(&D) -> usize, G: Fn(&L) -> usize
+    {
+        self.map.values().map(|node| {
+            mem::size_of::<(Id, Node<D, L>)>() + match &node.contents {
+                Leaf(leaf) => leaf_bytes(leaf),
+                Branch(data, children) =>
+                    data_bytes(data) + children.capacity() * mem::size_of::<Id>()
+            }
+        }).sum()
+    }
+
+    /// Is `ancestor` equal to `id`, or one of its (transitive) parents?
+    pub fn is_ancestor(&self, ancestor: Id, mut id: Id) -> bool {
+        loop {
+            if id == ancestor {
+                return true;
+            }
+            match self.get(id).parent {
+                None => return false,
+                Some(parent) => {
+                    id = parent;
+                }
+            }
+        }
+    }
+
+    pub fn index_of(&self, parent: Id, child: Id) -> usize {
+        match self.children(parent).iter().position(|id| *id == child) {
+            Some(index) => index,
+            None => panic!("Forest - parent {} does not contain child {}!", parent, child)
+        }
+    }
+
     pub fn is_valid(&self, id: Id) -> bool {
         self.map.get(&id).is_some()
     }
@@ -120,9 +289,43 @@ impl<D, L> RawForest<D, L> {
         }
     }
 
+    /// Exchange the data values of two branches.
+    pub fn swap_data(&mut self, a: Id, b: Id) {
+        if self.is_leaf(a) || self.is_leaf(b) {
+            panic!("Forest::swap_data - leaf node has no data! id1={}, id2={}", a, b);
+        }
+        if a == b {
+            return;
+        }
+        let mut node = self.remove(a);
+        if let (Branch(data1, _), Branch(data2, _)) =
+            (&mut node.contents, &mut self.get_mut(b).contents)
+        {
+            mem::swap(data1, data2);
+        }
+        self.map.insert(a, node);
+    }
+
+    /// Exchange the leaf values of two leaves.
+    pub fn swap_leaf(&mut self, a: Id, b: Id) {
+        if !self.is_leaf(a) || !self.is_leaf(b) {
+            panic!("Forest::swap_leaf - branch node has no leaf! id1={}, id2={}", a, b);
+        }
+        if a == b {
+            return;
+        }
+        let mut node = self.remove(a);
+        if let (Leaf(leaf1), Leaf(leaf2)) = (&mut node.contents, &mut self.get_mut(b).contents)
+        {
+            mem::swap(leaf1, leaf2);
+        }
+        self.map.insert(a, node);
+    }
+
     // Forest Mutation //
 
     pub fn create_branch(&mut self, data: D, children: Vec<Id>) -> Id {
+        self.check_children(children.len());
         let id = fresh();
         #[cfg(test)] (self.refcount += 1);
         for child in &children {
@@ -162,6 +365,7 @@ impl<D, L> RawForest<D, L> {
     }
 
     pub fn insert_child(&mut self, parent: Id, index: usize, new_child: Id) {
+        self.check_children(self.children(parent).len() + 1);
         self.get_mut(new_child).parent = Some(parent);
         let children = self.children_mut(parent);
         if index > children.len() {
@@ -182,18 +386,41 @@ impl<D, L> RawForest<D, L> {
         child
     }
 
-    pub fn delete_tree(&mut self, id: Id) {
+    pub fn swap(&mut self, id1: Id, id2: Id) {
+        if self.is_ancestor(id1, id2) || self.is_ancestor(id2, id1) {
+            panic!("Forest::swap - cannot swap a node with its own ancestor. id1={}, id2={}", id1, id2);
+        }
+        let parent1 = self.get(id1).parent;
+        let parent2 = self.get(id2).parent;
+        // Find both indices before mutating: if the nodes share a parent,
+        // replacing one would move the other.
+        let index1 = parent1.map(|parent| self.index_of(parent, id1));
+        let index2 = parent2.map(|parent| self.index_of(parent, id2));
+        if let (Some(parent), Some(index)) = (parent1, index1) {
+            self.children_mut(parent)[index] = id2;
+        }
+        if let (Some(parent), Some(index)) = (parent2, index2) {
+            self.children_mut(parent)[index] = id1;
+        }
+        self.get_mut(id1).parent = parent2;
+        self.get_mut(id2).parent = parent1;
+    }
+
+    /// Delete the node and its descendants. Returns the number of nodes
+    /// deleted.
+    pub fn delete_tree(&mut self, id: Id) -> usize {
         let node = self.remove(id);
         #[cfg(test)] (self.refcount -= 1);
         match node.contents {
             Leaf(leaf) => {
                 mem::drop(leaf);
+                1
             }
             Branch(data, children) => {
                 mem::drop(data);
-                children.into_iter().for_each(|child| self.delete_tree(child));
+                1 + children.into_iter().map(|child| self.delete_tree(child)).sum::<usize>()
             }
-        };
+        }
     }
 
     // Private //
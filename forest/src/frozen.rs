@@ -0,0 +1,95 @@
+use std::sync::Arc;
+
+use crate::tree_ref::TreeRef;
+
+
+/// An immutable copy of a tree, made with
+/// [`TreeRef::snapshot`](struct.TreeRef.html#method.snapshot). It does
+/// not refer back to the forest, so later edits to the forest do not
+/// affect it, and (if `D` and `L` are `Send + Sync`) it can be shared
+/// with other threads. Each node is behind an `Arc`, so cloning a
+/// snapshot, or any subtree of it, is cheap.
+#[derive(Debug, PartialEq, Eq)]
+pub enum FrozenTree<D, L> {
+    Leaf(L),
+    Branch(D, Vec<Arc<FrozenTree<D, L>>>)
+}
+
+impl<D, L> FrozenTree<D, L> {
+    /// Is this a leaf node?
+    pub fn is_leaf(&self) -> bool {
+        match self {
+            FrozenTree::Leaf(_) => true,
+            FrozenTree::Branch(_, _) => false
+        }
+    }
+
+    /// The data value at this node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a leaf node.
+    pub fn data(&self) -> &D {
+        match self {
+            FrozenTree::Leaf(_) => panic!("FrozenTree - leaf node has no data!"),
+            FrozenTree::Branch(data, _) => data
+        }
+    }
+
+    /// The leaf value at this node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a branch node.
+    pub fn leaf(&self) -> &L {
+        match self {
+            FrozenTree::Leaf(leaf) => leaf,
+            FrozenTree::Branch(_, _) => panic!("FrozenTree - branch node has no leaf!")
+        }
+    }
+
+    /// The children of this node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a leaf node.
+    pub fn children(&self) -> &[Arc<FrozenTree<D, L>>] {
+        match self {
+            FrozenTree::Leaf(_) => panic!("FrozenTree - leaf node has no children!"),
+            FrozenTree::Branch(_, children) => children
+        }
+    }
+
+    /// The `i`th child of this node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a leaf node, or if `i` is out of bounds.
+    pub fn child(&self, i: usize) -> &Arc<FrozenTree<D, L>> {
+        match self.children().get(i) {
+            None => panic!("FrozenTree - child index out of bounds. i={}", i),
+            Some(child) => child
+        }
+    }
+
+    /// The number of children this node has.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a leaf node.
+    pub fn num_children(&self) -> usize {
+        self.children().len()
+    }
+}
+
+impl<'f, D: Clone, L: Clone> TreeRef<'f, D, L> {
+    /// Copy this subtree into an immutable snapshot (see `FrozenTree`).
+    pub fn snapshot(&self) -> Arc<FrozenTree<D, L>> {
+        Arc::new(if self.is_leaf() {
+            FrozenTree::Leaf(self.leaf().clone())
+        } else {
+            FrozenTree::Branch(self.data().clone(),
+                               self.children().map(|child| child.snapshot()).collect())
+        })
+    }
+}
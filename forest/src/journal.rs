@@ -0,0 +1,136 @@
+use std::mem;
+use std::collections::VecDeque;
+
+use crate::forest::{Id, RawForest};
+use crate::tree::{Tree, Forest};
+
+
+/// The most undo steps that a forest remembers. Once there are more, the
+/// oldest are forgotten.
+pub const MAX_UNDO_STEPS: usize = 1000;
+
+/// A record of the leaf values that have been overwritten, so that the
+/// edits can be undone. Each entry is one undo step, holding the original
+/// value of every leaf it changed (in the order they were first changed).
+pub struct Journal<L> {
+    entries: VecDeque<Vec<(Id, L)>>,
+    // The number of edit groups that have been begun but not ended.
+    open_groups: usize
+}
+
+impl<L> Journal<L> {
+    pub fn new() -> Journal<L> {
+        Journal {
+            entries: VecDeque::new(),
+            open_groups: 0
+        }
+    }
+
+    fn push_entry(&mut self, entry: Vec<(Id, L)>) {
+        if self.entries.len() == MAX_UNDO_STEPS {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    fn record(&mut self, id: Id, old_leaf: L) {
+        if self.open_groups == 0 {
+            self.push_entry(vec!((id, old_leaf)));
+            return;
+        }
+        let group = self.entries.back_mut().expect("Journal - open group is missing");
+        // Only the value from before the group began needs restoring.
+        if group.iter().all(|(edited, _)| *edited != id) {
+            group.push((id, old_leaf));
+        }
+    }
+}
+
+impl<D, L> RawForest<D, L> {
+    fn set_leaf(&mut self, id: Id, leaf: L) {
+        let old_leaf = mem::replace(self.leaf_mut(id), leaf);
+        self.journal.record(id, old_leaf);
+    }
+
+    /// Record the current value of a leaf that is about to be modified in
+    /// place (through `Tree::leaf_mut_journaled`).
+    pub (super) fn record_leaf(&mut self, id: Id) where L: Clone {
+        let old_leaf = self.leaf(id).clone();
+        self.journal.record(id, old_leaf);
+    }
+
+    fn undo(&mut self) -> bool {
+        if self.journal.open_groups > 0 {
+            panic!("Forest::undo - an edit group is still open");
+        }
+        match self.journal.entries.pop_back() {
+            None => false,
+            Some(group) => {
+                for (id, old_leaf) in group.into_iter().rev() {
+                    if self.is_valid(id) && self.is_leaf(id) {
+                        *self.leaf_mut(id) = old_leaf;
+                    }
+                }
+                true
+            }
+        }
+    }
+}
+
+impl<D, L> Forest<D, L> {
+    /// Begin a group of edits, that will be undone all at once. Groups may
+    /// be nested, in which case only the outermost group counts.
+    pub fn begin_edit_group(&self) {
+        let journal = &mut self.write_lock().journal;
+        if journal.open_groups == 0 {
+            journal.push_entry(vec!());
+        }
+        journal.open_groups += 1;
+    }
+
+    /// End the group of edits begun by `begin_edit_group`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no group has been begun.
+    pub fn end_edit_group(&self) {
+        let journal = &mut self.write_lock().journal;
+        if journal.open_groups == 0 {
+            panic!("Forest::end_edit_group - no edit group has been begun");
+        }
+        journal.open_groups -= 1;
+        if journal.open_groups == 0 && journal.entries.back().is_some_and(Vec::is_empty) {
+            journal.entries.pop_back();
+        }
+    }
+
+    /// Undo the most recent leaf edit made with `set_leaf`,
+    /// `Edit::SetLeaf`, or `leaf_mut_journaled`, or the most recent group
+    /// of them.
+    /// Leaves that have since been deleted are skipped. Returns `false` if
+    /// there was nothing to undo. Only the last `MAX_UNDO_STEPS` steps can
+    /// be undone.
+    ///
+    /// Only leaf edits are journaled: structural edits, and edits to data,
+    /// cannot be undone.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an edit group is still open.
+    pub fn undo(&self) -> bool {
+        self.write_lock().undo()
+    }
+}
+
+impl<D, L> Tree<D, L> {
+    /// Set the leaf value of this node, in a way that can be undone with
+    /// `Forest::undo`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is not a leaf node.
+    pub fn set_leaf(&mut self, leaf: L) {
+        let id = self.id;
+        self.forest.write_lock().set_leaf(id, leaf);
+    }
+}
@@ -1,16 +1,40 @@
 //! A general represenation of trees.
 
+mod ambient;
+mod edit;
+mod error;
+mod fnv;
 mod forest;
+mod frozen;
+mod journal;
 mod tree;
 mod tree_ref;
+mod view;
+#[cfg(feature = "serde")]
+mod serialize;
+#[cfg(feature = "serde")]
+mod reconcile;
 
-pub use self::tree::{Tree, Forest, ReadLeaf, WriteLeaf, ReadData, WriteData};
+pub use self::forest::{Id, NodeKind};
+pub use self::journal::MAX_UNDO_STEPS;
+pub use self::frozen::FrozenTree;
+pub use self::edit::{Edit, MultiCursor};
+pub use self::error::EditError;
+pub use self::tree::{Tree, Forest, Bookmark, Selection,
+                     ReadLeaf, WriteLeaf, ReadData, WriteData};
 pub use self::tree_ref::TreeRef;
+pub use self::view::{ForestView, ReadSession};
+#[cfg(feature = "serde")]
+pub use self::serialize::TreeData;
+#[cfg(feature = "serde")]
+pub use self::reconcile::ReconcileReport;
 
 
 #[cfg(test)]
 mod forest_tests {
     use super::*;
+    use std::rc::Rc;
+    use std::sync::Arc;
 
     fn family(forest: &Forest<&'static str, &'static str>)
                   -> Tree<&'static str, &'static str>
@@ -100,6 +124,879 @@ mod forest_tests {
         assert_eq!(children, vec!("elder", "younger"));
     }
 
+    #[test]
+    fn test_leaves_iter() {
+        let forest: Forest<u32, u32> = Forest::new();
+        let tree = mirror(&forest, 3, 0);
+        let leaves: Vec<u32> = tree.borrow()
+            .leaves()
+            .map(|leaf| *leaf.leaf())
+            .collect();
+        assert_eq!(leaves, vec!(1, 3, 5, 7));
+        let leaves: Vec<u32> = tree.borrow()
+            .child(2)
+            .leaves()
+            .map(|leaf| *leaf.leaf())
+            .collect();
+        assert_eq!(leaves, vec!(5, 7));
+        let leaves: Vec<u32> = tree.borrow()
+            .child(0)
+            .leaves()
+            .map(|leaf| *leaf.leaf())
+            .collect();
+        assert_eq!(leaves, vec!(1));
+    }
+
+    #[test]
+    fn test_estimated_bytes() {
+        let forest: Forest<String, String> = Forest::new();
+        let estimate = || forest.estimated_bytes(|data| data.capacity(),
+                                                 |leaf| leaf.capacity());
+        assert_eq!(estimate(), 0);
+        let mut tree = forest.new_branch("parent".to_string(), vec!());
+        let mut sizes = vec!(estimate());
+        for i in 0..5 {
+            tree.insert_child(i, forest.new_leaf("x".repeat(i)));
+            sizes.push(estimate());
+        }
+        let mut sorted = sizes.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(sorted, sizes);
+        // The closures are used to estimate the sizes of the values.
+        let size = forest.estimated_bytes(|_| 0, |_| 0);
+        assert_eq!(estimate(), size + "parent".len() + 1 + 2 + 3 + 4);
+    }
+
+    #[test]
+    fn test_collect_leaves() {
+        let forest: Forest<u32, u32> = Forest::new();
+        let tree = mirror(&forest, 3, 0);
+        assert_eq!(tree.borrow().collect_leaves(|_| true), vec!(1, 3, 5, 7));
+        assert_eq!(tree.borrow().collect_leaves(|leaf| *leaf > 4), vec!(5, 7));
+        assert_eq!(tree.borrow().child(1).collect_leaves(|_| true), vec!(3));
+        assert!(tree.borrow().child(0).collect_leaves(|_| false).is_empty());
+    }
+
+    #[test]
+    fn test_read_session() {
+        let forest: Forest<&'static str, &'static str> = Forest::new();
+        let mut tree1 = family(&forest);
+        let tree2 = forest.new_branch("sibling", vec!(forest.new_leaf("only")));
+        tree1.goto_child(1);
+        let (id1, id2) = (tree1.id(), tree2.id());
+        {
+            let session = forest.read_all();
+            let younger = session.leaf(id1);
+            let sibling = session.data(id2);
+            let only = session.leaf(session.children(id2)[0]);
+            assert_eq!((*younger, *sibling, *only), ("younger", "sibling", "only"));
+            assert_eq!(*session.data(session.parent(id1).unwrap()), "parent");
+            assert!(session.is_leaf(id1) && !session.is_leaf(id2));
+            // Other readers can still get in, but writers can't.
+            assert_eq!(*tree1.leaf(), "younger");
+            assert!(forest.lock.try_borrow_mut().is_err());
+        }
+        tree1.goto_root();
+        *tree1.data_mut() = "parent!";
+        assert!(forest.read_all().is_valid(id1));
+    }
+
+    #[test]
+    fn test_path_to() {
+        let forest: Forest<u32, u32> = Forest::new();
+        let mut tree = mirror(&forest, 3, 0);
+        tree.goto_child(2);
+        tree.goto_child(1);
+        tree.goto_child(0);
+        assert_eq!(*tree.leaf(), 7);
+        let deep = tree.bookmark();
+        tree.goto_parent();
+        let middle = tree.bookmark();
+        tree.goto_root();
+        let other = mirror(&forest, 1, 100);
+        let elsewhere = other.borrow().bookmark();
+        {
+            let root = tree.borrow();
+            assert_eq!(root.path_to(deep), Some(vec!(2, 1, 0)));
+            assert_eq!(root.path_to(root.bookmark()), Some(vec!()));
+            let middle = root.lookup_bookmark(middle).unwrap();
+            assert_eq!(middle.path_to(deep), Some(vec!(0)));
+            assert_eq!(middle.path_to(root.bookmark()), None);
+            assert_eq!(root.child(0).path_to(deep), None);
+            assert_eq!(root.path_to(elsewhere), None);
+        }
+        tree.goto_child(2);
+        tree.goto_child(1);
+        tree.remove_child(0);
+        tree.goto_root();
+        assert_eq!(tree.borrow().path_to(deep), None);
+    }
+
+    #[test]
+    fn test_same_tree() {
+        let forest: Forest<u32, u32> = Forest::new();
+        let mut tree = mirror(&forest, 2, 0);
+        let other = mirror(&forest, 1, 10);
+        let root = tree.bookmark();
+        tree.goto_child(1);
+        tree.goto_child(0);
+        let leaf = tree.bookmark();
+        tree.goto_root();
+        let elsewhere = other.borrow().child(0).bookmark();
+        assert!(forest.same_tree(root, leaf));
+        assert!(forest.same_tree(leaf, leaf));
+        assert!(!forest.same_tree(root, elsewhere));
+        assert!(!forest.same_tree(elsewhere, leaf));
+
+        // A subtree that is removed becomes its own tree.
+        tree.goto_child(1);
+        let removed = tree.remove_child(0);
+        tree.goto_root();
+        assert!(!forest.same_tree(root, leaf));
+        assert!(forest.same_tree(removed.borrow().bookmark(), leaf));
+        drop(removed);
+        assert!(!forest.same_tree(leaf, leaf));
+    }
+
+    #[test]
+    fn test_swap_values() {
+        let forest: Forest<u32, u32> = Forest::new();
+        let mut tree = mirror(&forest, 3, 0);
+        let mut other = mirror(&forest, 1, 10);
+        let other_leaf = other.borrow().child(0).bookmark();
+
+        // Two leaves in the same tree.
+        tree.goto_child(2);
+        tree.goto_child(1);
+        tree.goto_child(0);
+        let seven = tree.bookmark();
+        tree.goto_root();
+        tree.goto_child(0);
+        tree.swap_leaf(seven);
+        assert_eq!(*tree.leaf(), 7);
+        assert!(tree.goto_bookmark(seven));
+        assert_eq!(*tree.leaf(), 1);
+
+        // A leaf in another tree, and the same leaf.
+        tree.swap_leaf(other_leaf);
+        assert_eq!(*tree.leaf(), 11);
+        assert_eq!(*other.borrow().child(0).leaf(), 1);
+        let here = tree.bookmark();
+        tree.swap_leaf(here);
+        assert_eq!(*tree.leaf(), 11);
+
+        // Two branches.
+        tree.goto_root();
+        let root = tree.bookmark();
+        other.swap_data(root);
+        assert_eq!(*other.data(), 0);
+        assert_eq!(*tree.data(), 10);
+        assert_eq!(tree.borrow().sum(), 28 - 1 - 7 + 10 + 11 + 7);
+    }
+
+    #[test]
+    #[should_panic(expected="branch node has no leaf")]
+    fn test_swap_leaf_with_branch() {
+        let forest: Forest<u32, u32> = Forest::new();
+        let mut tree = mirror(&forest, 2, 0);
+        let root = tree.bookmark();
+        tree.goto_child(0);
+        tree.swap_leaf(root);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let forest: Forest<u32, u32> = Forest::new();
+        let mut tree = mirror(&forest, 3, 0);
+        let snapshot = tree.borrow().snapshot();
+        let subtree = tree.borrow().child(2).snapshot();
+        assert_eq!(snapshot.child(2), &subtree);
+
+        // Edit the original every which way.
+        *tree.data_mut() = 100;
+        tree.goto_child(2);
+        tree.goto_child(0);
+        *tree.leaf_mut() = 500;
+        tree.goto_parent();
+        tree.remove_child(1);
+        tree.goto_root();
+        tree.remove_child(0);
+        assert_eq!(tree.borrow().sum(), 100 + 2 + 3 + 4 + 500);
+
+        // The snapshots are unchanged.
+        assert_eq!(*snapshot.data(), 0);
+        assert_eq!(snapshot.num_children(), 3);
+        assert_eq!(*snapshot.child(0).leaf(), 1);
+        assert_eq!(*snapshot.child(2).child(0).leaf(), 5);
+        assert_eq!(*snapshot.child(2).child(1).child(0).leaf(), 7);
+        assert!(subtree.child(1).child(0).is_leaf());
+
+        // Clones share nodes, and can be read from another thread.
+        let copy = snapshot.clone();
+        assert!(Arc::ptr_eq(&copy.children()[1], &snapshot.children()[1]));
+        let sum = std::thread::spawn(move || {
+            fn sum(tree: &FrozenTree<u32, u32>) -> u32 {
+                if tree.is_leaf() {
+                    *tree.leaf()
+                } else {
+                    tree.data() + tree.children().iter().map(|child| sum(child)).sum::<u32>()
+                }
+            }
+            sum(&copy)
+        }).join().unwrap();
+        assert_eq!(sum, 28);
+    }
+
+    #[test]
+    fn test_outline() {
+        let forest: Forest<&'static str, &'static str> = Forest::new();
+        let mut tree = family(&forest);
+        let outline: Vec<(usize, NodeKind)> = forest.outline(tree.id()).into_iter()
+            .map(|(depth, _, kind)| (depth, kind))
+            .collect();
+        assert_eq!(outline, vec!((0, NodeKind::Branch),
+                                 (1, NodeKind::Leaf),
+                                 (1, NodeKind::Leaf)));
+
+        tree.goto_child(1);
+        let younger = tree.id();
+        assert_eq!(forest.outline(younger), vec!((0, younger, NodeKind::Leaf)));
+        tree.goto_parent();
+        assert_eq!(forest.outline(tree.id())[2].1, younger);
+    }
+
+    #[test]
+    fn test_count_where() {
+        let forest: Forest<u32, u32> = Forest::new();
+        let tree = mirror(&forest, 3, 0);
+        let big_leaf = |node: TreeRef<u32, u32>| node.is_leaf() && *node.leaf() > 2;
+        assert_eq!(tree.borrow().count_where(big_leaf), 3);
+        assert_eq!(tree.borrow().count_where(|_| true), 8);
+        assert_eq!(tree.borrow().child(1).count_where(big_leaf), 1);
+        assert_eq!(tree.borrow().child(0).count_where(big_leaf), 0);
+    }
+
+    #[test]
+    fn test_visit_until() {
+        use std::ops::ControlFlow;
+
+        let forest: Forest<u32, u32> = Forest::new();
+        let tree = mirror(&forest, 3, 0);
+        // Pre-order: 0 1 2 3 4 5 6 7
+        let mut visited = 0;
+        let found = tree.borrow().visit_until(|node| {
+            visited += 1;
+            if node.is_leaf() && *node.leaf() > 2 {
+                ControlFlow::Break(*node.leaf())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert_eq!(found, Some(3));
+        assert_eq!(visited, 4);
+
+        let mut visited = 0;
+        let found: Option<()> = tree.borrow().visit_until(|_| {
+            visited += 1;
+            ControlFlow::Continue(())
+        });
+        assert_eq!(found, None);
+        assert_eq!(visited, 8);
+    }
+
+    #[test]
+    fn test_preorder_index() {
+        use std::ops::ControlFlow;
+
+        let forest: Forest<&'static str, &'static str> = Forest::new();
+        let tree = family(&forest);
+        assert_eq!(tree.borrow().preorder_index(), 0);
+        assert_eq!(tree.borrow().child(0).preorder_index(), 1);
+        assert_eq!(tree.borrow().child(1).preorder_index(), 2);
+
+        // Each node of a mirror tree is labeled with its pre-order index
+        let forest: Forest<u32, u32> = Forest::new();
+        let tree = mirror(&forest, 3, 0);
+        let mut visited = 0;
+        tree.borrow().visit_until(|node| -> ControlFlow<()> {
+            let label = if node.is_leaf() { *node.leaf() } else { *node.data() };
+            assert_eq!(node.preorder_index(), label as usize);
+            visited += 1;
+            ControlFlow::Continue(())
+        });
+        assert_eq!(visited, 8);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_binary_round_trip() {
+        use crate::serialize::SerNode;
+
+        fn show(tree: TreeRef<u32, u32>) -> String {
+            if tree.is_leaf() {
+                format!("{}", *tree.leaf())
+            } else {
+                let children: Vec<String> = tree.children().map(show).collect();
+                format!("{}({})", *tree.data(), children.join(" "))
+            }
+        }
+
+        let forest: Forest<u32, u32> = Forest::new();
+        let tree = mirror(&forest, 6, 0);
+        let mut binary = vec!();
+        forest.save_binary(&tree, &mut binary).unwrap();
+        let loaded = forest.load_binary(&mut binary.as_slice()).unwrap();
+        assert_eq!(show(loaded.borrow()), show(tree.borrow()));
+        assert_eq!(forest.read_lock().tree_count(), 2 * 64);
+
+        let json = serde_json::to_vec(&SerNode {
+            forest: &forest.read_lock(),
+            id: tree.id
+        }).unwrap();
+        assert!(binary.len() < json.len());
+
+        let mut truncated = &binary[..binary.len() - 1];
+        assert!(forest.load_binary(&mut truncated).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_reconcile() {
+        use TreeData::{Leaf, Branch};
+        let forest: Forest<u32, u32> = Forest::new();
+        let mut tree = mirror(&forest, 3, 0);
+        let ids = |tree: &Tree<u32, u32>| -> Vec<Id> {
+            forest.outline(tree.id()).into_iter().map(|(_, id, _)| id).collect()
+        };
+        let before = ids(&tree);
+
+        // Edit one leaf: every node is kept.
+        let mut new = tree.borrow().to_tree_data();
+        if let Branch(_, children) = &mut new {
+            children[1] = Branch(2, vec!(Leaf(30)));
+        }
+        let report = tree.reconcile(new.clone());
+        assert_eq!(report, ReconcileReport { reused: 8, created: 0 });
+        assert_eq!(ids(&tree), before);
+        assert_eq!(tree.borrow().to_tree_data(), new);
+
+        // Insert a child in the middle, and turn a leaf into a branch.
+        let new = Branch(0, vec!(
+            Leaf(1),
+            Leaf(100),
+            Branch(2, vec!(Leaf(30))),
+            Branch(4, vec!(Branch(5, vec!()), Branch(6, vec!(Leaf(7)))))));
+        let report = tree.reconcile(new.clone());
+        assert_eq!(report, ReconcileReport { reused: 7, created: 2 });
+        assert_eq!(tree.borrow().to_tree_data(), new);
+        let after = ids(&tree);
+        assert_eq!(&after[..2], &before[..2]);
+        assert_eq!(&after[3..6], &before[2..5]);
+        assert_eq!(&after[7..], &before[6..]);
+        assert!(!forest.read_all().is_valid(before[5]));
+
+        // Remove children, and change the root from a branch to a leaf.
+        let new = Branch(0, vec!(Leaf(1), Branch(4, vec!())));
+        assert_eq!(tree.reconcile(new.clone()),
+                   ReconcileReport { reused: 3, created: 0 });
+        assert_eq!(tree.borrow().to_tree_data(), new);
+        assert_eq!(tree.reconcile(Leaf(9)), ReconcileReport { reused: 0, created: 1 });
+        assert_eq!(*tree.leaf(), 9);
+        assert_eq!(forest.read_lock().tree_count(), 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_cut_child() {
+        let forest: Forest<u32, u32> = Forest::new();
+        {
+            let mut tree = mirror(&forest, 3, 0);
+            assert_eq!(forest.read_lock().tree_count(), 8);
+            let cut = tree.cut_child(2);
+            assert_eq!(cut, TreeData::Branch(4, vec!(
+                TreeData::Leaf(5),
+                TreeData::Branch(6, vec!(TreeData::Leaf(7))))));
+            assert_eq!(forest.read_lock().tree_count(), 4);
+            assert_eq!(tree.num_children(), 2);
+
+            // Paste it back in.
+            let pasted = forest.from_tree_data(cut.clone());
+            tree.insert_child(0, pasted);
+            assert_eq!(tree.borrow().child(0).to_tree_data(), cut);
+            assert_eq!(tree.borrow().sum(), 28);
+        }
+        assert_eq!(forest.read_lock().tree_count(), 0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_replace_child_from_data() {
+        let forest: Forest<u32, u32> = Forest::new();
+        {
+            let mut tree = mirror(&forest, 3, 0);
+            let payload = "{\"Branch\":[8,[{\"Leaf\":9},{\"Leaf\":10}]]}";
+            let data: TreeData<u32, u32> = serde_json::from_str(payload).unwrap();
+            let old = tree.replace_child_from_data(1, data.clone());
+            assert_eq!(old.borrow().to_tree_data(),
+                       TreeData::Branch(2, vec!(TreeData::Leaf(3))));
+            assert!(old.borrow().parent().is_none());
+            assert_eq!(tree.borrow().child(1).to_tree_data(), data);
+            assert_eq!(tree.num_children(), 3);
+            assert_eq!(tree.borrow().sum(), 28 - 5 + 27);
+            assert_eq!(forest.read_lock().tree_count(), 9 + 2);
+        }
+        assert_eq!(forest.read_lock().tree_count(), 0);
+    }
+
+    #[test]
+    fn test_for_each_descendant_mut() {
+        let forest: Forest<u32, u32> = Forest::new();
+        let mut tree = mirror(&forest, 3, 0);
+        tree.goto_child(2);
+        let start = tree.id();
+
+        // Only the subtree 4 -> [5, 6 -> [7]] is visited.
+        let mut visited = vec!();
+        tree.for_each_descendant_mut(|node| {
+            if node.is_leaf() {
+                visited.push(*node.leaf());
+            } else {
+                visited.push(*node.data());
+                *node.data_mut() += 10;
+            }
+        });
+        assert_eq!(visited, vec!(4, 5, 6, 7));
+        assert_eq!(tree.id(), start);
+        tree.goto_root();
+        assert_eq!(tree.borrow().sum(), 28 + 20);
+
+        // Every node, including the root. The closure may move the tree,
+        // and may add children, which are then visited.
+        let mut count = 0;
+        tree.for_each_descendant_mut(|node| {
+            count += 1;
+            if node.is_leaf() {
+                *node.leaf_mut() += 1;
+            } else if *node.data() == 16 {
+                let leaf = forest.new_leaf(100);
+                node.insert_child(0, leaf);
+            }
+            node.goto_root();
+        });
+        assert_eq!(count, 9);
+        assert!(tree.at_root());
+        assert_eq!(tree.borrow().sum(), 28 + 20 + 4 + 101);
+        assert_eq!(forest.read_lock().tree_count(), 9);
+    }
+
+    #[test]
+    fn test_max_children() {
+        let forest: Forest<u32, u32> = Forest::new();
+        forest.set_max_children(Some(2));
+        let leaf = |n| forest.new_leaf(n);
+        let mut tree = forest.new_branch(0, vec!(
+            forest.new_branch(1, vec!(leaf(2), leaf(3))),
+            forest.new_branch(4, vec!(leaf(5)))));
+        tree.goto_child(0);
+        assert_eq!(tree.merge_next_sibling(), Err(EditError::TooManyChildren));
+        assert_eq!(tree.unwrap_into_parent(), Err(EditError::TooManyChildren));
+        tree.goto_root();
+        assert_eq!(tree.borrow().sum(), 15);
+
+        forest.set_max_children(None);
+        tree.goto_child(0);
+        assert_eq!(tree.merge_next_sibling(), Ok(()));
+        assert_eq!(tree.num_children(), 3);
+        tree.goto_root();
+    }
+
+    #[test]
+    #[should_panic(expected="too many children")]
+    fn test_max_children_insert() {
+        let forest: Forest<u32, u32> = Forest::new();
+        forest.set_max_children(Some(2));
+        let mut tree = forest.new_branch(0, vec!(forest.new_leaf(1)));
+        tree.insert_child(1, forest.new_leaf(2));
+        tree.insert_child(2, forest.new_leaf(3));
+    }
+
+    #[test]
+    fn test_max_children_apply() {
+        let forest: Forest<u32, u32> = Forest::new();
+        forest.set_max_children(Some(2));
+        {
+            let mut tree = forest.new_branch(0, vec!(forest.new_leaf(1), forest.new_leaf(2)));
+            assert_eq!(tree.apply(Edit::InsertChild(1, forest.new_leaf(3))),
+                       Err(EditError::TooManyChildren));
+            assert_eq!(tree.num_children(), 2);
+            assert_eq!(tree.borrow().sum(), 3);
+            // The tree in the edit was deleted.
+            assert_eq!(forest.read_lock().tree_count(), 3);
+            let results = tree.apply_all(vec!(Edit::RemoveChild(0),
+                                              Edit::InsertChild(0, forest.new_leaf(4)),
+                                              Edit::InsertChild(0, forest.new_leaf(5))));
+            assert_eq!(results, vec!(Ok(()), Ok(()), Err(EditError::TooManyChildren)));
+            assert_eq!(tree.borrow().sum(), 6);
+        }
+        assert_eq!(forest.read_lock().tree_count(), 0);
+    }
+
+    #[test]
+    fn test_max_children_new_branch() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let forest: Forest<u32, u32> = Forest::new();
+        forest.set_max_children(Some(2));
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            forest.new_branch(0, vec!(forest.new_leaf(1),
+                                      forest.new_leaf(2),
+                                      forest.new_branch(3, vec!(forest.new_leaf(4)))))
+        }));
+        assert!(result.is_err());
+        // The children were deleted, not leaked.
+        assert_eq!(forest.read_lock().tree_count(), 0);
+    }
+
+    #[test]
+    fn test_new_branch_from_iter() {
+        let forest: Forest<u32, u32> = Forest::new();
+        {
+            let tree = forest.new_branch_from_iter(
+                10, (1..4).map(|n| forest.new_branch(n, vec!(forest.new_leaf(n * 100)))));
+            assert_eq!(tree.num_children(), 3);
+            assert_eq!(*tree.borrow().child(2).data(), 3);
+            assert_eq!(*tree.borrow().child(2).child(0).leaf(), 300);
+            assert_eq!(tree.borrow().sum(), 10 + 6 + 600);
+            assert_eq!(forest.read_lock().tree_count(), 7);
+
+            let empty = forest.new_branch_from_iter(0, std::iter::empty());
+            assert_eq!(empty.num_children(), 0);
+        }
+        assert_eq!(forest.read_lock().tree_count(), 0);
+    }
+
+    #[test]
+    fn test_replace_leaves_where() {
+        let forest: Forest<u32, String> = Forest::new();
+        let leaf = |s: &str| forest.new_leaf(s.to_string());
+        let mut tree = forest.new_branch(0, vec!(
+            leaf("x"),
+            leaf("y"),
+            forest.new_branch(1, vec!(leaf("x"), leaf("xx")))));
+
+        tree.goto_child(2);
+        assert_eq!(tree.replace_leaves_where(|s| s == "x", |_| "z".to_string()), 1);
+        tree.goto_root();
+        assert_eq!(tree.replace_leaves_where(|s| s == "x", |s| format!("{}!", s)), 1);
+        assert_eq!(tree.replace_leaves_where(|s| s == "w", |_| "v".to_string()), 0);
+
+        let mut leaves = vec!();
+        tree.for_each_descendant_mut(|node| {
+            if node.is_leaf() {
+                leaves.push(node.leaf().clone());
+            }
+        });
+        assert_eq!(leaves, vec!("x!", "y", "z", "xx"));
+
+        // Leaves don't need to be `Clone`, and the replacements can still
+        // be undone.
+        #[derive(Debug, PartialEq)]
+        struct Name(&'static str);
+        let forest: Forest<u32, Name> = Forest::new();
+        let mut tree = forest.new_branch(0, vec!(forest.new_leaf(Name("a")),
+                                                 forest.new_leaf(Name("b"))));
+        assert_eq!(tree.replace_leaves_where(|n| n.0 == "b", |_| Name("c")), 1);
+        tree.goto_child(1);
+        assert_eq!(*tree.leaf(), Name("c"));
+        assert!(forest.undo());
+        assert_eq!(*tree.leaf(), Name("b"));
+    }
+
+    #[test]
+    fn test_rotate_children() {
+        let forest: Forest<u32, u32> = Forest::new();
+        let mut tree = forest.new_branch(0, vec!(
+            forest.new_leaf(1), forest.new_leaf(2), forest.new_leaf(3)));
+        let leaves = |tree: &Tree<u32, u32>| -> Vec<u32> {
+            tree.borrow().children().map(|child| *child.leaf()).collect()
+        };
+
+        tree.rotate_children_left(1);
+        assert_eq!(leaves(&tree), vec!(2, 3, 1));
+        tree.rotate_children_right(1);
+        assert_eq!(leaves(&tree), vec!(1, 2, 3));
+        tree.rotate_children_right(1);
+        assert_eq!(leaves(&tree), vec!(3, 1, 2));
+        tree.rotate_children_left(1);
+
+        // Rotating by the number of children does nothing.
+        tree.rotate_children_left(3);
+        assert_eq!(leaves(&tree), vec!(1, 2, 3));
+        tree.rotate_children_right(3);
+        assert_eq!(leaves(&tree), vec!(1, 2, 3));
+
+        // The children still know their parent.
+        tree.goto_child(2);
+        assert_eq!(*tree.leaf(), 3);
+        tree.goto_parent();
+        assert!(tree.at_root());
+    }
+
+    #[test]
+    fn test_ambient_forest() {
+        fn ambient_mirror(height: u32, id: u32) -> Tree<u32, u32> {
+            if height == 0 {
+                Tree::ambient_leaf(id)
+            } else {
+                let children = (0..height)
+                    .map(|i| ambient_mirror(i, id + 2_u32.pow(i)))
+                    .collect();
+                Tree::ambient_branch(id, children)
+            }
+        }
+
+        let forest: Forest<u32, u32> = Forest::new();
+        let mut explicit = mirror(&forest, 3, 0);
+        let mut ambient = forest.with(|| ambient_mirror(3, 0));
+        assert_eq!(ambient.borrow().sum(), explicit.borrow().sum());
+        assert_eq!(forest.outline(ambient.id()).len(), forest.outline(explicit.id()).len());
+        for tree in &mut [&mut explicit, &mut ambient] {
+            tree.goto_child(2);
+            tree.goto_child(1);
+            assert_eq!(*tree.data(), 6);
+            tree.goto_child(0);
+            assert_eq!(*tree.leaf(), 7);
+            tree.goto_root();
+        }
+
+        // Scopes nest, and are uninstalled when they end.
+        let other: Forest<u32, u32> = Forest::new();
+        forest.with(|| {
+            let leaf: Tree<u32, u32> = other.with(|| Tree::ambient_leaf(1));
+            assert!(Rc::ptr_eq(&leaf.forest.lock, &other.lock));
+            assert!(Rc::ptr_eq(&Forest::<u32, u32>::ambient().lock, &forest.lock));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected="no forest is in scope")]
+    fn test_ambient_forest_out_of_scope() {
+        let forest: Forest<u32, u32> = Forest::new();
+        forest.with(|| ());
+        let _leaf: Tree<u32, u32> = Tree::ambient_leaf(1);
+    }
+
+    #[test]
+    fn test_id() {
+        let forest: Forest<&'static str, &'static str> = Forest::new();
+        let mut tree = family(&forest);
+        tree.goto_child(1);
+        let younger = tree.id();
+        tree.goto_parent();
+        let parent = tree.id();
+        assert_eq!(*forest.get(younger).unwrap().leaf(), "younger");
+        assert_eq!(*forest.get(parent).unwrap().data(), "parent");
+        assert_eq!(forest.get(younger).unwrap().parent().unwrap().preorder_index(), 0);
+        // Ids are stable across edits
+        tree.insert_child(0, forest.new_leaf("eldest"));
+        assert_eq!(forest.get(younger).unwrap().preorder_index(), 3);
+        // But not across deletion
+        tree.remove_child(2);
+        assert!(forest.get(younger).is_none());
+        assert!(forest.get(parent).is_some());
+    }
+
+    #[test]
+    fn test_forest_view() {
+        let forest: Forest<u32, u32> = Forest::new();
+        let mut tree = mirror(&forest, 3, 0);
+        tree.goto_child(2);
+        let mark = tree.bookmark();
+        tree.goto_child(1);
+        let id = tree.id();
+        tree.goto_root();
+
+        // A `ForestView` only exposes lookups, which hand out `TreeRef`s;
+        // there is no way to edit the forest through it.
+        let view = forest.view();
+        let node = view.get(id).unwrap();
+        assert_eq!(*node.data(), 6);
+        assert_eq!(node.children().map(|child| *child.leaf()).collect::<Vec<_>>(), vec!(7));
+        assert_eq!(*node.parent().unwrap().data(), 4);
+        assert_eq!(*view.lookup_bookmark(mark).unwrap().data(), 4);
+        assert!(view.find_orphans().is_empty());
+
+        tree.remove_child(2);
+        assert!(view.get(id).is_none());
+        assert!(view.lookup_bookmark(mark).is_none());
+    }
+
+    #[test]
+    fn test_goto_nth_leaf() {
+        let forest: Forest<u32, u32> = Forest::new();
+        let mut tree = mirror(&forest, 3, 0);
+        tree.goto_child(2);
+        assert!(tree.goto_nth_leaf(0));
+        assert_eq!(*tree.leaf(), 1);
+        assert!(tree.goto_nth_leaf(3));
+        assert_eq!(*tree.leaf(), 7);
+        assert!(tree.goto_nth_leaf(1));
+        assert_eq!(*tree.leaf(), 3);
+        assert!(tree.goto_nth_leaf(2));
+        assert_eq!(*tree.leaf(), 5);
+        assert!(!tree.goto_nth_leaf(4));
+        assert_eq!(*tree.leaf(), 5);
+        tree.goto_root();
+    }
+
+    #[test]
+    fn test_goto_leaf() {
+        let forest: Forest<u32, u32> = Forest::new();
+        let mut tree = mirror(&forest, 3, 0);
+        let mut leaves = vec!();
+        while tree.goto_next_leaf() {
+            leaves.push(*tree.leaf());
+        }
+        assert_eq!(leaves, vec!(1, 3, 5, 7));
+        let mut leaves = vec!();
+        while tree.goto_prev_leaf() {
+            leaves.push(*tree.leaf());
+        }
+        assert_eq!(leaves, vec!(5, 3, 1));
+        assert_eq!(*tree.leaf(), 1);
+
+        // Starting from a branch, and skipping over empty branches
+        tree.goto_root();
+        assert!(!tree.goto_prev_leaf());
+        tree.goto_child(2);
+        tree.insert_child(0, forest.new_branch(8, vec!()));
+        tree.goto_parent();
+        tree.goto_child(1);
+        assert_eq!(*tree.data(), 2);
+        assert!(tree.goto_next_leaf());
+        assert_eq!(*tree.leaf(), 3);
+        assert!(tree.goto_next_leaf());
+        assert_eq!(*tree.leaf(), 5);
+        assert!(tree.goto_prev_leaf());
+        assert_eq!(*tree.leaf(), 3);
+        tree.goto_root();
+    }
+
+    #[test]
+    fn test_structural_hash() {
+        let forest: Forest<u32, u32> = Forest::new();
+        let mut tree = mirror(&forest, 3, 0);
+        let copy = mirror(&forest, 3, 0);
+        let hash = tree.borrow().structural_hash();
+        assert_eq!(copy.borrow().structural_hash(), hash);
+        assert_eq!(Forest::<u32, u32>::new().new_leaf(1).borrow().structural_hash(),
+                   tree.borrow().child(0).structural_hash());
+        // Changing a leaf changes the hash
+        tree.goto_child(0);
+        *tree.leaf_mut() = 9;
+        tree.goto_parent();
+        assert_ne!(tree.borrow().structural_hash(), hash);
+        tree.goto_child(0);
+        *tree.leaf_mut() = 1;
+        tree.goto_parent();
+        assert_eq!(tree.borrow().structural_hash(), hash);
+        // As does changing the shape
+        let leaf = tree.remove_child(0);
+        tree.goto_child(0);
+        tree.insert_child(0, leaf);
+        tree.goto_parent();
+        assert_ne!(tree.borrow().structural_hash(), hash);
+        // Leaves and branches with the same value differ
+        let forest: Forest<u32, u32> = Forest::new();
+        assert_ne!(forest.new_leaf(0).borrow().structural_hash(),
+                   forest.new_branch(0, vec!()).borrow().structural_hash());
+        // The hash is FNV-1a, so it never changes
+        assert_eq!(forest.new_leaf(1).borrow().structural_hash(), 0x44c1_a3d0_cef6_2cbe);
+    }
+
+    #[test]
+    fn test_retain_children() {
+        let forest: Forest<u32, u32> = Forest::new();
+        {
+            let leaves = vec!(0, 7, 1, 7, 7, 2, 7).into_iter()
+                .map(|n| forest.new_leaf(n))
+                .collect();
+            let mut tree = forest.new_branch(10, leaves);
+            tree.insert_child(1, mirror(&forest, 2, 7));
+            assert_eq!(forest.read_lock().tree_count(), 8 + 4);
+            tree.retain_children(|child| !child.is_leaf() || *child.leaf() != 7);
+            let children: Vec<u32> = tree.borrow().children()
+                .map(|child| if child.is_leaf() { *child.leaf() } else { *child.data() })
+                .collect();
+            assert_eq!(children, vec!(0, 7, 1, 2));
+            assert_eq!(forest.read_lock().tree_count(), 4 + 4);
+            tree.retain_children(|_| false);
+            assert_eq!(tree.borrow().num_children(), 0);
+            assert_eq!(forest.read_lock().tree_count(), 1);
+        }
+        assert_eq!(forest.read_lock().tree_count(), 0);
+    }
+
+    #[test]
+    fn test_find_orphans() {
+        let forest: Forest<u32, u32> = Forest::new();
+        let tree = mirror(&forest, 3, 0);
+        let _detached = forest.new_leaf(8);
+        assert!(forest.find_orphans().is_empty());
+
+        // Corrupt the forest, by removing node 4 from its parent's children
+        let four = tree.borrow().child(2).id;
+        let orphan = forest.write_lock().children_mut(tree.id).pop().unwrap();
+        assert_eq!(orphan, four);
+        // Its descendants are unreachable too, but only it is an orphan
+        assert_eq!(forest.find_orphans(), vec!(four));
+
+        forest.write_lock().children_mut(tree.id).push(orphan);
+        assert!(forest.find_orphans().is_empty());
+    }
+
+    #[test]
+    fn test_enumerate_paths() {
+        let forest: Forest<&'static str, &'static str> = Forest::new();
+        let tree = family(&forest);
+        let paths: Vec<Vec<usize>> = tree.borrow()
+            .enumerate_paths()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(paths, vec!(vec!(), vec!(0), vec!(1)));
+
+        let forest: Forest<u32, u32> = Forest::new();
+        let tree = mirror(&forest, 3, 0);
+        for (path, node) in tree.borrow().enumerate_paths() {
+            let mut found = tree.borrow();
+            for i in &path {
+                found = found.child(*i);
+            }
+            assert_eq!(found.id, node.id);
+        }
+        let paths: Vec<Vec<usize>> = tree.borrow()
+            .child(2)
+            .enumerate_paths()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(paths, vec!(vec!(), vec!(0), vec!(1), vec!(1, 0)));
+    }
+
+    #[test]
+    fn test_siblings() {
+        let forest: Forest<&'static str, &'static str> = Forest::new();
+        let tree = family(&forest);
+        let elder = tree.borrow().child(0);
+        let younger = tree.borrow().child(1);
+        assert_eq!(*elder.next_sibling().unwrap().leaf(), "younger");
+        assert_eq!(*younger.prev_sibling().unwrap().leaf(), "elder");
+        assert!(elder.prev_sibling().is_none());
+        assert!(younger.next_sibling().is_none());
+        assert!(tree.borrow().next_sibling().is_none());
+        assert!(tree.borrow().prev_sibling().is_none());
+    }
+
     #[test]
     fn test_at_root_mut() {
         let forest: Forest<&'static str, &'static str> = Forest::new();
@@ -219,6 +1116,426 @@ mod forest_tests {
         assert_eq!(*tree.borrow().child(1).parent().unwrap().data(), "parent");
     }
 
+    #[test]
+    fn test_swap_across_parents() {
+        let forest: Forest<&'static str, &'static str> = Forest::new();
+        let mut tree = family(&forest);
+        let leaves = vec!(forest.new_leaf("other elder"),
+                          forest.new_leaf("other younger"));
+        let mut other_tree = forest.new_branch("other parent", leaves);
+        tree.goto_child(0);
+        other_tree.goto_child(1);
+        let mark_elder = tree.bookmark();
+        Tree::swap(&mut tree, &mut other_tree);
+        assert_eq!(*tree.leaf(), "other younger");
+        assert_eq!(*other_tree.leaf(), "elder");
+        tree.goto_parent();
+        other_tree.goto_parent();
+        assert_eq!(*tree.data(), "parent");
+        assert_eq!(*other_tree.data(), "other parent");
+        let children: Vec<&'static str> = tree.borrow()
+            .children()
+            .map(|child| *child.leaf())
+            .collect();
+        assert_eq!(children, vec!("other younger", "younger"));
+        let children: Vec<&'static str> = other_tree.borrow()
+            .children()
+            .map(|child| *child.leaf())
+            .collect();
+        assert_eq!(children, vec!("other elder", "elder"));
+        assert!(!tree.goto_bookmark(mark_elder));
+        assert!(other_tree.goto_bookmark(mark_elder));
+        assert_eq!(*other_tree.leaf(), "elder");
+    }
+
+    #[test]
+    fn test_swap_detached() {
+        let forest: Forest<&'static str, &'static str> = Forest::new();
+        let mut tree = family(&forest);
+        let mut stranger = forest.new_leaf("stranger");
+        tree.goto_child(1);
+        Tree::swap(&mut tree, &mut stranger);
+        assert_eq!(*tree.leaf(), "stranger");
+        assert_eq!(*stranger.leaf(), "younger");
+        assert!(stranger.at_root());
+        assert!(stranger.borrow().parent().is_none());
+        tree.goto_root();
+        assert_eq!(*tree.borrow().child(1).leaf(), "stranger");
+        // Swapping two detached trees just exchanges them.
+        let mut outsider = forest.new_leaf("outsider");
+        Tree::swap(&mut stranger, &mut outsider);
+        assert_eq!(*stranger.leaf(), "outsider");
+        assert_eq!(*outsider.leaf(), "younger");
+        stranger.goto_root();
+        assert_eq!(*stranger.leaf(), "outsider");
+    }
+
+    #[test]
+    fn test_raw_swap_siblings() {
+        let forest: Forest<u32, u32> = Forest::new();
+        let tree = mirror(&forest, 3, 0);
+        let (leaf, branch) = {
+            let raw = forest.read_lock();
+            (raw.child(tree.id, 0), raw.child(tree.id, 2))
+        };
+        forest.write_lock().swap(leaf, branch);
+        let raw = forest.read_lock();
+        assert_eq!(raw.children(tree.id), &vec!(branch, raw.child(tree.id, 1), leaf));
+        assert_eq!(raw.parent(leaf), Some(tree.id));
+        assert_eq!(raw.parent(branch), Some(tree.id));
+        drop(raw);
+        assert_eq!(tree.borrow().sum(), 28);
+    }
+
+    #[test]
+    #[should_panic(expected="cannot swap a node with its own ancestor")]
+    fn test_raw_swap_ancestor() {
+        let forest: Forest<u32, u32> = Forest::new();
+        let tree = mirror(&forest, 3, 0);
+        let grandchild = {
+            let raw = forest.read_lock();
+            raw.child(raw.child(tree.id, 2), 1)
+        };
+        forest.write_lock().swap(tree.id, grandchild);
+    }
+
+    #[test]
+    #[should_panic(expected="different forests")]
+    fn test_swap_across_forests() {
+        let forest: Forest<u32, u32> = Forest::new();
+        let other_forest: Forest<u32, u32> = Forest::new();
+        let mut tree = forest.new_leaf(1);
+        let mut other = other_forest.new_leaf(2);
+        Tree::swap(&mut tree, &mut other);
+    }
+
+    #[test]
+    fn test_delete_selection() {
+        let forest: Forest<u32, u32> = Forest::new();
+        {
+            let mut tree = mirror(&forest, 3, 0);
+            let stranger = forest.new_leaf(99);
+            let mut selection = Selection::new();
+            selection.insert(tree.borrow().child(0).bookmark());
+            selection.insert(tree.borrow().child(1).child(0).bookmark());
+            selection.insert(tree.borrow().child(2).child(1).child(0).bookmark());
+            selection.insert(stranger.borrow().bookmark());
+            assert_eq!(selection.len(), 4);
+            tree.goto_child(2);
+            tree.goto_child(1);
+            tree.goto_child(0);
+            assert_eq!(tree.delete_selection(&selection), 3);
+            // The cursor was on a deleted node, so it moves to its parent.
+            assert_eq!(*tree.data(), 6);
+            assert_eq!(tree.num_children(), 0);
+            tree.goto_root();
+            assert_eq!(tree.borrow().sum(), 28 - 1 - 3 - 7);
+            assert_eq!(tree.num_children(), 2);
+            assert_eq!(*stranger.leaf(), 99);
+            assert_eq!(forest.read_lock().tree_count(), 5 + 1);
+            // Deleting the same selection again does nothing.
+            assert_eq!(tree.delete_selection(&selection), 0);
+
+            // Nested selections delete the outer node, with everything in
+            // it, counting each node once.
+            let mut tree = mirror(&forest, 3, 0);
+            let mut selection = Selection::new();
+            selection.insert(tree.borrow().child(2).bookmark());
+            selection.insert(tree.borrow().child(2).child(1).bookmark());
+            selection.insert(tree.borrow().child(2).child(1).child(0).bookmark());
+            tree.goto_child(2);
+            tree.goto_child(1);
+            assert_eq!(tree.delete_selection(&selection), 4);
+            assert!(tree.at_root());
+            assert_eq!(tree.borrow().sum(), 1 + 2 + 3);
+            assert_eq!(forest.read_lock().tree_count(), 5 + 1 + 4);
+        }
+        assert_eq!(forest.read_lock().tree_count(), 0);
+    }
+
+    #[test]
+    fn test_unwrap_into_parent() {
+        let forest: Forest<u32, u32> = Forest::new();
+        {
+            let mut tree = mirror(&forest, 3, 0);
+            assert_eq!(tree.unwrap_into_parent(), Err(EditError::AtRoot));
+            tree.goto_child(0);
+            assert_eq!(tree.unwrap_into_parent(), Err(EditError::NotABranch));
+            tree.goto_parent();
+            // Unwrap the middle child, 2, leaving its child 3 in its place.
+            tree.goto_child(1);
+            assert_eq!(tree.unwrap_into_parent(), Ok(()));
+            assert_eq!(*tree.data(), 0);
+            assert!(tree.at_root());
+            // Then unwrap 4, whose children are 5 and 6.
+            tree.goto_child(2);
+            assert_eq!(tree.unwrap_into_parent(), Ok(()));
+            let children: Vec<u32> = tree.borrow()
+                .children()
+                .map(|child| if child.is_leaf() { *child.leaf() } else { *child.data() })
+                .collect();
+            assert_eq!(children, vec!(1, 3, 5, 6));
+            assert_eq!(*tree.borrow().child(1).parent().unwrap().data(), 0);
+            assert_eq!(*tree.borrow().child(3).child(0).leaf(), 7);
+            assert_eq!(tree.borrow().sum(), 28 - 2 - 4);
+            assert_eq!(forest.read_lock().tree_count(), 6);
+        }
+        assert_eq!(forest.read_lock().tree_count(), 0);
+    }
+
+    #[test]
+    fn test_wrap_in() {
+        let forest: Forest<u32, u32> = Forest::new();
+        {
+            let mut tree = mirror(&forest, 3, 0);
+            // Wrap the leaf 1 (the first child of the root).
+            tree.goto_child(0);
+            tree.wrap_in(10);
+            assert!(!tree.is_leaf());
+            assert_eq!(*tree.data(), 10);
+            assert_eq!(tree.num_children(), 1);
+            assert_eq!(*tree.borrow().child(0).leaf(), 1);
+            assert_eq!(*tree.borrow().parent().unwrap().data(), 0);
+            assert_eq!(tree.borrow().preorder_index(), 1);
+            tree.goto_root();
+            assert_eq!(tree.num_children(), 3);
+            assert_eq!(*tree.borrow().child(0).data(), 10);
+            assert_eq!(tree.borrow().sum(), 28 + 10);
+            // Wrap the root.
+            tree.wrap_in(100);
+            assert!(tree.at_root());
+            tree.goto_child(0);
+            tree.goto_root();
+            assert_eq!(*tree.data(), 100);
+            assert_eq!(*tree.borrow().child(0).data(), 0);
+            assert_eq!(tree.borrow().sum(), 28 + 10 + 100);
+            assert_eq!(forest.read_lock().tree_count(), 10);
+        }
+        assert_eq!(forest.read_lock().tree_count(), 0);
+    }
+
+    #[test]
+    fn test_merge_next_sibling() {
+        let forest: Forest<u32, u32> = Forest::new();
+        {
+            let mut tree = mirror(&forest, 3, 0);
+            assert_eq!(tree.merge_next_sibling(), Err(EditError::AtRoot));
+            tree.goto_child(0);
+            assert_eq!(tree.merge_next_sibling(), Err(EditError::NotABranch));
+            tree.goto_parent();
+            // Merge 4 (whose children are 5 and 6) into 2 (whose child is 3).
+            tree.goto_child(1);
+            assert_eq!(tree.merge_next_sibling(), Ok(()));
+            assert_eq!(*tree.data(), 2);
+            let children: Vec<u32> = tree.borrow()
+                .children()
+                .map(|child| if child.is_leaf() { *child.leaf() } else { *child.data() })
+                .collect();
+            assert_eq!(children, vec!(3, 5, 6));
+            assert_eq!(*tree.borrow().child(2).parent().unwrap().data(), 2);
+            assert_eq!(*tree.borrow().child(2).child(0).leaf(), 7);
+            assert_eq!(tree.merge_next_sibling(), Err(EditError::IndexOutOfBounds));
+            tree.goto_parent();
+            assert_eq!(tree.num_children(), 2);
+            assert_eq!(tree.borrow().sum(), 28 - 4);
+            assert_eq!(forest.read_lock().tree_count(), 7);
+        }
+        assert_eq!(forest.read_lock().tree_count(), 0);
+    }
+
+    #[test]
+    fn test_apply_at_cursors() {
+        let forest: Forest<u32, u32> = Forest::new();
+        {
+            let mut tree = mirror(&forest, 3, 0);
+            let mut cursors = MultiCursor::new();
+            tree.goto_child(0);
+            cursors.push(tree.bookmark());
+            tree.goto_parent();
+            tree.goto_child(2);
+            tree.goto_child(0);
+            cursors.push(tree.bookmark());
+            tree.goto_parent();
+            tree.goto_child(1);
+            tree.goto_child(0);
+            cursors.push(tree.bookmark());
+            tree.goto_root();
+            tree.goto_child(1);
+            let results = tree.apply_at_cursors(&cursors, || Edit::SetLeaf(9));
+            assert_eq!(results, vec!(Ok(()), Ok(()), Ok(())));
+            assert_eq!(*tree.data(), 2);
+            tree.goto_root();
+            let leaves: Vec<u32> = tree.borrow().leaves().map(|leaf| *leaf.leaf()).collect();
+            assert_eq!(leaves, vec!(9, 3, 9, 9));
+
+            // Delete node 4 at the same time as editing its descendant 6,
+            // and try to edit a leaf as a branch.
+            let mut cursors = MultiCursor::new();
+            cursors.push(tree.bookmark());
+            tree.goto_child(2);
+            tree.goto_child(1);
+            cursors.push(tree.bookmark());
+            tree.goto_child(0);
+            cursors.push(tree.bookmark());
+            let results = tree.apply_at_cursors(&cursors, || Edit::RemoveChild(0));
+            assert_eq!(results, vec!(Ok(()), Ok(()), Err(EditError::NotABranch)));
+            // This Tree was at the deleted leaf, so it moves up to 6.
+            assert_eq!(*tree.data(), 6);
+            assert_eq!(tree.num_children(), 0);
+            tree.goto_root();
+            assert_eq!(tree.borrow().sum(), 2 + 3 + 4 + 9 + 6);
+
+            // Deleted cursors are reported as such.
+            let results = tree.apply_at_cursors(&cursors, || Edit::SetData(0));
+            assert_eq!(results, vec!(Ok(()), Ok(()), Err(EditError::InvalidBookmark)));
+        }
+        assert_eq!(forest.read_lock().tree_count(), 0);
+    }
+
+    #[test]
+    fn test_undo_edit_group() {
+        let forest: Forest<u32, u32> = Forest::new();
+        let mut tree = mirror(&forest, 3, 0);
+        let leaves = |tree: &Tree<u32, u32>| tree.borrow().collect_leaves(|_| true);
+        assert!(!forest.undo());
+
+        tree.goto_child(0);
+        tree.set_leaf(10);
+        forest.begin_edit_group();
+        tree.set_leaf(11);
+        tree.set_leaf(12);
+        tree.set_leaf(13);
+        tree.goto_nth_leaf(1);
+        tree.set_leaf(30);
+        forest.end_edit_group();
+        tree.goto_root();
+        assert_eq!(leaves(&tree), vec!(13, 30, 5, 7));
+
+        // One undo reverts the whole group.
+        assert!(forest.undo());
+        assert_eq!(leaves(&tree), vec!(10, 3, 5, 7));
+        assert!(forest.undo());
+        assert_eq!(leaves(&tree), vec!(1, 3, 5, 7));
+        assert!(!forest.undo());
+
+        // Empty groups are not undo steps, and nested groups are merged.
+        forest.begin_edit_group();
+        forest.end_edit_group();
+        assert!(!forest.undo());
+        tree.goto_nth_leaf(2);
+        forest.begin_edit_group();
+        assert_eq!(tree.apply(Edit::SetLeaf(50)), Ok(()));
+        forest.begin_edit_group();
+        tree.set_leaf(51);
+        forest.end_edit_group();
+        tree.goto_nth_leaf(3);
+        tree.set_leaf(70);
+        forest.end_edit_group();
+        tree.goto_root();
+        assert_eq!(leaves(&tree), vec!(1, 3, 51, 70));
+        assert!(forest.undo());
+        assert_eq!(leaves(&tree), vec!(1, 3, 5, 7));
+    }
+
+    #[test]
+    fn test_undo_leaf_mut() {
+        let forest: Forest<u32, u32> = Forest::new();
+        let mut tree = mirror(&forest, 3, 0);
+        let leaves = |tree: &Tree<u32, u32>| tree.borrow().collect_leaves(|_| true);
+
+        // Each guard is one undo step, however many times it's written through.
+        tree.goto_child(0);
+        {
+            let mut leaf = tree.leaf_mut_journaled();
+            *leaf = 10;
+            *leaf += 1;
+        }
+        // Reading through the guard does not make an undo step, and
+        // neither does writing through `leaf_mut`.
+        assert_eq!(*tree.leaf_mut_journaled(), 11);
+        *tree.leaf_mut() = 12;
+        *tree.leaf_mut() = 11;
+        tree.goto_root();
+        assert_eq!(tree.replace_leaves_where(|n| *n > 4 && *n < 10, |n| n * 10), 2);
+        assert_eq!(leaves(&tree), vec!(11, 3, 50, 70));
+
+        // One undo reverts all of the replacements.
+        assert!(forest.undo());
+        assert_eq!(leaves(&tree), vec!(11, 3, 5, 7));
+        assert!(forest.undo());
+        assert_eq!(leaves(&tree), vec!(1, 3, 5, 7));
+        assert!(!forest.undo());
+    }
+
+    #[test]
+    fn test_undo_history_is_bounded() {
+        let forest: Forest<u32, usize> = Forest::new();
+        let mut tree = forest.new_leaf(0);
+        for i in 1..MAX_UNDO_STEPS + 10 {
+            tree.set_leaf(i);
+        }
+        let mut steps = 0;
+        while forest.undo() {
+            steps += 1;
+        }
+        assert_eq!(steps, MAX_UNDO_STEPS);
+        assert_eq!(*tree.leaf(), 9);
+    }
+
+    #[test]
+    #[should_panic(expected = "an edit group is still open")]
+    fn test_undo_in_edit_group() {
+        let forest: Forest<u32, u32> = Forest::new();
+        let mut tree = forest.new_leaf(1);
+        forest.begin_edit_group();
+        tree.set_leaf(2);
+        forest.undo();
+    }
+
+    #[test]
+    fn test_apply_all() {
+        let forest: Forest<&'static str, &'static str> = Forest::new();
+        {
+            let mut tree = family(&forest);
+            let results = tree.apply_all(vec!(
+                Edit::InsertChild(2, forest.new_leaf("youngest")),
+                Edit::InsertChild(7, forest.new_leaf("lost")),
+                Edit::RemoveChild(0),
+                Edit::RemoveChild(2),
+                Edit::ReplaceChild(1, forest.new_leaf("imposter")),
+                Edit::ReplaceChild(5, forest.new_leaf("lost")),
+                Edit::SetData("guardian"),
+                Edit::SetLeaf("nope")));
+            assert_eq!(results, vec!(Ok(()),
+                                     Err(EditError::IndexOutOfBounds),
+                                     Ok(()),
+                                     Err(EditError::IndexOutOfBounds),
+                                     Ok(()),
+                                     Err(EditError::IndexOutOfBounds),
+                                     Ok(()),
+                                     Err(EditError::NotALeaf)));
+            assert_eq!(*tree.data(), "guardian");
+            let children: Vec<&'static str> = tree.borrow()
+                .children()
+                .map(|child| *child.leaf())
+                .collect();
+            assert_eq!(children, vec!("younger", "imposter"));
+
+            tree.goto_child(0);
+            let results = tree.apply_all(vec!(
+                Edit::SetLeaf("elder"),
+                Edit::SetData("nope"),
+                Edit::RemoveChild(0)));
+            assert_eq!(results, vec!(Ok(()),
+                                     Err(EditError::NotABranch),
+                                     Err(EditError::NotABranch)));
+            assert_eq!(*tree.leaf(), "elder");
+            assert_eq!(forest.read_lock().tree_count(), 3);
+            tree.goto_parent();
+        }
+        assert_eq!(forest.read_lock().tree_count(), 0);
+    }
+
     #[test]
     fn comprehensive_exam() {
         let forest: Forest<u32, u32> = Forest::new();
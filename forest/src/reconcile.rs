@@ -0,0 +1,153 @@
+//! Bringing a tree up to date with a new copy of it (e.g. from re-parsing
+//! its source), while keeping the nodes that are still there.
+
+use crate::edit::Edit;
+use crate::serialize::TreeData;
+use crate::tree::Tree;
+use crate::tree_ref::TreeRef;
+
+
+/// What [`Tree::reconcile`](struct.Tree.html#method.reconcile) did.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReconcileReport {
+    /// The number of nodes that were kept, and so kept their ids and
+    /// bookmarks. (Their data or leaf values may have been updated.)
+    pub reused: usize,
+    /// The number of nodes that had to be created.
+    pub created: usize
+}
+
+impl<D: PartialEq, L: PartialEq> Tree<D, L> {
+    /// Edit the subtree at this node until it matches `new`, keeping as
+    /// many of its nodes as is easy to do. A node that is still a leaf
+    /// (or still a branch) is kept, and has its value updated if needed.
+    /// The children of a branch are diffed against the new children:
+    /// those with unchanged values are kept even if others were inserted
+    /// or removed around them, and the rest are matched up by position.
+    ///
+    /// Afterwards, this Tree is at the node that matches `new`. This is
+    /// a new node if it had to change between being a leaf and a branch.
+    pub fn reconcile(&mut self, new: TreeData<D, L>) -> ReconcileReport {
+        let mut report = ReconcileReport::default();
+        self.reconcile_node(new, &mut report);
+        report
+    }
+
+    fn reconcile_node(&mut self, new: TreeData<D, L>, report: &mut ReconcileReport) {
+        match new {
+            TreeData::Leaf(leaf) if self.is_leaf() => {
+                report.reused += 1;
+                if *self.leaf() != leaf {
+                    self.apply(Edit::SetLeaf(leaf)).expect("reconcile: failed to set leaf");
+                }
+            }
+            TreeData::Branch(data, children) if !self.is_leaf() => {
+                report.reused += 1;
+                if *self.data() != data {
+                    self.apply(Edit::SetData(data)).expect("reconcile: failed to set data");
+                }
+                self.reconcile_children(children, report);
+            }
+            new => {
+                report.created += size(&new);
+                let mut fresh = self.forest.from_tree_data(new);
+                Tree::swap(self, &mut fresh);
+            }
+        }
+    }
+
+    fn reconcile_children(&mut self, new: Vec<TreeData<D, L>>, report: &mut ReconcileReport) {
+        // Pair up the old and new children that have the same value, and
+        // keep them. Between those pairs, match the rest up by position.
+        let mut anchors = {
+            let tree = self.borrow();
+            let old: Vec<TreeRef<D, L>> = tree.children().collect();
+            longest_common_subsequence(&old, &new)
+        };
+        anchors.push((self.num_children(), new.len()));
+        let mut new = new.into_iter();
+        let (mut old_start, mut new_start, mut index) = (0, 0, 0);
+        for (old_anchor, new_anchor) in anchors {
+            let old_gap = old_anchor - old_start;
+            let new_gap = new_anchor - new_start;
+            for i in 0..old_gap.max(new_gap) {
+                if i < old_gap.min(new_gap) {
+                    self.reconcile_child(index, new.next().unwrap(), report);
+                    index += 1;
+                } else if i < new_gap {
+                    let child = new.next().unwrap();
+                    report.created += size(&child);
+                    let tree = self.forest.from_tree_data(child);
+                    self.apply(Edit::InsertChild(index, tree))
+                        .expect("reconcile: failed to insert");
+                    index += 1;
+                } else {
+                    self.apply(Edit::RemoveChild(index)).expect("reconcile: failed to remove");
+                }
+            }
+            if let Some(child) = new.next() {
+                self.reconcile_child(index, child, report);
+                index += 1;
+            }
+            old_start = old_anchor + 1;
+            new_start = new_anchor + 1;
+        }
+    }
+
+    fn reconcile_child(&mut self, index: usize, new: TreeData<D, L>,
+                       report: &mut ReconcileReport) {
+        self.goto_child(index);
+        self.reconcile_node(new, report);
+        self.goto_parent();
+    }
+}
+
+// Does the node have the same leaf value, or the same data, as `data`'s
+// root? (Its children are not compared.)
+fn same_value<D: PartialEq, L: PartialEq>(tree: &TreeRef<D, L>, data: &TreeData<D, L>) -> bool {
+    match data {
+        TreeData::Leaf(leaf) => tree.is_leaf() && *tree.leaf() == *leaf,
+        TreeData::Branch(value, _) => !tree.is_leaf() && *tree.data() == *value
+    }
+}
+
+// The index pairs of a longest common subsequence of `old` and `new`,
+// comparing them with `same_value`. In increasing order.
+fn longest_common_subsequence<D: PartialEq, L: PartialEq>(old: &[TreeRef<D, L>],
+                                                          new: &[TreeData<D, L>])
+                                                          -> Vec<(usize, usize)>
+{
+    // lengths[i][j] is the length of the LCS of old[i..] and new[j..].
+    let mut lengths = vec!(vec!(0; new.len() + 1); old.len() + 1);
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if same_value(&old[i], &new[j]) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let mut pairs = vec!();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if same_value(&old[i], &new[j]) {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+// The number of nodes in `data`.
+fn size<D, L>(data: &TreeData<D, L>) -> usize {
+    match data {
+        TreeData::Leaf(_) => 1,
+        TreeData::Branch(_, children) => 1 + children.iter().map(size).sum::<usize>()
+    }
+}
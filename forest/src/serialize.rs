@@ -0,0 +1,115 @@
+//! A compact binary format for saving and loading trees, and an owned
+//! (serializable) form of a tree.
+
+use std::io::{Read, Write};
+
+use serde::{Serialize, Serializer, Deserialize};
+use serde::de::DeserializeOwned;
+use serde::ser::SerializeTupleVariant;
+
+use crate::forest::{Id, RawForest};
+use crate::tree::{Tree, Forest};
+use crate::tree_ref::TreeRef;
+
+
+/// A node in a forest, serialized in the same shape as `TreeData`.
+pub (crate) struct SerNode<'a, D, L> {
+    pub (crate) forest: &'a RawForest<D, L>,
+    pub (crate) id: Id
+}
+
+/// A tree that has been copied out of a forest (or not yet added to one),
+/// e.g. to put on a clipboard.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TreeData<D, L> {
+    Leaf(L),
+    Branch(D, Vec<TreeData<D, L>>)
+}
+
+impl<'a, D: Serialize, L: Serialize> Serialize for SerNode<'a, D, L> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.forest.is_leaf(self.id) {
+            serializer.serialize_newtype_variant("TreeData", 0, "Leaf", self.forest.leaf(self.id))
+        } else {
+            let children: Vec<SerNode<D, L>> = self.forest.children(self.id).iter()
+                .map(|&id| SerNode { forest: self.forest, id })
+                .collect();
+            let mut variant = serializer.serialize_tuple_variant("TreeData", 1, "Branch", 2)?;
+            variant.serialize_field(self.forest.data(self.id))?;
+            variant.serialize_field(&children)?;
+            variant.end()
+        }
+    }
+}
+
+impl<D, L> Forest<D, L> {
+    /// Write the subtree at `root`'s current node in a compact binary
+    /// format. Bookmarks are not saved.
+    pub fn save_binary<W: Write>(&self, root: &Tree<D, L>, writer: &mut W) -> bincode::Result<()>
+        where D: Serialize, L: Serialize
+    {
+        let forest = self.read_lock();
+        bincode::serialize_into(writer, &SerNode { forest: &forest, id: root.id })
+    }
+
+    /// Read a tree written by `save_binary`, and add it to this forest.
+    pub fn load_binary<R: Read>(&self, reader: &mut R) -> bincode::Result<Tree<D, L>>
+        where D: DeserializeOwned, L: DeserializeOwned
+    {
+        let data: TreeData<D, L> = bincode::deserialize_from(reader)?;
+        Ok(self.from_tree_data(data))
+    }
+
+    /// Add a copy of `data` to this forest, as a new tree.
+    pub fn from_tree_data(&self, data: TreeData<D, L>) -> Tree<D, L> {
+        match data {
+            TreeData::Leaf(leaf) => self.new_leaf(leaf),
+            TreeData::Branch(data, children) => {
+                let children = children.into_iter()
+                    .map(|child| self.from_tree_data(child))
+                    .collect();
+                self.new_branch(data, children)
+            }
+        }
+    }
+}
+
+impl<'f, D: Clone, L: Clone> TreeRef<'f, D, L> {
+    /// Copy this subtree out of the forest.
+    pub fn to_tree_data(&self) -> TreeData<D, L> {
+        if self.is_leaf() {
+            TreeData::Leaf(self.leaf().clone())
+        } else {
+            TreeData::Branch(self.data().clone(),
+                             self.children().map(|child| child.to_tree_data()).collect())
+        }
+    }
+}
+
+impl<D, L> Tree<D, L> {
+    /// Add `data` to the forest, and replace the `i`th child of this node
+    /// with it. Returns the original child. (That is, apply an edit whose
+    /// new contents arrived in serialized form.)
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a leaf node, or if `i` is out of bounds.
+    pub fn replace_child_from_data(&mut self, i: usize, data: TreeData<D, L>) -> Tree<D, L> {
+        let new_child = self.forest.from_tree_data(data);
+        self.replace_child(i, new_child)
+    }
+}
+
+impl<D: Clone, L: Clone> Tree<D, L> {
+    /// Remove the `i`th child of this node, delete it from the forest, and
+    /// return a copy of it. (That is, cut it to the clipboard.)
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a leaf node, or if `i` is out of bounds.
+    pub fn cut_child(&mut self, i: usize) -> TreeData<D, L> {
+        let child = self.remove_child(i);
+        let data = child.borrow().to_tree_data();
+        data
+    }
+}
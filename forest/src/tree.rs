@@ -1,10 +1,13 @@
 use std::mem;
+use std::collections::HashSet;
 use std::rc::Rc;
 use std::cell::{RefCell, Ref, RefMut};
 use std::ops::{Deref, DerefMut};
 use std::thread;
 
-use crate::forest::{Id, RawForest};
+use crate::error::EditError;
+use crate::forest::{Id, NodeKind, RawForest};
+use crate::tree_ref::TreeRef;
 
 
 /// All [Trees](struct.Tree.html) belong to a Forest.
@@ -12,6 +15,12 @@ use crate::forest::{Id, RawForest};
 /// It is your responsibility to ensure that Trees are kept with the
 /// Forest they came from. The methods on Trees will panic if you use
 /// them on a different Forest.
+///
+/// Each Tree keeps a handle to its Forest, so once a tree has been
+/// created, it (and any `TreeRef` borrowed from it) can be navigated and
+/// edited without passing the Forest around. The Forest itself is only
+/// needed to create new nodes, and even that can be done without it
+/// inside of `Forest::with`.
 pub struct Forest<D, L> {
     pub (super) lock: Rc<RefCell<RawForest<D, L>>>
 }
@@ -45,11 +54,47 @@ pub struct Tree<D, L> {
     pub (super) id: Id
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Bookmark {
     pub (super) id: Id
 }
 
+/// A set of (not necessarily adjacent) nodes, for operating on many
+/// nodes at once.
+#[derive(Clone, Default)]
+pub struct Selection {
+    marks: HashSet<Bookmark>
+}
+
+impl Selection {
+    /// Construct an empty selection.
+    pub fn new() -> Selection {
+        Selection {
+            marks: HashSet::new()
+        }
+    }
+
+    /// Add a node to the selection.
+    pub fn insert(&mut self, mark: Bookmark) {
+        self.marks.insert(mark);
+    }
+
+    /// Remove a node from the selection.
+    pub fn remove(&mut self, mark: Bookmark) {
+        self.marks.remove(&mark);
+    }
+
+    /// The number of nodes in the selection.
+    pub fn len(&self) -> usize {
+        self.marks.len()
+    }
+
+    /// Returns `true` if nothing is selected.
+    pub fn is_empty(&self) -> bool {
+        self.marks.is_empty()
+    }
+}
+
 impl<D, L> Forest<D, L> {
     /// Construct a new forest.
     pub fn new() -> Forest<D, L> {
@@ -58,6 +103,16 @@ impl<D, L> Forest<D, L> {
         }
     }
 
+    /// Limit the number of children any one branch in this forest may
+    /// have, to catch runaway insertions. Making a branch with too many
+    /// children (e.g. with `new_branch` or `Tree::insert_child`) panics,
+    /// and edits that return a `Result` fail with
+    /// `EditError::TooManyChildren` instead. `None` (the default) means
+    /// no limit. Existing branches are not checked.
+    pub fn set_max_children(&self, max: Option<usize>) {
+        self.write_lock().set_max_children(max);
+    }
+
     /// Construct a new leaf.
     pub fn new_leaf(&self, leaf: L) -> Tree<D, L> {
         let leaf_id = self.write_lock().create_leaf(leaf);
@@ -66,6 +121,25 @@ impl<D, L> Forest<D, L> {
 
     /// Construct a new branch.
     pub fn new_branch(&self, data: D, children: Vec<Tree<D, L>>) -> Tree<D, L> {
+        self.new_branch_from_iter(data, children)
+    }
+
+    /// Like `new_branch`, but taking the children from an iterator, e.g.
+    /// `forest.new_branch_from_iter(data, names.map(|name| forest.new_leaf(name)))`.
+    /// The iterator is consumed before the forest is locked, so it may
+    /// use the forest itself.
+    pub fn new_branch_from_iter<I>(&self, data: D, children: I) -> Tree<D, L>
+        where I: IntoIterator<Item = Tree<D, L>>
+    {
+        let children: Vec<Tree<D, L>> = children.into_iter().collect();
+        let count = children.len();
+        if !self.read_lock().allows_children(count) {
+            // Delete the children before panicking (trees aren't deleted
+            // while unwinding), rather than leaking them.
+            mem::drop(children);
+            self.read_lock().check_children(count);
+            unreachable!();
+        }
         let child_ids = children.into_iter().map(|tree| {
             let id = tree.id;
             mem::forget(tree);
@@ -75,6 +149,58 @@ impl<D, L> Forest<D, L> {
         Tree::new(self, branch_id)
     }
 
+    /// Look up a node by its id. Returns `None` if the node has since
+    /// been deleted.
+    pub fn get(&self, id: Id) -> Option<TreeRef<'_, D, L>> {
+        let forest = self.read_lock();
+        if forest.is_valid(id) {
+            Some(TreeRef {
+                forest: self,
+                root: forest.root(id),
+                id
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Are the nodes of both bookmarks in the same tree? False if either
+    /// node has been deleted.
+    pub fn same_tree(&self, a: Bookmark, b: Bookmark) -> bool {
+        let forest = self.read_lock();
+        forest.is_valid(a.id) && forest.is_valid(b.id)
+            && forest.root(a.id) == forest.root(b.id)
+    }
+
+    /// List the node `root` and all of its descendants in pre-order, each
+    /// with its depth below `root` and whether it is a leaf or a branch.
+    /// This is convenient for displaying a tree as an indented outline.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `root` has been deleted.
+    pub fn outline(&self, root: Id) -> Vec<(usize, Id, NodeKind)> {
+        self.read_lock().outline(root)
+    }
+
+    /// Find nodes that have become unreachable due to a bug: they claim
+    /// to have a parent, but no node has them as a child. This should
+    /// always be empty.
+    pub fn find_orphans(&self) -> Vec<Id> {
+        self.read_lock().find_orphans()
+    }
+
+    /// An _approximate_ count of the bytes of memory used by the nodes in
+    /// this forest. The sizes of the data and leaf values themselves are
+    /// included, but anything they own (e.g. the contents of a `String`)
+    /// is opaque to the forest, so must be estimated by `data_bytes` and
+    /// `leaf_bytes`. The overhead of the forest's hash map is not counted.
+    pub fn estimated_bytes<F, G>(&self, data_bytes: F, leaf_bytes: G) -> usize
+        where F: Fn(&D) -> usize, G: Fn(&L) -> usize
+    {
+        self.read_lock().estimated_bytes(data_bytes, leaf_bytes)
+    }
+
     pub (super) fn write_lock(&self) -> RefMut<RawForest<D, L>> {
         self.lock.try_borrow_mut().expect("Failed to obtain write lock for forest.")
     }
@@ -137,7 +263,9 @@ impl<D, L> Tree<D, L> {
         }
     }
 
-    /// Obtain a mutable reference to the leaf value at this node.
+    /// Obtain a mutable reference to the leaf value at this node. Edits
+    /// made through it are not journaled, so they cannot be undone (see
+    /// `leaf_mut_journaled`).
     ///
     /// # Panics
     ///
@@ -145,10 +273,52 @@ impl<D, L> Tree<D, L> {
     pub fn leaf_mut(&mut self) -> WriteLeaf<D, L> {
         WriteLeaf {
             guard: self.forest_mut(),
-            id: self.id
+            id: self.id,
+            journal: None
+        }
+    }
+
+    /// Like `leaf_mut`, but the first time it is mutably dereferenced,
+    /// the old value is journaled, so that the edit can be undone with
+    /// `Forest::undo`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a branch node.
+    pub fn leaf_mut_journaled(&mut self) -> WriteLeaf<D, L> where L: Clone {
+        WriteLeaf {
+            guard: self.forest_mut(),
+            id: self.id,
+            journal: Some(RawForest::record_leaf)
         }
     }
 
+    /// Exchange the data value of this node with that of the bookmark's
+    /// node, without copying either. The bookmark's node may be in any
+    /// tree in the forest.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either node is a leaf, or if the bookmark's node has
+    /// been deleted.
+    pub fn swap_data(&mut self, other: Bookmark) {
+        let id = self.id;
+        self.forest_mut().swap_data(id, other.id);
+    }
+
+    /// Exchange the leaf value of this node with that of the bookmark's
+    /// node, without copying either. The bookmark's node may be in any
+    /// tree in the forest. Unlike `leaf_mut`, this cannot be undone.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either node is a branch, or if the bookmark's node has
+    /// been deleted.
+    pub fn swap_leaf(&mut self, other: Bookmark) {
+        let id = self.id;
+        self.forest_mut().swap_leaf(id, other.id);
+    }
+
     /// Replace the `i`th child of this node with `tree`.
     /// Returns the original child.
     /// 
@@ -182,6 +352,227 @@ impl<D, L> Tree<D, L> {
         Tree::new(&self.forest, old_tree_id)
     }
 
+    /// Remove (and delete) every child of this node for which `pred`
+    /// returns `false`. The remaining children keep their order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a leaf node.
+    pub fn retain_children<F>(&mut self, pred: F)
+        where F: Fn(TreeRef<D, L>) -> bool
+    {
+        let doomed: Vec<usize> = self.borrow().children().enumerate()
+            .filter_map(|(i, child)| if pred(child) { None } else { Some(i) })
+            .collect();
+        for i in doomed.into_iter().rev() {
+            self.remove_child(i);
+        }
+    }
+
+    /// Rotate this node's children `n` places to the left, so that the
+    /// `n`th child becomes the first, and the first `n` children move to
+    /// the end.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a leaf node, or if `n` is greater than the number
+    /// of children.
+    pub fn rotate_children_left(&mut self, n: usize) {
+        self.forest_mut().children_mut(self.id).rotate_left(n);
+    }
+
+    /// Rotate this node's children `n` places to the right, so that the
+    /// last `n` children move to the front.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a leaf node, or if `n` is greater than the number
+    /// of children.
+    pub fn rotate_children_right(&mut self, n: usize) {
+        self.forest_mut().children_mut(self.id).rotate_right(n);
+    }
+
+    /// Call `f` on this node and each of its descendants, in pre-order,
+    /// with this Tree moved to that node, so that `f` can edit it in
+    /// place. Afterwards, this Tree is back where it started.
+    ///
+    /// The forest is only locked for the duration of each method call on
+    /// the Tree, never while `f` runs, so `f` may use any method of the
+    /// Tree it is given (but must not hold onto a `data_mut` or similar
+    /// guard across calls). `f` may move the Tree: it is put back on the
+    /// node being visited when `f` returns. Changes that `f` makes to a
+    /// node's children are seen by the traversal, which visits the
+    /// children the node has after `f` returns. `f` must not delete the
+    /// node it is given.
+    pub fn for_each_descendant_mut<F>(&mut self, mut f: F)
+        where F: FnMut(&mut Tree<D, L>)
+    {
+        let start = self.id;
+        self.visit_descendants_mut(&mut f);
+        self.id = start;
+    }
+
+    fn visit_descendants_mut<F>(&mut self, f: &mut F)
+        where F: FnMut(&mut Tree<D, L>)
+    {
+        let id = self.id;
+        f(self);
+        self.id = id;
+        if self.is_leaf() {
+            return;
+        }
+        let mut i = 0;
+        while i < self.num_children() {
+            self.goto_child(i);
+            self.visit_descendants_mut(f);
+            self.id = id;
+            i += 1;
+        }
+    }
+
+    /// Replace the value of every leaf in this subtree that satisfies
+    /// `pred` with `new` of its old value. Returns the number of leaves
+    /// replaced. The replacements are undone all at once by
+    /// `Forest::undo`.
+    pub fn replace_leaves_where<P, N>(&mut self, pred: P, new: N) -> usize
+        where P: Fn(&L) -> bool, N: Fn(&L) -> L
+    {
+        let mut count = 0;
+        let forest = self.forest.clone();
+        forest.begin_edit_group();
+        self.for_each_descendant_mut(|node| {
+            if !node.is_leaf() {
+                return;
+            }
+            let new_leaf = {
+                let leaf = node.leaf();
+                if pred(&leaf) { Some(new(&leaf)) } else { None }
+            };
+            if let Some(new_leaf) = new_leaf {
+                node.set_leaf(new_leaf);
+                count += 1;
+            }
+        });
+        forest.end_edit_group();
+        count
+    }
+
+    /// Exchange the subtree at `a` with the subtree at `b`. Each node
+    /// takes the other's place in its parent; if a node was the root of
+    /// its tree, the other node becomes the root instead. Afterwards,
+    /// `a` and `b` each point at the node that now occupies their
+    /// original position. Bookmarks follow the nodes they were made on.
+    /// Since no two Trees share a tree, `a` and `b` are always in
+    /// different trees (of the same Forest), so neither can be an
+    /// ancestor of the other.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the Trees belong to different Forests.
+    pub fn swap(a: &mut Tree<D, L>, b: &mut Tree<D, L>) {
+        assert!(Rc::ptr_eq(&a.forest.lock, &b.forest.lock),
+                "Tree::swap - the trees belong to different forests");
+        let a_at_root = a.at_root();
+        let b_at_root = b.at_root();
+        a.forest_mut().swap(a.id, b.id);
+        mem::swap(&mut a.id, &mut b.id);
+        if a_at_root {
+            a.root = a.id;
+        }
+        if b_at_root {
+            b.root = b.id;
+        }
+    }
+
+    /// Remove this branch node from its parent, putting its children in
+    /// its place (in order). The branch's data is discarded. Afterwards,
+    /// this Tree is positioned at the former parent.
+    ///
+    /// Fails if this is a leaf node or the root of the tree.
+    pub fn unwrap_into_parent(&mut self) -> Result<(), EditError> {
+        let mut forest = self.forest.write_lock();
+        let parent = match forest.parent(self.id) {
+            None => return Err(EditError::AtRoot),
+            Some(parent) => parent
+        };
+        if forest.is_leaf(self.id) {
+            return Err(EditError::NotABranch);
+        }
+        let count = forest.children(parent).len() - 1 + forest.children(self.id).len();
+        if !forest.allows_children(count) {
+            return Err(EditError::TooManyChildren);
+        }
+        let index = forest.index_of(parent, self.id);
+        forest.remove_child(parent, index);
+        let children = mem::take(forest.children_mut(self.id));
+        for (i, child) in children.into_iter().enumerate() {
+            forest.insert_child(parent, index + i, child);
+        }
+        forest.delete_tree(self.id);
+        self.id = parent;
+        Ok(())
+    }
+
+    /// Replace this node with a new branch containing `data`, whose only
+    /// child is this node. (The inverse of `unwrap_into_parent`.) The new
+    /// branch takes this node's place in its parent, or becomes the root
+    /// of the tree if this was the root. Afterwards, this Tree is
+    /// positioned at the new branch.
+    pub fn wrap_in(&mut self, data: D) {
+        let mut forest = self.forest.write_lock();
+        let place = forest.parent(self.id).map(|parent| {
+            let index = forest.index_of(parent, self.id);
+            forest.remove_child(parent, index);
+            (parent, index)
+        });
+        let wrapper = forest.create_branch(data, vec!(self.id));
+        match place {
+            Some((parent, index)) => forest.insert_child(parent, index, wrapper),
+            None => self.root = wrapper
+        }
+        self.id = wrapper;
+    }
+
+    /// Move all of the children of this branch's next sibling onto the
+    /// end of this branch's children, and delete the (now empty) sibling.
+    /// The sibling's data is discarded.
+    ///
+    /// Fails if this node is the root of the tree or has no next sibling,
+    /// or if either node is a leaf.
+    pub fn merge_next_sibling(&mut self) -> Result<(), EditError> {
+        let mut forest = self.forest.write_lock();
+        let parent = match forest.parent(self.id) {
+            None => return Err(EditError::AtRoot),
+            Some(parent) => parent
+        };
+        let index = forest.index_of(parent, self.id);
+        if index + 1 >= forest.children(parent).len() {
+            return Err(EditError::IndexOutOfBounds);
+        }
+        let sibling = forest.child(parent, index + 1);
+        if forest.is_leaf(self.id) || forest.is_leaf(sibling) {
+            return Err(EditError::NotABranch);
+        }
+        if !forest.allows_children(forest.children(self.id).len()
+                                   + forest.children(sibling).len()) {
+            return Err(EditError::TooManyChildren);
+        }
+        forest.remove_child(parent, index + 1);
+        let children = mem::take(forest.children_mut(sibling));
+        let len = forest.children(self.id).len();
+        for (i, child) in children.into_iter().enumerate() {
+            forest.insert_child(self.id, len + i, child);
+        }
+        forest.delete_tree(sibling);
+        Ok(())
+    }
+
+    /// The id of the node this Tree is at. It can be resolved back into
+    /// a node with `Forest::get`, for as long as the node exists.
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
     /// Save a bookmark to return to later.
     pub fn bookmark(&mut self) -> Bookmark {
         Bookmark {
@@ -204,6 +595,45 @@ impl<D, L> Tree<D, L> {
         }
     }
 
+    /// Delete every selected node (and its descendants) from this tree.
+    /// Bookmarks whose nodes have been deleted, are in a different tree,
+    /// or are the root of this tree are skipped. Returns the number of
+    /// nodes that were deleted, counting descendants. (A selected node
+    /// inside another selected node is only counted once.)
+    ///
+    /// If this Tree is positioned within a deleted node, it moves to the
+    /// nearest surviving ancestor.
+    pub fn delete_selection(&mut self, selection: &Selection) -> usize {
+        let mut targets: Vec<(usize, usize, Id)> = {
+            let forest = self.forest();
+            selection.marks.iter()
+                .map(|mark| mark.id)
+                .filter(|id| {
+                    forest.is_valid(*id) && forest.root(*id) == self.root && *id != self.root
+                })
+                .map(|id| {
+                    let parent = forest.parent(id).expect("Forest - non-root node has no parent!");
+                    (forest.depth(id), forest.index_of(parent, id), id)
+                })
+                .collect()
+        };
+        // Remove deeper and later nodes first, so that removing one
+        // node never invalidates another.
+        targets.sort_by(|a, b| b.cmp(a));
+        let mut forest = self.forest.write_lock();
+        let mut count = 0;
+        for (_, _, id) in &targets {
+            let parent = forest.parent(*id).expect("Forest - non-root node has no parent!");
+            if forest.is_ancestor(*id, self.id) {
+                self.id = parent;
+            }
+            let index = forest.index_of(parent, *id);
+            forest.remove_child(parent, index);
+            count += forest.delete_tree(*id);
+        }
+        count
+    }
+
     /// Returns `true` if this is the root of the tree, and `false` if
     /// it isn't (and thus this node has a parent).
     pub fn at_root(&self) -> bool {
@@ -238,6 +668,56 @@ impl<D, L> Tree<D, L> {
         self.id = id;
     }
 
+    /// Go to the next leaf in the tree, in pre-order (i.e., document
+    /// order). Returns `false` and stays put if there isn't one.
+    pub fn goto_next_leaf(&mut self) -> bool {
+        let forest = self.forest();
+        let mut id = self.id;
+        while let Some(next) = forest.preorder_next(id) {
+            if forest.is_leaf(next) {
+                drop(forest);
+                self.id = next;
+                return true;
+            }
+            id = next;
+        }
+        false
+    }
+
+    /// Go to the `n`th leaf (counting from 0) of the whole tree, in
+    /// pre-order (i.e., document order). Returns `false` and stays put if
+    /// the tree has `n` or fewer leaves.
+    pub fn goto_nth_leaf(&mut self, n: usize) -> bool {
+        let root = TreeRef {
+            forest: &self.forest,
+            root: self.root,
+            id: self.root
+        };
+        match root.leaves().nth(n).map(|leaf| leaf.id) {
+            Some(id) => {
+                self.id = id;
+                true
+            }
+            None => false
+        }
+    }
+
+    /// Go to the previous leaf in the tree, in pre-order (i.e., document
+    /// order). Returns `false` and stays put if there isn't one.
+    pub fn goto_prev_leaf(&mut self) -> bool {
+        let forest = self.forest();
+        let mut id = self.id;
+        while let Some(prev) = forest.preorder_prev(id) {
+            if forest.is_leaf(prev) {
+                drop(forest);
+                self.id = prev;
+                return true;
+            }
+            id = prev;
+        }
+        false
+    }
+
     // Private //
 
     pub (super) fn new(forest: &Forest<D, L>, id: Id) -> Tree<D, L> {
@@ -290,9 +770,15 @@ pub struct WriteData<'f, D, L> {
 /// Provides write access to a tree's leaf. Released on drop.
 pub struct WriteLeaf<'f, D, L> {
     pub (super) guard: RefMut<'f, RawForest<D, L>>,
-    pub (super) id: Id
+    pub (super) id: Id,
+    // How to journal the leaf's old value, if it is to be journaled and
+    // hasn't been yet.
+    pub (super) journal: Option<RecordLeaf<D, L>>
 }
 
+// Journals the old value of a leaf that is about to be modified in place.
+type RecordLeaf<D, L> = fn(&mut RawForest<D, L>, Id);
+
 impl<'f, D, L> Deref for ReadData<'f, D, L> {
     type Target = D;
     fn deref(&self) -> &D {
@@ -329,6 +815,9 @@ impl<'f, D, L> Deref for WriteLeaf<'f, D, L> {
 
 impl<'f, D, L> DerefMut for WriteLeaf<'f, D, L> {
     fn deref_mut(&mut self) -> &mut L {
+        if let Some(record) = self.journal.take() {
+            record(&mut self.guard, self.id);
+        }
         self.guard.leaf_mut(self.id)
     }
 }
@@ -1,6 +1,9 @@
 use std::iter::Iterator;
 use std::cell::Ref;
+use std::ops::ControlFlow;
+use std::hash::{Hash, Hasher};
 
+use crate::fnv::FnvHasher;
 use crate::forest::{Id, RawForest};
 use crate::tree::{Tree, Bookmark, Forest, ReadData, ReadLeaf};
 
@@ -92,6 +95,26 @@ impl<'f, D, L> TreeRef<'f, D, L> {
         }
     }
 
+    /// The child indices leading from this node down to the bookmark's
+    /// node, if that node is this one or one of its descendants. (It is
+    /// empty if the bookmark is of this node.) Returns `None` if the
+    /// bookmark's node has been deleted, or is not below this one.
+    pub fn path_to(&self, mark: Bookmark) -> Option<Vec<usize>> {
+        let forest = self.forest();
+        if !forest.is_valid(mark.id) {
+            return None;
+        }
+        let mut path = vec!();
+        let mut id = mark.id;
+        while id != self.id {
+            let parent = forest.parent(id)?;
+            path.push(forest.index_of(parent, id));
+            id = parent;
+        }
+        path.reverse();
+        Some(path)
+    }
+
     /// Get the parent node. Returns `None` if we're already at the
     /// root of the tree.
     pub fn parent(&self) -> Option<TreeRef<'f, D, L>> {
@@ -105,6 +128,77 @@ impl<'f, D, L> TreeRef<'f, D, L> {
         }
     }
 
+    /// The number of nodes that come before this one in a pre-order
+    /// walk of the whole tree. (The root has index 0.)
+    pub fn preorder_index(&self) -> usize {
+        let forest = self.forest();
+        let mut index = 0;
+        let mut id = self.id;
+        while let Some(parent) = forest.parent(id) {
+            let i = forest.index_of(parent, id);
+            index += 1 + forest.children(parent)[..i].iter()
+                .map(|&sibling| forest.subtree_size(sibling))
+                .sum::<usize>();
+            id = parent;
+        }
+        index
+    }
+
+    /// A hash of the contents of this subtree: its shape, data, and
+    /// leaves. Subtrees with the same contents hash the same, no matter
+    /// where they are or which forest they live in. The hash uses a fixed
+    /// algorithm (FNV-1a), so it is stable across runs, builds, and
+    /// platforms, as long as the `Hash` impls of `D` and `L` are.
+    pub fn structural_hash(&self) -> u64
+        where D: Hash, L: Hash
+    {
+        let mut hasher = FnvHasher::new();
+        self.hash_structure(&mut hasher);
+        hasher.finish()
+    }
+
+    fn hash_structure<H: Hasher>(&self, hasher: &mut H)
+        where D: Hash, L: Hash
+    {
+        if self.is_leaf() {
+            false.hash(hasher);
+            self.leaf().hash(hasher);
+        } else {
+            true.hash(hasher);
+            self.data().hash(hasher);
+            self.num_children().hash(hasher);
+            for child in self.children() {
+                child.hash_structure(hasher);
+            }
+        }
+    }
+
+    /// Get the sibling just after this node, or `None` if this is the
+    /// last child or the root.
+    pub fn next_sibling(&self) -> Option<TreeRef<'f, D, L>> {
+        self.sibling(|i| i.checked_add(1))
+    }
+
+    /// Get the sibling just before this node, or `None` if this is the
+    /// first child or the root.
+    pub fn prev_sibling(&self) -> Option<TreeRef<'f, D, L>> {
+        self.sibling(|i| i.checked_sub(1))
+    }
+
+    fn sibling<F>(&self, offset: F) -> Option<TreeRef<'f, D, L>>
+        where F: Fn(usize) -> Option<usize>
+    {
+        let forest = self.forest();
+        let parent = forest.parent(self.id)?;
+        let index = offset(forest.index_of(parent, self.id))?;
+        let id = *forest.children(parent).get(index)?;
+        Some(TreeRef {
+            forest: self.forest,
+            root: self.root,
+            id
+        })
+    }
+
     /// Get the `i`th child of this branch node.
     ///
     /// # Panics
@@ -130,6 +224,78 @@ impl<'f, D, L> TreeRef<'f, D, L> {
         }
     }
 
+    /// Obtain an iterator over all of the leaves in this subtree, in
+    /// left-to-right (document) order. Branch nodes are skipped. If this
+    /// node is itself a leaf, the iterator yields just this node.
+    pub fn leaves(&self) -> RefLeavesIter<'f, D, L> {
+        RefLeavesIter {
+            forest: self.forest,
+            root: self.root,
+            stack: vec!(self.id)
+        }
+    }
+
+    /// Clone the value of every leaf in this subtree for which `f`
+    /// returns `true`, in left-to-right (document) order.
+    pub fn collect_leaves<F>(&self, f: F) -> Vec<L>
+        where F: Fn(&L) -> bool, L: Clone
+    {
+        self.leaves()
+            .filter(|leaf| f(&leaf.leaf()))
+            .map(|leaf| leaf.leaf().clone())
+            .collect()
+    }
+
+    /// Iterate over all nodes in this subtree in pre-order, together
+    /// with their paths: the child indices to follow to reach them from
+    /// this node. (This node's path is empty.)
+    pub fn enumerate_paths(&self) -> RefPathsIter<'f, D, L> {
+        RefPathsIter {
+            forest: self.forest,
+            root: self.root,
+            stack: vec!((vec!(), self.id))
+        }
+    }
+
+    /// Visit every node in this subtree in pre-order, stopping as soon as
+    /// `visitor` returns `ControlFlow::Break`. Returns the value it broke
+    /// with, or `None` if it visited every node without breaking.
+    pub fn visit_until<R, F>(&self, mut visitor: F) -> Option<R>
+        where F: FnMut(TreeRef<'f, D, L>) -> ControlFlow<R>
+    {
+        let mut stack = vec!(self.id);
+        while let Some(id) = stack.pop() {
+            let node = TreeRef {
+                forest: self.forest,
+                root: self.root,
+                id
+            };
+            if let ControlFlow::Break(result) = visitor(node) {
+                return Some(result);
+            }
+            let forest = self.forest();
+            if !forest.is_leaf(id) {
+                stack.extend(forest.children(id).iter().rev());
+            }
+        }
+        None
+    }
+
+    /// The number of nodes in this subtree (including this one) for which
+    /// `pred` returns `true`.
+    pub fn count_where<F>(&self, pred: F) -> usize
+        where F: Fn(TreeRef<'f, D, L>) -> bool
+    {
+        let mut count = 0;
+        self.visit_until(|node| {
+            if pred(node) {
+                count += 1;
+            }
+            ControlFlow::<()>::Continue(())
+        });
+        count
+    }
+
     // Private //
 
     fn forest(&self) -> Ref<'f, RawForest<D, L>> {
@@ -161,3 +327,55 @@ impl<'f, D, L> Iterator for RefChildrenIter<'f, D, L> {
         }
     }
 }
+
+/// An iterator over the leaves of a tree, in left-to-right order.
+pub struct RefLeavesIter<'f, D: 'f, L: 'f> {
+    forest: &'f Forest<D, L>,
+    root: Id,
+    stack: Vec<Id>
+}
+
+impl<'f, D, L> Iterator for RefLeavesIter<'f, D, L> {
+    type Item = TreeRef<'f, D, L>;
+    fn next(&mut self) -> Option<TreeRef<'f, D, L>> {
+        let forest = self.forest.read_lock();
+        while let Some(id) = self.stack.pop() {
+            if forest.is_leaf(id) {
+                return Some(TreeRef {
+                    forest: self.forest,
+                    root: self.root,
+                    id
+                });
+            }
+            self.stack.extend(forest.children(id).iter().rev());
+        }
+        None
+    }
+}
+
+/// An iterator over the nodes in a tree, and their paths.
+pub struct RefPathsIter<'f, D: 'f, L: 'f> {
+    forest: &'f Forest<D, L>,
+    root: Id,
+    stack: Vec<(Vec<usize>, Id)>
+}
+
+impl<'f, D, L> Iterator for RefPathsIter<'f, D, L> {
+    type Item = (Vec<usize>, TreeRef<'f, D, L>);
+    fn next(&mut self) -> Option<(Vec<usize>, TreeRef<'f, D, L>)> {
+        let (path, id) = self.stack.pop()?;
+        let forest = self.forest.read_lock();
+        if !forest.is_leaf(id) {
+            for (i, child) in forest.children(id).iter().enumerate().rev() {
+                let mut child_path = path.clone();
+                child_path.push(i);
+                self.stack.push((child_path, *child));
+            }
+        }
+        Some((path, TreeRef {
+            forest: self.forest,
+            root: self.root,
+            id
+        }))
+    }
+}
@@ -0,0 +1,100 @@
+use std::cell::Ref;
+
+use crate::forest::{Id, RawForest};
+use crate::tree::{Forest, Bookmark};
+use crate::tree_ref::TreeRef;
+
+
+/// A read-only view of a forest.
+///
+/// It can be used to look up and navigate trees, but not to create or
+/// edit them: it only ever hands out [TreeRefs](struct.TreeRef.html).
+pub struct ForestView<'f, D, L> {
+    forest: &'f Forest<D, L>
+}
+
+impl<'f, D, L> Clone for ForestView<'f, D, L> {
+    fn clone(&self) -> ForestView<'f, D, L> {
+        *self
+    }
+}
+
+impl<'f, D, L> Copy for ForestView<'f, D, L> {}
+
+/// A single read lock on an entire forest, through which any number of
+/// its trees can be read at once. The data and leaves it hands out are
+/// plain references, which last as long as the session does.
+///
+/// While a session is alive, the forest cannot be edited: trying to do
+/// so will panic.
+pub struct ReadSession<'f, D, L> {
+    forest: Ref<'f, RawForest<D, L>>
+}
+
+impl<D, L> Forest<D, L> {
+    /// Obtain a read-only view of this forest.
+    pub fn view(&self) -> ForestView<'_, D, L> {
+        ForestView {
+            forest: self
+        }
+    }
+
+    /// Lock this forest for reading, until the session is dropped.
+    pub fn read_all(&self) -> ReadSession<'_, D, L> {
+        ReadSession {
+            forest: self.read_lock()
+        }
+    }
+}
+
+impl<'f, D, L> ReadSession<'f, D, L> {
+    /// Does the node `id` exist (i.e., has it not been deleted)?
+    pub fn is_valid(&self, id: Id) -> bool {
+        self.forest.is_valid(id)
+    }
+
+    /// Is the node `id` a leaf?
+    pub fn is_leaf(&self, id: Id) -> bool {
+        self.forest.is_leaf(id)
+    }
+
+    /// The data of the branch `id`. Panics if it is a leaf.
+    pub fn data(&self, id: Id) -> &D {
+        self.forest.data(id)
+    }
+
+    /// The value of the leaf `id`. Panics if it is a branch.
+    pub fn leaf(&self, id: Id) -> &L {
+        self.forest.leaf(id)
+    }
+
+    /// The children of the branch `id`. Panics if it is a leaf.
+    pub fn children(&self, id: Id) -> &[Id] {
+        self.forest.children(id)
+    }
+
+    /// The parent of the node `id`, unless it is a root.
+    pub fn parent(&self, id: Id) -> Option<Id> {
+        self.forest.parent(id)
+    }
+}
+
+impl<'f, D, L> ForestView<'f, D, L> {
+    /// Look up a node by its id. Returns `None` if the node has since
+    /// been deleted.
+    pub fn get(&self, id: Id) -> Option<TreeRef<'f, D, L>> {
+        self.forest.get(id)
+    }
+
+    /// Look up a bookmarked node. Returns `None` if the node has since
+    /// been deleted.
+    pub fn lookup_bookmark(&self, mark: Bookmark) -> Option<TreeRef<'f, D, L>> {
+        self.forest.get(mark.id)
+    }
+
+    /// Find nodes that have become unreachable due to a bug. (See
+    /// [`Forest::find_orphans`](struct.Forest.html#method.find_orphans).)
+    pub fn find_orphans(&self) -> Vec<Id> {
+        self.forest.find_orphans()
+    }
+}
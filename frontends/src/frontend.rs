@@ -32,7 +32,9 @@ pub trait Frontend: Sized {
     }
 
     /// Render a string with the given style at the given position.
-    /// No newlines allowed.
+    /// No newlines allowed. The text should not contain escape sequences
+    /// either: the style is applied separately, and must not take up any
+    /// columns.
     fn print_str(&mut self, text: &str, pos: Pos, style: Style) -> Result<(), Self::Error>;
 
     /// Render a character with the given style at the given position.
@@ -41,6 +43,11 @@ pub trait Frontend: Sized {
         self.print_str(&ch.to_string(), pos, style)
     }
 
+    /// Paint the background of the given style from `pos` to the right
+    /// edge of the screen, e.g. to extend a highlighted region past the
+    /// end of its text.
+    fn fill_to_width(&mut self, pos: Pos, style: Style) -> Result<(), Self::Error>;
+
     /// Clear the whole screen.
     fn clear(&mut self) -> Result<(), Self::Error>;
 
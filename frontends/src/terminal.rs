@@ -32,28 +32,6 @@ impl Terminal {
     fn write<T: Display>(&mut self, thing: T) -> Result<(), io::Error> {
         write!(self.stdout, "{}", thing)
     }
-
-    fn go_to(&mut self, pos: Pos) -> Result<(), io::Error> {
-        let (x, y) = pos_to_coords(pos);
-        self.write(cursor::Goto(x, y))
-    }
-
-    fn apply_style(&mut self, style: Style) -> Result<(), io::Error> {
-        if style.emph.bold {
-            self.write(Bold)?;
-        } else {
-            self.write(NoBold)?;
-        }
-
-        if style.emph.underlined {
-            self.write(Underline)?;
-        } else {
-            self.write(NoUnderline)?;
-        }
-
-        self.write(Fg(to_termion_rgb(self.color_theme.foreground(style))))?;
-        self.write(Bg(to_termion_rgb(self.color_theme.background(style))))
-    }
 }
 
 impl Frontend for Terminal {
@@ -74,9 +52,12 @@ impl Frontend for Terminal {
     }
 
     fn print_str(&mut self, text: &str, pos: Pos, style: Style) -> Result<(), io::Error> {
-        self.go_to(pos)?;
-        self.apply_style(style)?;
-        self.write(text)
+        write_str(&mut self.stdout, &self.color_theme, text, pos, style)
+    }
+
+    fn fill_to_width(&mut self, pos: Pos, style: Style) -> Result<(), io::Error> {
+        let width = self.size()?.col;
+        write_fill(&mut self.stdout, &self.color_theme, pos, style, width)
     }
 
     fn clear(&mut self) -> Result<(), io::Error> {
@@ -138,6 +119,59 @@ impl Drop for Terminal {
     }
 }
 
+/// Write `text` at `pos`, in the given style. The cursor is moved to
+/// `pos` explicitly, so the escape sequences used for styling never
+/// affect which column the text (or anything written after it) lands in.
+fn write_str<W: Write>(
+    out: &mut W,
+    theme: &ColorTheme,
+    text: &str,
+    pos: Pos,
+    style: Style,
+) -> Result<(), io::Error> {
+    debug_assert!(
+        !text.contains('\x1b'),
+        "escape sequence in printed text would throw off column positions"
+    );
+    let (x, y) = pos_to_coords(pos);
+    write!(out, "{}", cursor::Goto(x, y))?;
+    apply_style(out, theme, style)?;
+    write!(out, "{}", text)
+}
+
+/// Paint the background of `style` from `pos` to the right edge of a
+/// screen that is `width` characters wide.
+fn write_fill<W: Write>(
+    out: &mut W,
+    theme: &ColorTheme,
+    pos: Pos,
+    style: Style,
+    width: Col,
+) -> Result<(), io::Error> {
+    if pos.col >= width {
+        return Ok(());
+    }
+    let fill = " ".repeat((width - pos.col) as usize);
+    write_str(out, theme, &fill, pos, style)
+}
+
+fn apply_style<W: Write>(out: &mut W, theme: &ColorTheme, style: Style) -> Result<(), io::Error> {
+    if style.emph.bold {
+        write!(out, "{}", Bold)?;
+    } else {
+        write!(out, "{}", NoBold)?;
+    }
+
+    if style.emph.underlined {
+        write!(out, "{}", Underline)?;
+    } else {
+        write!(out, "{}", NoUnderline)?;
+    }
+
+    write!(out, "{}", Fg(to_termion_rgb(theme.foreground(style))))?;
+    write!(out, "{}", Bg(to_termion_rgb(theme.background(style))))
+}
+
 /// Convert the native synless Rgb type into the termion one. They're both
 /// defined in different crates, so we can't impl From/Into.
 fn to_termion_rgb(synless_rgb: Rgb) -> TermionRgb {
@@ -164,3 +198,73 @@ fn size_to_pos(x: u16, y: u16) -> Pos {
         row: y as Row,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty::Shade;
+
+    #[test]
+    fn test_fill_to_width() {
+        let theme = ColorTheme::default_dark();
+        let style = Style {
+            shade: Shade(0),
+            ..Style::plain()
+        };
+        let bg = format!("{}", Bg(to_termion_rgb(theme.background(style))));
+        let pos = Pos { row: 2, col: 3 };
+
+        let mut out = vec![];
+        write_str(&mut out, &theme, "abc", pos, style).unwrap();
+        write_fill(&mut out, &theme, Pos { row: 2, col: 6 }, style, 10).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let (text, fill) = out.split_at(out.find("abc").unwrap() + 3);
+        assert!(text.contains(&bg));
+        assert!(fill.contains(&format!("{}", cursor::Goto(7, 3))));
+        assert!(fill.contains(&bg));
+        assert!(fill.ends_with(&format!("{}    ", bg)));
+
+        let mut out = vec![];
+        write_fill(&mut out, &theme, Pos { row: 2, col: 10 }, style, 10).unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_styles_take_no_columns() {
+        // Remove `ESC [ ... letter` sequences, leaving the visible text.
+        fn strip_escapes(s: &str) -> String {
+            let mut visible = String::new();
+            let mut chars = s.chars();
+            while let Some(ch) = chars.next() {
+                if ch == '\x1b' {
+                    chars.by_ref().find(|ch| ch.is_ascii_alphabetic());
+                } else {
+                    visible.push(ch);
+                }
+            }
+            visible
+        }
+
+        let theme = ColorTheme::default_dark();
+        let plain = Style::plain();
+        let fancy = Style {
+            shade: Shade(1),
+            emph: pretty::Emph::underlined(),
+            ..Style::plain()
+        };
+        let mut plain_out = vec![];
+        let mut fancy_out = vec![];
+        for (out, style) in &mut [(&mut plain_out, plain), (&mut fancy_out, fancy)] {
+            write_str(*out, &theme, "abc", Pos { row: 0, col: 0 }, *style).unwrap();
+            write_str(*out, &theme, "de", Pos { row: 0, col: 3 }, *style).unwrap();
+        }
+        let plain_out = String::from_utf8(plain_out).unwrap();
+        let fancy_out = String::from_utf8(fancy_out).unwrap();
+        assert_ne!(plain_out, fancy_out);
+        assert_eq!(strip_escapes(&plain_out), "abcde");
+        assert_eq!(strip_escapes(&fancy_out), "abcde");
+        // Each string is positioned explicitly, rather than relying on the
+        // cursor having advanced past the (escape sequence laden) previous one.
+        assert!(fancy_out.contains(&format!("{}", cursor::Goto(4, 1))));
+    }
+}
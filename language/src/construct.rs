@@ -2,6 +2,9 @@
 // TODO: use or remove commented code
 
 use lazy_static::lazy_static;
+use pretty::Notation;
+
+use crate::error::NotationError;
 
 pub type ConstructName = String;
 pub type Sort = String; // "Any" is special
@@ -16,31 +19,80 @@ pub struct Construct {
     pub name:  ConstructName,
     pub sort:  Sort,
     pub arity: Arity,
-    pub key:   char
+    pub key:   char,
+    pub role:  StyleRole
 }
 
 impl Construct {
-    pub fn new(name: &str, sort: &str, arity: Arity, key: char) -> Construct {
+    pub fn new(name: &str, sort: &str, arity: Arity, key: char, role: StyleRole) -> Construct {
         Construct{
             name: name.to_string(),
             sort: sort.to_string(),
             arity: arity,
-            key: key
+            key: key,
+            role
+        }
+    }
+
+    /// Can a node of this construct that has `current_children` children
+    /// be given another one? Fixed arity (and mixed) constructs can only
+    /// grow until they have their full number of children, extendable
+    /// constructs can always grow, and texty constructs have no children.
+    pub fn can_grow(&self, current_children: usize) -> bool {
+        match &self.arity {
+            Arity::Text => false,
+            Arity::Mixed { fixed, .. } => current_children < fixed.len(),
+            Arity::Forest(sorts, None) => current_children < sorts.len(),
+            Arity::Forest(_, Some(_)) => true
+        }
+    }
+
+    /// Check that `notation` could display nodes of this construct: it
+    /// only displays text if the construct has text, and only displays
+    /// children (by index) that the construct always has.
+    pub fn validate_notation(&self, notation: &Notation) -> Result<(), NotationError> {
+        let (num_fixed, has_text) = match &self.arity {
+            Arity::Text => (0, true),
+            Arity::Mixed { fixed, text } => (fixed.len(), *text),
+            Arity::Forest(sorts, _) => (sorts.len(), false)
+        };
+        if !has_text && notation.uses_text() {
+            return Err(NotationError::UnexpectedText(self.name.clone()));
+        }
+        match notation.child_indices().into_iter().find(|&index| index >= num_fixed) {
+            Some(index) => Err(NotationError::NoSuchChild { construct: self.name.clone(), index }),
+            None => Ok(())
         }
     }
 }
 
+/// What kind of syntax a construct is, for the purpose of picking its
+/// default style. A [`Theme`](type.Theme.html) says what style to use
+/// for each role.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StyleRole {
+    Plain,
+    Keyword,
+    Punctuation,
+    Identifier,
+    String
+}
+
 #[derive(Debug)]
 pub enum Arity {
     Text,
-    Mixed(Sort),
+    /// A fixed sequence of children, with the given sorts, plus text if
+    /// `text` is true (e.g. a template string with interpolations).
+    /// Documents do not store the text of mixed nodes yet, so they start
+    /// out with only their children.
+    Mixed { fixed: Vec<Sort>, text: bool },
     Forest(Vec<Sort>, Option<Sort>) // if Some, rest of children have this sort
 }
 
 lazy_static! {
     /// A hole in the document, for when your program is incomplete.
     pub static ref HOLE: Construct =
-        Construct::new("?", "Any", Arity::Forest(vec!(), None), '?');
+        Construct::new("?", "Any", Arity::Forest(vec!(), None), '?', StyleRole::Plain);
 }
 
 /*
@@ -64,3 +116,56 @@ lazy_static! {
     };
 }
 */
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty::{child, literal, map_text, text, Style};
+
+    fn construct(arity: Arity) -> Construct {
+        Construct::new("c", "Expr", arity, 'c', StyleRole::Plain)
+    }
+
+    #[test]
+    fn test_can_grow() {
+        let expr = || "Expr".to_string();
+        let fixed = construct(Arity::Forest(vec!(expr(), expr()), None));
+        assert!(fixed.can_grow(0));
+        assert!(fixed.can_grow(1));
+        assert!(!fixed.can_grow(2));
+
+        let extendable = construct(Arity::Forest(vec!(expr()), Some(expr())));
+        assert!(extendable.can_grow(0));
+        assert!(extendable.can_grow(1));
+        assert!(extendable.can_grow(100));
+
+        let mixed = construct(Arity::Mixed { fixed: vec!(expr()), text: true });
+        assert!(mixed.can_grow(0));
+        assert!(!mixed.can_grow(1));
+        assert!(!construct(Arity::Text).can_grow(0));
+        assert!(!HOLE.can_grow(0));
+    }
+
+    #[test]
+    fn test_validate_mixed_notation() {
+        let template = construct(Arity::Mixed { fixed: vec!("Expr".to_string()), text: true });
+        let note = text(Style::plain()) + literal("${", Style::plain()) + child(0)
+            + literal("}", Style::plain());
+        assert_eq!(template.validate_notation(&note), Ok(()));
+        assert_eq!(template.validate_notation(&(note.clone() + child(1))),
+                   Err(NotationError::NoSuchChild { construct: "c".to_string(), index: 1 }));
+
+        let untexty = construct(Arity::Mixed { fixed: vec!("Expr".to_string()), text: false });
+        assert_eq!(untexty.validate_notation(&child(0)), Ok(()));
+        assert_eq!(untexty.validate_notation(&note),
+                   Err(NotationError::UnexpectedText("c".to_string())));
+
+        let texty = construct(Arity::Text);
+        assert_eq!(texty.validate_notation(&text(Style::plain())), Ok(()));
+        assert!(texty.validate_notation(&child(0)).is_err());
+        let plus = construct(Arity::Forest(vec!("Expr".to_string(), "Expr".to_string()), None));
+        assert_eq!(plus.validate_notation(&(child(0) ^ (child(1) | child(0)))), Ok(()));
+        assert!(plus.validate_notation(&map_text(Style::plain(), str::to_uppercase)).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+//! Documents: trees of constructs.
+
+use forest::{Forest, Tree};
+
+use crate::construct::{Arity, ConstructName, Sort, HOLE};
+use crate::error::BuildError;
+use crate::language::Language;
+
+/// The forest that documents live in. Each branch node holds the name
+/// of its construct. Each leaf node holds the name of its (texty)
+/// construct, together with its text.
+pub type DocForest = Forest<ConstructName, (ConstructName, String)>;
+
+/// A document, or a piece of one.
+pub type Doc = Tree<ConstructName, (ConstructName, String)>;
+
+impl Language {
+    /// Construct a new, empty document, ready for editing. It consists of
+    /// the language's root construct (see `set_root`), with holes for all
+    /// of its children (or empty text, if it is texty). If the language
+    /// has no root construct, the document is a single hole.
+    pub fn empty_document(&self, forest: &DocForest) -> Doc {
+        let construct = match self.root() {
+            Some(construct) => construct,
+            None => return new_hole(forest)
+        };
+        match &construct.arity {
+            Arity::Text => {
+                forest.new_leaf((construct.name.clone(), String::new()))
+            }
+            Arity::Mixed { fixed: sorts, .. } | Arity::Forest(sorts, _) => {
+                let holes = sorts.iter().map(|_| new_hole(forest)).collect();
+                forest.new_branch(construct.name.clone(), holes)
+            }
+        }
+    }
+
+    /// Build a node of the given (non-texty) construct, after checking
+    /// that it has the right number of children for the construct's
+    /// arity, and that each child's construct has the sort expected in
+    /// its position. Holes may go anywhere. If the check fails, the
+    /// children are dropped.
+    pub fn make_node(&self, forest: &DocForest, construct: &ConstructName, children: Vec<Doc>)
+                     -> Result<Doc, BuildError>
+    {
+        let con = match self.get_construct(construct) {
+            Some(con) => con,
+            None => return Err(BuildError::UnknownConstruct(construct.clone()))
+        };
+        let (fixed, rest): (&[Sort], Option<&Sort>) = match &con.arity {
+            Arity::Text => return Err(BuildError::Texty(construct.clone())),
+            Arity::Mixed { fixed, .. } => (fixed, None),
+            Arity::Forest(sorts, rest) => (sorts, rest.as_ref())
+        };
+        let found = children.len();
+        if found < fixed.len() {
+            return Err(BuildError::TooFewChildren {
+                construct: construct.clone(), expected: fixed.len(), found
+            });
+        }
+        if rest.is_none() && found > fixed.len() {
+            return Err(BuildError::TooManyChildren {
+                construct: construct.clone(), expected: fixed.len(), found
+            });
+        }
+        for (index, child) in children.iter().enumerate() {
+            let expected = fixed.get(index).or(rest).expect("make_node: arity already checked");
+            let name = if child.is_leaf() {
+                child.leaf().0.clone()
+            } else {
+                child.data().clone()
+            };
+            if name == HOLE.name || expected == "Any" {
+                continue;
+            }
+            let sort = match self.get_construct(&name) {
+                Some(child_con) => &child_con.sort,
+                None => return Err(BuildError::UnknownConstruct(name))
+            };
+            if sort != expected {
+                return Err(BuildError::WrongSort {
+                    construct: construct.clone(), index, expected: expected.clone(), found: name
+                });
+            }
+        }
+        Ok(forest.new_branch(construct.clone(), children))
+    }
+}
+
+/// Construct a hole, to stand in for a part of the document that has
+/// not been written yet.
+pub fn new_hole(forest: &DocForest) -> Doc {
+    forest.new_branch(HOLE.name.clone(), vec!())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Construct, StyleRole};
+
+    fn example_language() -> Language {
+        let mut language = Language::new("TestLang");
+        let arity = Arity::Forest(vec!("Expr".to_string(),
+                                       "Expr".to_string()),
+                                  None);
+        language.add(Construct::new("plus", "Expr", arity, 'p', StyleRole::Punctuation));
+        language.add(Construct::new("id", "Expr", Arity::Text, 'i', StyleRole::Identifier));
+        let arity = Arity::Forest(vec!("Expr".to_string()), Some("Stmt".to_string()));
+        language.add(Construct::new("func", "Stmt", arity, 'f', StyleRole::Keyword));
+        language
+    }
+
+    #[test]
+    fn test_make_node() {
+        let forest = DocForest::new();
+        let language = example_language();
+        let name = |s: &str| s.to_string();
+        let id = |s: &str| forest.new_leaf((name("id"), s.to_string()));
+
+        let plus = language.make_node(&forest, &name("plus"), vec!(id("a"), new_hole(&forest)));
+        let plus = plus.unwrap();
+        assert_eq!(*plus.data(), "plus");
+        assert_eq!(plus.num_children(), 2);
+
+        assert_eq!(language.make_node(&forest, &name("plus"), vec!(id("a"))).err(),
+                   Some(BuildError::TooFewChildren {
+                       construct: name("plus"), expected: 2, found: 1
+                   }));
+        assert_eq!(language.make_node(&forest, &name("plus"), vec!(id("a"), id("b"), id("c")))
+                   .err(),
+                   Some(BuildError::TooManyChildren {
+                       construct: name("plus"), expected: 2, found: 3
+                   }));
+        assert_eq!(language.make_node(&forest, &name("id"), vec!()).err(),
+                   Some(BuildError::Texty(name("id"))));
+        assert_eq!(language.make_node(&forest, &name("times"), vec!()).err(),
+                   Some(BuildError::UnknownConstruct(name("times"))));
+
+        // A func takes an expression, then any number of statements.
+        let inner = language.make_node(&forest, &name("func"), vec!(id("f"))).unwrap();
+        let func = language.make_node(&forest, &name("func"),
+                                      vec!(plus, inner, new_hole(&forest))).unwrap();
+        assert_eq!(func.num_children(), 3);
+        let err = language.make_node(&forest, &name("func"), vec!(id("g"), id("x")))
+            .err().unwrap();
+        assert_eq!(err, BuildError::WrongSort {
+            construct: name("func"), index: 1, expected: name("Stmt"), found: name("id")
+        });
+        assert_eq!(format!("{}", err), "child 1 of func should be a Stmt, but is a id");
+        let err = language.make_node(&forest, &name("plus"), vec!(func, id("b"))).err().unwrap();
+        assert_eq!(err, BuildError::WrongSort {
+            construct: name("plus"), index: 0, expected: name("Expr"), found: name("func")
+        });
+    }
+
+    #[test]
+    fn test_empty_document() {
+        let forest = DocForest::new();
+        let mut language = example_language();
+        let doc = language.empty_document(&forest);
+        assert_eq!(*doc.data(), "?");
+        assert_eq!(doc.num_children(), 0);
+
+        language.set_root("plus");
+        let doc = language.empty_document(&forest);
+        assert_eq!(*doc.data(), "plus");
+        let children: Vec<ConstructName> = doc.borrow()
+            .children()
+            .map(|child| child.data().clone())
+            .collect();
+        assert_eq!(children, vec!("?", "?"));
+
+        language.set_root("id");
+        let doc = language.empty_document(&forest);
+        assert!(doc.is_leaf());
+        assert_eq!(*doc.leaf(), ("id".to_string(), "".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected="Could not find construct")]
+    fn test_set_missing_root() {
+        let mut language = example_language();
+        language.set_root("times");
+    }
+}
@@ -0,0 +1,67 @@
+use std::fmt;
+
+use crate::construct::{ConstructName, Sort};
+
+
+/// The ways in which building a node with
+/// [`Language::make_node`](struct.Language.html#method.make_node) can fail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// The construct (or the construct of one of the children) is not in
+    /// the language.
+    UnknownConstruct(ConstructName),
+    /// The construct is texty, so its nodes are leaves, not branches.
+    Texty(ConstructName),
+    /// The construct needs at least `expected` children, but was given
+    /// `found`.
+    TooFewChildren { construct: ConstructName, expected: usize, found: usize },
+    /// The construct takes at most `expected` children, but was given
+    /// `found`.
+    TooManyChildren { construct: ConstructName, expected: usize, found: usize },
+    /// The `index`th child is a `found`, but the construct expects a
+    /// child of sort `expected` there.
+    WrongSort { construct: ConstructName, index: usize, expected: Sort, found: ConstructName }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::UnknownConstruct(name) =>
+                write!(f, "no construct named {} in language", name),
+            BuildError::Texty(name) =>
+                write!(f, "{} is texty, so it has no children", name),
+            BuildError::TooFewChildren { construct, expected, found } =>
+                write!(f, "{} needs at least {} children, but was given {}",
+                       construct, expected, found),
+            BuildError::TooManyChildren { construct, expected, found } =>
+                write!(f, "{} takes at most {} children, but was given {}",
+                       construct, expected, found),
+            BuildError::WrongSort { construct, index, expected, found } =>
+                write!(f, "child {} of {} should be a {}, but is a {}",
+                       index, construct, expected, found)
+        }
+    }
+}
+
+/// The ways in which a notation can be invalid for a construct (see
+/// [`Construct::validate_notation`](struct.Construct.html#method.validate_notation)).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NotationError {
+    /// The notation displays text, but the construct has none.
+    UnexpectedText(ConstructName),
+    /// The notation displays the `index`th child, but the construct does
+    /// not always have one.
+    NoSuchChild { construct: ConstructName, index: usize }
+}
+
+impl fmt::Display for NotationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NotationError::UnexpectedText(name) =>
+                write!(f, "the notation for {} displays text, but it has none", name),
+            NotationError::NoSuchChild { construct, index } =>
+                write!(f, "the notation for {} displays child {}, which it may not have",
+                       construct, index)
+        }
+    }
+}
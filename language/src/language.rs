@@ -5,7 +5,7 @@
 use std::collections::HashMap;
 use std::iter::Iterator;
 
-use crate::construct::{ConstructName, Sort, Construct};
+use crate::construct::{ConstructName, Sort, Construct, Arity};
 
 pub type LanguageName = String;
 
@@ -15,7 +15,8 @@ pub struct Language {
     name:       LanguageName,
     constructs: HashMap<ConstructName, Construct>,
     sorts:      HashMap<Sort, Vec<ConstructName>>,
-    keymap:     HashMap<char, ConstructName>
+    keymap:     HashMap<char, ConstructName>,
+    root:       Option<ConstructName>
 }
 
 impl Language {
@@ -25,7 +26,8 @@ impl Language {
             name:       name.to_string(),
             sorts:      HashMap::new(),
             constructs: HashMap::new(),
-            keymap:     HashMap::new()
+            keymap:     HashMap::new(),
+            root:       None
         }
     }
 
@@ -60,9 +62,59 @@ impl Language {
         }
     }
 
+    /// Like `lookup_construct`, but returns `None` instead of panicking
+    /// if there is no such construct.
+    pub fn get_construct(&self, construct_name: &str) -> Option<&Construct> {
+        self.constructs.get(construct_name)
+    }
+
     pub fn constructs(&self) -> impl Iterator<Item=&Construct> {
         self.constructs.values()
     }
+
+    /// The constructs that could be placed in the `slot`th child of a
+    /// `parent` construct: those whose sort matches the sort the slot
+    /// expects. Sorted by name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `parent` is not in the language.
+    pub fn valid_constructs_at(&self, parent: &ConstructName, slot: usize) -> Vec<&Construct> {
+        let expected = match &self.lookup_construct(parent).arity {
+            Arity::Text => None,
+            Arity::Mixed { fixed, .. } => fixed.get(slot),
+            Arity::Forest(sorts, rest) => sorts.get(slot).or(rest.as_ref())
+        };
+        let mut constructs: Vec<&Construct> = match expected {
+            None => vec!(),
+            Some(sort) if sort == "Any" => self.constructs().collect(),
+            Some(sort) => match self.sorts.get(sort) {
+                None => vec!(),
+                Some(names) => names.iter().map(|name| self.lookup_construct(name)).collect()
+            }
+        };
+        constructs.sort_by(|a, b| a.name.cmp(&b.name));
+        constructs
+    }
+
+    /// Set the construct that new documents in this language start from.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the construct is not in the language.
+    pub fn set_root(&mut self, construct_name: &str) {
+        let construct = self.lookup_construct(construct_name);
+        self.root = Some(construct.name.clone());
+    }
+
+    /// The construct that new documents in this language start from, if
+    /// one has been set.
+    pub fn root(&self) -> Option<&Construct> {
+        match &self.root {
+            Some(name) => Some(self.lookup_construct(name)),
+            None => None
+        }
+    }
 }
 
 //#[cfg(test)]
@@ -70,7 +122,7 @@ impl Language {
 
 #[cfg(test)]
 mod example {
-    use crate::Arity;
+    use crate::StyleRole;
     use super::*;
 
     /// An example language for testing.
@@ -81,10 +133,33 @@ mod example {
         let arity = Arity::Forest(vec!("Expr".to_string(),
                                        "Expr".to_string()),
                                   None);
-        let construct = Construct::new("plus", "Expr", arity, 'p');
+        let construct = Construct::new("plus", "Expr", arity, 'p', StyleRole::Punctuation);
+        language.add(construct);
+
+        let construct = Construct::new("id", "Expr", Arity::Text, 'i', StyleRole::Identifier);
+        language.add(construct);
+
+        let arity = Arity::Forest(vec!("Expr".to_string()), Some("Stmt".to_string()));
+        let construct = Construct::new("block", "Stmt", arity, 'b', StyleRole::Keyword);
         language.add(construct);
 
         language
     }
 
+    fn names(constructs: Vec<&Construct>) -> Vec<&str> {
+        constructs.iter().map(|construct| construct.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_valid_constructs_at() {
+        let language = example_language();
+        let plus = "plus".to_string();
+        let block = "block".to_string();
+        assert_eq!(names(language.valid_constructs_at(&plus, 0)), vec!("id", "plus"));
+        assert_eq!(names(language.valid_constructs_at(&plus, 1)), vec!("id", "plus"));
+        assert!(language.valid_constructs_at(&plus, 2).is_empty());
+        assert_eq!(names(language.valid_constructs_at(&block, 0)), vec!("id", "plus"));
+        assert_eq!(names(language.valid_constructs_at(&block, 5)), vec!("block"));
+        assert!(language.valid_constructs_at(&"id".to_string(), 0).is_empty());
+    }
 }
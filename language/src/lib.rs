@@ -1,5 +1,13 @@
 mod construct;
+mod document;
+mod error;
 mod language;
+mod notationset;
+mod render;
 
-pub use self::construct::{Construct, ConstructName, Sort, Arity};
+pub use self::construct::{Construct, ConstructName, Sort, Arity, StyleRole, HOLE};
+pub use self::document::{Doc, DocForest, new_hole};
+pub use self::error::{BuildError, NotationError};
 pub use self::language::{Language, LanguageName};
+pub use self::notationset::{NotationSet, Theme};
+pub use self::render::{render_tree, render_tree_with, RenderMode};
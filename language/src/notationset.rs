@@ -1,12 +1,20 @@
 use std::collections::HashMap;
 
-use pretty::Notation;
-use crate::language::{ConstructName, Language, LanguageName};
+use pretty::{Notation, Style};
+use crate::construct::{Construct, ConstructName, StyleRole};
+use crate::language::{Language, LanguageName};
 
 
+/// The style to use for each `StyleRole`. Roles that are missing from
+/// the theme are displayed with `Style::plain()`.
+pub type Theme = HashMap<StyleRole, Style>;
+
+/// A notation for each construct in a language.
 pub struct NotationSet {
     name: LanguageName,
-    notations: HashMap<ConstructName, Notation>
+    notations: HashMap<ConstructName, Notation>,
+    // The role of each construct that is in the language.
+    roles: HashMap<ConstructName, StyleRole>
 }
 
 impl NotationSet {
@@ -16,25 +24,85 @@ impl NotationSet {
                -> NotationSet
     {
         let mut map = HashMap::new();
+        let mut roles = HashMap::new();
         for (construct, notation) in notations {
+            if let Some(con) = language.get_construct(&construct) {
+                roles.insert(construct.clone(), con.role);
+            }
             map.insert(construct, notation);
         }
         NotationSet {
             name: language.name().to_string(),
-            notations: map
+            notations: map,
+            roles
         }
     }
-}
 
+    /// Construct a notation for every construct in the language, by
+    /// calling `build` with the construct and the style that `theme`
+    /// assigns to its role. Building again with a different theme
+    /// recolors everything.
+    pub fn themed<F>(language: &Language, theme: &Theme, build: F) -> NotationSet
+        where F: Fn(&Construct, Style) -> Notation
+    {
+        let notations = language.constructs().map(|construct| {
+            let style = theme.get(&construct.role).cloned().unwrap_or_else(Style::plain);
+            (construct.name.clone(), build(construct, style))
+        }).collect();
+        NotationSet::new(language, notations)
+    }
+
+    /// A copy of this notation set, recolored from the theme `from` (that
+    /// it was built with) to the theme `to`. Each style in a notation that
+    /// `from` assigns to a role is replaced by the style that `to` assigns
+    /// to that role. If `from` assigns the style to several roles, the
+    /// construct's own role wins; if it is not one of them, the style is
+    /// ambiguous. Styles that are ambiguous, or that `from` does not
+    /// assign to any role, or whose role is missing from `to`, are left
+    /// as they are.
+    pub fn restyle(&self, from: &Theme, to: &Theme) -> NotationSet {
+        let notations = self.notations.iter().map(|(construct, notation)| {
+            let own_role = self.roles.get(construct)
+                .filter(|role| from.contains_key(role));
+            let restyle = |style: Style| {
+                let mut roles = from.iter()
+                    .filter(|(_, from_style)| **from_style == style)
+                    .map(|(role, _)| role);
+                let role = match own_role {
+                    Some(role) if from[role] == style => Some(role),
+                    _ => match (roles.next(), roles.next()) {
+                        (Some(role), None) => Some(role),
+                        _ => None
+                    }
+                };
+                role.and_then(|role| to.get(role)).cloned().unwrap_or(style)
+            };
+            (construct.clone(), notation.map_styles(&restyle))
+        }).collect();
+        NotationSet {
+            name: self.name.clone(),
+            notations,
+            roles: self.roles.clone()
+        }
+    }
+
+    /// The name of the language this is a notation for.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The notation for the given construct, if there is one.
+    pub fn lookup(&self, construct_name: &str) -> Option<&Notation> {
+        self.notations.get(construct_name)
+    }
+}
 
-#[cfg(test)]
-use self::example::*;
 
 #[cfg(test)]
-mod example {
+pub(crate) mod example {
     use super::*;
     use pretty::*;
-    use language::{Language, Construct, Arity};
+    use crate::{Language, Construct, Arity, StyleRole};
 
     fn punct(s: &str) -> Notation {
         literal(s, Style::color(Color::Base0A))
@@ -44,8 +112,9 @@ mod example {
         literal(s, Style::color(Color::Base0B))
     }
 
+    // `Shade::background()` is what used to be called `Shade::black()`.
     fn txt() -> Notation {
-        text(Style::new(Color::Base0D, Emph::underlined(), Shade::black(), false))
+        text(Style::new(Color::Base0D, Emph::underlined(), Shade::background(), false))
     }
 
     /// An example language for testing.
@@ -55,15 +124,49 @@ mod example {
         let arity = Arity::Forest(vec!("Expr".to_string(),
                                        "Expr".to_string()),
                                   None);
-        let construct = Construct::new("plus", "Expr", arity, 'p');
+        let construct = Construct::new("plus", "Expr", arity, 'p', StyleRole::Punctuation);
         language.add(construct);
+        // The second option puts the operator on a new line (it used to be
+        // written with `flush`, which vertical concatenation replaced).
         let plus_notation =
-            child(0) + punct(" + ") + child(1)
-            | flush(child(0)) + punct("+ ") + child(1);
+            (child(0) + punct(" + ") + child(1))
+            | (child(0) ^ (punct("+ ") + child(1)));
+
+        let arity = Arity::Forest(vec!("Expr".to_string(),
+                                       "Args".to_string(),
+                                       "Expr".to_string()),
+                                  None);
+        language.add(Construct::new("func", "Stmt", arity, 'f', StyleRole::Keyword));
+        let func_notation =
+            (word("func ") + child(0)
+             + punct("(") + child(1) + punct(") { ") + child(2) + punct(" }"))
+            | ((word("func ") + child(0) + punct("(") + child(1) + punct(") {"))
+               ^ (word("  ") + child(2))
+               ^ punct("}"));
+
+        let arity = Arity::Forest(vec!(), Some("Expr".to_string()));
+        language.add(Construct::new("args", "Args", arity, 'a', StyleRole::Plain));
+        let args_notation = repeat(Repeat{
+            empty:  empty(),
+            lone:   star(),
+            first:  star() + punct(", "),
+            middle: star() + punct(", "),
+            last:   star()
+        });
+
+        language.add(Construct::new("id", "Expr", Arity::Text, 'i', StyleRole::Identifier));
+        let id_notation = if_empty_text(txt() + punct("·"), txt());
+
+        language.add(Construct::new("strn", "Expr", Arity::Text, 's', StyleRole::String));
+        let strn_notation = punct("'") + txt() + punct("'");
 
         let notation = NotationSet::new(
             &language,
-            vec!(("plus".to_string(), plus_notation)));
+            vec!(("plus".to_string(), plus_notation),
+                 ("func".to_string(), func_notation),
+                 ("args".to_string(), args_notation),
+                 ("id".to_string(), id_notation),
+                 ("strn".to_string(), strn_notation)));
         (language, notation)
 /*
         let syn = repeat(Repeat{
@@ -147,3 +250,76 @@ mod example {
     }
 */
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::example::example_language;
+    use pretty::{child, literal, Color};
+
+    fn binop(_construct: &Construct, style: Style) -> Notation {
+        child(0) + literal(" + ", style) + child(1)
+    }
+
+    #[test]
+    fn test_themes() {
+        let (language, _) = example_language();
+        let mut light = Theme::new();
+        light.insert(StyleRole::Punctuation, Style::color(Color::Base0A));
+        let mut dark = Theme::new();
+        dark.insert(StyleRole::Punctuation, Style::color(Color::Base0E));
+        let light_notes = NotationSet::themed(&language, &light, binop);
+        let dark_notes = NotationSet::themed(&language, &dark, binop);
+        let default_notes = NotationSet::themed(&language, &Theme::new(), binop);
+        let light_plus = format!("{:?}", light_notes.lookup("plus").unwrap());
+        let dark_plus = format!("{:?}", dark_notes.lookup("plus").unwrap());
+        let default_plus = format!("{:?}", default_notes.lookup("plus").unwrap());
+        assert!(light_plus.contains("Base0A"));
+        assert!(dark_plus.contains("Base0E"));
+        assert!(default_plus.contains("Base05"));
+        assert_ne!(light_plus, dark_plus);
+        assert!(light_notes.lookup("times").is_none());
+        assert_eq!(light_notes.name(), "TestLang");
+    }
+
+    #[test]
+    fn test_restyle() {
+        let (_, notations) = example_language();
+        let (yellow, green) = (Style::color(Color::Base0A), Style::color(Color::Base0B));
+        let red = Style::color(Color::Base08);
+        let mut from = Theme::new();
+        from.insert(StyleRole::Punctuation, yellow);
+        from.insert(StyleRole::Keyword, green);
+        let mut to = Theme::new();
+        to.insert(StyleRole::Punctuation, red);
+        let restyled = notations.restyle(&from, &to);
+
+        let plus = (child(0) + literal(" + ", red) + child(1))
+            | (child(0) ^ (literal("+ ", red) + child(1)));
+        assert_eq!(restyled.lookup("plus"), Some(&plus));
+        assert_ne!(notations.lookup("plus"), Some(&plus));
+        // Each style is mapped by its own role, not the construct's, and
+        // roles missing from `to` keep their style.
+        let func = format!("{:?}", restyled.lookup("func").unwrap());
+        assert!(func.contains("Base08"));
+        assert!(func.contains("Base0B"));
+        assert!(!func.contains("Base0A"));
+        // Styles that aren't in `from` are left alone.
+        let id = format!("{:?}", notations.lookup("id").unwrap());
+        assert_eq!(format!("{:?}", restyled.lookup("id").unwrap()),
+                   id.replace("Base0A", "Base08"));
+        assert!(id.contains("Base0D"));
+        assert_eq!(restyled.name(), "TestLang");
+
+        // A style shared by several roles is mapped by the construct's
+        // own role, if it's one of them, and otherwise left alone.
+        from.insert(StyleRole::String, yellow);
+        to.insert(StyleRole::String, green);
+        let restyled = notations.restyle(&from, &to);
+        let strn = format!("{:?}", notations.lookup("strn").unwrap());
+        assert_eq!(format!("{:?}", restyled.lookup("strn").unwrap()),
+                   strn.replace("Base0A", "Base0B"));
+        assert_eq!(restyled.lookup("plus"), Some(&plus));
+        assert_eq!(restyled.lookup("id"), notations.lookup("id"));
+    }
+}
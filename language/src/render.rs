@@ -0,0 +1,186 @@
+//! Rendering documents as plain text.
+
+use std::borrow::Cow;
+
+use forest::TreeRef;
+use pretty::{Bounds, BoundsCache, Notation, PlainText, PrettyDocument, Style, literal};
+
+use crate::construct::ConstructName;
+use crate::document::Doc;
+use crate::notationset::NotationSet;
+
+
+/// How `render_tree_with` displays a document.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Display each node with its construct's notation.
+    Plain,
+    /// For debugging notations: wrap each node in `⟨construct: … ⟩`, to
+    /// show which construct displayed what. The markers take up space,
+    /// so the document may be laid out differently than in `Plain` mode.
+    DebugConstructs
+}
+
+/// Pretty-print `doc` at the given width, displaying each node with its
+/// construct's notation from `notations`.
+///
+/// # Panics
+///
+/// Panics if `notations` has no notation for one of the document's
+/// constructs.
+pub fn render_tree(doc: &Doc, notations: &NotationSet, width: usize) -> String {
+    render_tree_with(doc, notations, width, RenderMode::Plain)
+}
+
+/// Like `render_tree`, but in the given mode.
+pub fn render_tree_with(doc: &Doc, notations: &NotationSet, width: usize, mode: RenderMode)
+                        -> String
+{
+    let mut nodes = vec!();
+    flatten(doc.borrow(), None, notations, mode, &mut nodes);
+    let mut screen = PlainText::new(width);
+    let bounds = BoundsCache::new();
+    RenderRef { nodes: &nodes, bounds: &bounds, index: 0 }
+        .pretty_print(&mut screen)
+        .expect("render_tree: failed to render");
+    format!("{}", screen)
+}
+
+// A copy of a document node, with its notation looked up.
+struct RenderNode<'n> {
+    notation: Cow<'n, Notation>,
+    text: Option<String>,
+    parent: Option<usize>,
+    children: Vec<usize>
+}
+
+// A node, by its index in `nodes`. Its Bounds are cached in `bounds`,
+// by the same index.
+#[derive(Clone)]
+struct RenderRef<'a, 'n> {
+    nodes: &'a [RenderNode<'n>],
+    bounds: &'a BoundsCache<usize>,
+    index: usize
+}
+
+// Copy the subtree at `tree` onto the end of `nodes`, in preorder.
+fn flatten<'n>(tree: TreeRef<ConstructName, (ConstructName, String)>, parent: Option<usize>,
+               notations: &'n NotationSet, mode: RenderMode, nodes: &mut Vec<RenderNode<'n>>)
+{
+    let (construct, text) = if tree.is_leaf() {
+        let (construct, text) = tree.leaf().clone();
+        (construct, Some(text))
+    } else {
+        (tree.data().clone(), None)
+    };
+    let notation = notations.lookup(&construct).unwrap_or_else(|| {
+        panic!("render_tree: no notation for construct {}", construct)
+    });
+    let notation = match mode {
+        RenderMode::Plain => Cow::Borrowed(notation),
+        RenderMode::DebugConstructs => Cow::Owned(
+            literal(&format!("⟨{}: ", construct), Style::plain())
+                + notation.clone()
+                + literal(" ⟩", Style::plain()))
+    };
+    let index = nodes.len();
+    nodes.push(RenderNode {
+        notation,
+        text,
+        parent,
+        children: vec!()
+    });
+    if !tree.is_leaf() {
+        for child in tree.children() {
+            let child_index = nodes.len();
+            nodes[index].children.push(child_index);
+            flatten(child, Some(index), notations, mode, nodes);
+        }
+    }
+}
+
+impl<'a, 'n> RenderRef<'a, 'n> {
+    fn node(&self) -> &'a RenderNode<'n> {
+        &self.nodes[self.index]
+    }
+
+    fn at(&self, index: usize) -> RenderRef<'a, 'n> {
+        RenderRef { nodes: self.nodes, bounds: self.bounds, index }
+    }
+}
+
+impl<'a, 'n> PrettyDocument for RenderRef<'a, 'n> {
+    fn arity(&self) -> usize {
+        self.node().children.len()
+    }
+
+    fn parent(&self) -> Option<Self> {
+        self.node().parent.map(|index| self.at(index))
+    }
+
+    fn child(&self, i: usize) -> Self {
+        self.at(self.node().children[i])
+    }
+
+    fn children(&self) -> Vec<Self> {
+        self.node().children.iter().map(|&index| self.at(index)).collect()
+    }
+
+    fn notation(&self) -> &Notation {
+        &self.node().notation
+    }
+
+    fn text(&self) -> Option<&str> {
+        self.node().text.as_deref()
+    }
+
+    fn bounds(&self) -> Bounds {
+        self.bounds.get(&self.index).expect("render_tree: bounds not computed")
+    }
+
+    fn child_bounds_cache(&self) -> Vec<Bounds> {
+        self.bounds.get_all(&self.node().children)
+    }
+
+    fn is_dirty(&self) -> bool {
+        !self.bounds.contains(&self.index)
+    }
+
+    fn set_bounds(&self, bounds: Bounds) {
+        self.bounds.insert(self.index, bounds);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::DocForest;
+    use crate::notationset::example::example_language;
+
+    #[test]
+    fn test_render_tree() {
+        let (_, notations) = example_language();
+        let forest = DocForest::new();
+        let leaf = |construct: &str, text: &str| {
+            forest.new_leaf((construct.to_string(), text.to_string()))
+        };
+        let branch = |construct: &str, children| forest.new_branch(construct.to_string(), children);
+
+        let doc = branch("func", vec!(
+            leaf("id", "foo"),
+            branch("args", vec!(leaf("id", "abc"), leaf("id", "def"))),
+            branch("plus", vec!(leaf("strn", "abcdef"), leaf("strn", "abcdef")))));
+        assert_eq!(render_tree(&doc, &notations, 80),
+                   "func foo(abc, def) { 'abcdef' + 'abcdef' }");
+        assert_eq!(render_tree(&doc, &notations, 20),
+                   "func foo(abc, def) {\n  'abcdef'\n  + 'abcdef'\n}");
+
+        let debug = render_tree_with(&doc, &notations, 200, RenderMode::DebugConstructs);
+        assert_eq!(debug, "⟨func: func ⟨id: foo ⟩(⟨args: ⟨id: abc ⟩, ⟨id: def ⟩ ⟩) { \
+                           ⟨plus: ⟨strn: 'abcdef' ⟩ + ⟨strn: 'abcdef' ⟩ ⟩ } ⟩");
+        // The plain rendering is unaffected.
+        assert_eq!(render_tree(&doc, &notations, 80),
+                   "func foo(abc, def) { 'abcdef' + 'abcdef' }");
+    }
+}
@@ -1,6 +1,7 @@
 use std::fmt;
 
 use super::pos::{Row, Col, MAX_WIDTH};
+use super::width::{WidthFn, CharCount};
 
 
 
@@ -47,6 +48,26 @@ impl Bound {
         self.width > MAX_WIDTH
     }
 
+    /// The Bound of a piece of (possibly multi-line) text: it is as wide
+    /// as its longest line, and its indent is the length of its last line.
+    pub fn from_multiline(s: &str) -> Bound {
+        Bound::measure(s, &CharCount)
+    }
+
+    /// Like `from_multiline`, but measuring each line with `width_fn`.
+    pub fn measure(s: &str, width_fn: &dyn WidthFn) -> Bound {
+        // Measure each line once, without collecting them.
+        let mut lines = s.split('\n').map(|line| width_fn.width(line) as Col);
+        let first = lines.next().unwrap_or(0);
+        lines.fold(Bound { width: first, indent: first, height: 0 }, |bound, line| {
+            Bound {
+                width:  bound.width.max(line),
+                indent: line,
+                height: bound.height + 1
+            }
+        })
+    }
+
     /// A Bound that has the given width and is "infinitely" tall.
     pub fn infinite_scroll(width: Col) -> Bound {
         Bound{
@@ -69,10 +90,28 @@ impl Bound {
         }
         write!(f, "{}", ch.to_string().repeat(self.indent as usize))
     }
+
+    /// Like `debug_print`, but mark the indent column with a `|` at the
+    /// end of the last line.
+    pub(crate) fn debug_print_indent(&self, f: &mut fmt::Formatter, ch: char, indent: Col)
+                                     -> fmt::Result
+    {
+        self.debug_print(f, ch, indent)?;
+        if self.height > 30 {
+            return Ok(());
+        }
+        write!(f, "|")
+    }
 }
 
+/// `{:?}` draws the shape of the Bound with `*`s. `{:#?}` also marks
+/// its indent column (see `debug_print_indent`).
 impl fmt::Debug for Bound {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.debug_print(f, '*', 0)
+        if f.alternate() {
+            self.debug_print_indent(f, '*', 0)
+        } else {
+            self.debug_print(f, '*', 0)
+        }
     }
 }
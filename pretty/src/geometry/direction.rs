@@ -0,0 +1,44 @@
+use super::pos::{Pos, Col};
+
+
+/// Which way lines run across the screen. Documents are always laid out
+/// left-to-right; in `Rtl`, the columns are mirrored when displayed, so
+/// that each line starts at the right edge and indentation is measured
+/// from the right. (This does not do any bidi shaping.)
+///
+/// Only `PlainText` displays documents right-to-left. Everything else,
+/// including the positions in a `LayoutRegion` and the regions passed
+/// to a `PrettyScreen`, is always left-to-right.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Left-to-right.
+    Ltr,
+    /// Right-to-left.
+    Rtl
+}
+
+impl Direction {
+    /// On a screen of the given width, where to display the leftmost of
+    /// `len` characters that were laid out starting at `pos`. (In `Rtl`,
+    /// that is the last of them.) If some of them would stick out past
+    /// the left edge, this is the left edge; see `overhang`.
+    pub fn place(self, pos: Pos, len: Col, width: Col) -> Pos {
+        match self {
+            Direction::Ltr => pos,
+            Direction::Rtl => Pos {
+                row: pos.row,
+                col: width.saturating_sub(pos.col.saturating_add(len))
+            }
+        }
+    }
+
+    /// How many of the `len` characters laid out starting at `pos` would
+    /// stick out past the left edge of a screen of the given width, and
+    /// so should not be displayed. (In `Rtl`, those are the last of them.)
+    pub fn overhang(self, pos: Pos, len: Col, width: Col) -> Col {
+        match self {
+            Direction::Ltr => 0,
+            Direction::Rtl => pos.col.saturating_add(len).saturating_sub(width).min(len)
+        }
+    }
+}
@@ -3,7 +3,11 @@ mod range;
 mod rect;
 mod bound;
 mod region;
+mod width;
+mod direction;
 
 pub use self::pos::{Pos, Row, Col, MAX_WIDTH};
 pub use self::bound::Bound;
 pub use self::region::Region;
+pub use self::width::{WidthFn, CharCount};
+pub use self::direction::Direction;
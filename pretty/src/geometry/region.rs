@@ -1,3 +1,4 @@
+use std::cmp;
 use std::fmt;
 use std::ops::Add;
 
@@ -56,6 +57,51 @@ impl Region {
             || self.last_line().overlaps(other.last_line())
     }
 
+    /// Do the two regions share any character positions? (The same as
+    /// `overlaps`.)
+    pub fn intersects(&self, other: &Region) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    /// The character positions that lie on both regions, or `None` if
+    /// there are none. (The intersection of two regions is always itself
+    /// a region: every row starts at the same column, and only the last
+    /// row can be shorter than the rest.)
+    pub fn intersection(&self, other: &Region) -> Option<Region> {
+        let top = cmp::max(self.pos.row, other.pos.row);
+        let bottom = cmp::min(self.end().row, other.end().row);
+        if top > bottom {
+            return None;
+        }
+        let left = cmp::max(self.pos.col, other.pos.col);
+        let right = cmp::min(self.pos.col + self.width(), other.pos.col + other.width());
+        let last_right = |region: &Region| if region.end().row == bottom {
+            region.end().col
+        } else {
+            region.pos.col + region.width()
+        };
+        let last_right = cmp::min(last_right(self), last_right(other));
+        let bound = if top == bottom {
+            if last_right <= left {
+                return None;
+            }
+            Bound{ width: last_right - left, height: 0, indent: last_right - left }
+        } else {
+            if right <= left {
+                return None;
+            }
+            Bound{
+                width: right - left,
+                height: bottom - top,
+                indent: last_right.saturating_sub(left)
+            }
+        };
+        Some(Region{
+            pos: Pos{ row: top, col: left },
+            bound
+        })
+    }
+
     pub fn covers(&self, other: Region) -> bool {
         self.bounding_box().covers(other.body())
             && self.bounding_box().covers(other.last_line())
@@ -200,6 +246,56 @@ mod tests {
                    None);
     }
 
+    #[test]
+    fn test_region_contains() {
+        // Corners of the body
+        assert!(REGION.contains(Pos{ row: 2, col: 3 }));
+        assert!(REGION.contains(Pos{ row: 2, col: 6 }));
+        assert!(!REGION.contains(Pos{ row: 2, col: 7 }));
+        assert!(!REGION.contains(Pos{ row: 1, col: 3 }));
+        assert!(!REGION.contains(Pos{ row: 2, col: 2 }));
+        // The last line is only `indent` wide
+        assert!(REGION.contains(Pos{ row: 5, col: 4 }));
+        assert!(!REGION.contains(Pos{ row: 5, col: 5 }));
+        assert!(!REGION.contains(Pos{ row: 6, col: 3 }));
+    }
+
+    #[test]
+    fn test_region_intersection() {
+        // REGION2 ends on the same row as REGION, so both last lines count.
+        assert!(REGION.intersects(&REGION2));
+        assert_eq!(REGION.intersection(&REGION2), Some(Region{
+            pos: Pos{ row: 3, col: 4 },
+            bound: Bound{ width: 3, height: 2, indent: 1 }
+        }));
+        assert_eq!(REGION2.intersection(&REGION), REGION.intersection(&REGION2));
+        // A region inside of another
+        assert_eq!(REGION.intersection(&REGION3), Some(REGION3));
+        // A region that overlaps only with the body
+        let above = Region{
+            pos: Pos{ row: 0, col: 5 },
+            bound: Bound{ width: 5, height: 2, indent: 1 }
+        };
+        assert_eq!(REGION.intersection(&above), Some(Region{
+            pos: Pos{ row: 2, col: 5 },
+            bound: Bound{ width: 1, height: 0, indent: 1 }
+        }));
+        // Disjoint: to the right of the last line
+        let beside = Region::char_region(Pos{ row: 5, col: 5 });
+        assert!(!REGION.intersects(&beside));
+        assert_eq!(REGION.intersection(&beside), None);
+        assert_eq!(beside.intersection(&REGION), None);
+        // Disjoint: below
+        let below = Region::char_region(Pos{ row: 6, col: 3 });
+        assert_eq!(REGION.intersection(&below), None);
+        // Touching edges do not intersect
+        let right = Region{
+            pos: Pos{ row: 2, col: 7 },
+            bound: Bound{ width: 2, height: 1, indent: 2 }
+        };
+        assert!(!REGION.intersects(&right));
+    }
+
     #[test]
     fn test_regions() {
         assert_eq!(REGION.overlaps(REGION2), true);
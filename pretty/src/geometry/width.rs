@@ -0,0 +1,15 @@
+/// Measures how many columns a (single-line) string takes up when
+/// displayed. By default (`CharCount`), every character takes up one
+/// column; a host editor with its own fonts can supply its own.
+pub trait WidthFn {
+    fn width(&self, s: &str) -> usize;
+}
+
+/// Every character is one column wide.
+pub struct CharCount;
+
+impl WidthFn for CharCount {
+    fn width(&self, s: &str) -> usize {
+        s.chars().count()
+    }
+}
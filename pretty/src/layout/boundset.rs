@@ -1,77 +1,155 @@
 use std::iter;
 use std::fmt;
+use std::cmp::Reverse;
+
+use crate::geometry::{Bound, Col};
+use crate::notation::Weight;
 
-use crate::geometry::Bound;
-#[cfg(test)]
-use crate::geometry::Col;
 
+/// The most Bounds a BoundSet will hold. Beyond this, the tallest are
+/// discarded, to keep pathological notations (with many independent
+/// `Choice`s) from taking forever to lay out. The set then remembers
+/// that it was truncated (see `Bounds::is_truncated`).
+pub const MAX_BOUND_CANDIDATES: usize = 128;
 
-/// A set of Bounds. If one Bound is strictly smaller than another,
-/// only the smaller one will be kept.
-/// Each Bound may have some related data T.
+/// A set of Bounds. If one Bound is strictly smaller than another
+/// (and has no greater weight), only the smaller one will be kept.
+/// Each Bound has a weight (see `Notation::Weighted`), and may have some
+/// related data T.
 #[derive(Clone)]
 pub struct BoundSet<T> where T: Clone {
-    set: Vec<(Bound, T)>
+    set: Vec<(Bound, Weight, T)>,
+    /// Whether any Bounds were discarded, from this set or from the sets
+    /// it was built from, because there were too many of them.
+    truncated: bool
 }
 
 impl<T> BoundSet<T> where T: Clone {
     /// Construct an empty BoundSet.
     pub(super) fn new() -> BoundSet<T> {
         BoundSet {
-            set: vec!()
+            set: vec!(),
+            truncated: false
         }
     }
 
+    /// Were any Bounds discarded because there were more than
+    /// `MAX_BOUND_CANDIDATES` of them, in this set or in one that it was
+    /// built from?
+    pub(super) fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Mark this set as truncated if `from` (which it was built from) is.
+    /// Sets built with `combine` do this automatically.
+    pub(super) fn inherit_truncation<U: Clone>(mut self, from: &BoundSet<U>) -> BoundSet<T> {
+        self.truncated |= from.truncated;
+        self
+    }
+
     /// Pick the best (i.e., smallest) Bound that fits within the
     /// given Bound. Panics if none fit.
     pub(super) fn fit_bound(&self, space: Bound) -> (Bound, T) {
-        let bound = self.into_iter().filter(|(bound, _)| {
-            bound.dominates(space)
-        }).nth(0);
-        match bound {
+        match self.try_fit_bound(space) {
             Some(bound) => bound,
             None        => panic!("No bound fits within given width {}.\nBoundset: {:?}",
                                   space.width, self)
         }
     }
 
+    /// Pick the best Bound that fits within the given Bound, or `None`
+    /// if none fit. The best is the one with the least weight, or if
+    /// there's a tie, the smallest.
+    pub(super) fn try_fit_bound(&self, space: Bound) -> Option<(Bound, T)> {
+        self.set.iter().filter(|(bound, _, _)| {
+            bound.dominates(space)
+        }).min_by_key(|(_, weight, _)| *weight)
+            .map(|(bound, _, val)| (*bound, val.clone()))
+    }
+
+    /// Pick the Bound that fits within the given Bound and takes the
+    /// fewest rows, or `None` if none fit. Ties are broken by weight,
+    /// and then by width.
+    pub(super) fn try_fit_bound_shortest(&self, space: Bound) -> Option<(Bound, T)> {
+        self.set.iter().filter(|(bound, _, _)| {
+            bound.dominates(space)
+        }).min_by_key(|(bound, weight, _)| (bound.height, *weight, bound.width))
+            .map(|(bound, _, val)| (*bound, val.clone()))
+    }
+
+    /// Pick the widest Bound that fits within the given width. If there
+    /// is a tie, pick the shortest of them (and then the first). Panics
+    /// if none fit.
+    pub(super) fn widest_fit(&self, width: Col) -> (Bound, T) {
+        match self.set.iter().filter(|(bound, _, _)| bound.width <= width)
+            .min_by_key(|(bound, _, _)| (Reverse(bound.width), bound.height))
+        {
+            Some((bound, _, val)) => (*bound, val.clone()),
+            None => panic!("No bound fits within given width {}.\nBoundset: {:?}",
+                           width, self)
+        }
+    }
+
     pub(super) fn singleton(bound: Bound, val: T) -> BoundSet<T> {
         let mut set = BoundSet::new();
-        set.insert(bound, val);
+        set.insert(bound, 0, val);
         set
     }
 
     // TODO: efficiency (can go from O(n) to O(sqrt(n)))
     // MUST FILTER IDENTICALLY TO LayoutSet::insert
-    pub(super) fn insert(&mut self, bound: Bound, val: T) {
+    pub(super) fn insert(&mut self, bound: Bound, weight: Weight, val: T) {
         if bound.too_wide() {
             return;
         }
-        for (b, _) in &self.set {
-            if b.dominates(bound) {
+        for &(b, w, _) in &self.set {
+            if b.dominates(bound) && w <= weight {
                 return;
             }
         }
-        self.set.retain(|&(b, _)| !bound.dominates(b));
-        self.set.push((bound, val));
+        self.set.retain(|&(b, w, _)| !(bound.dominates(b) && weight <= w));
+        self.set.push((bound, weight, val));
+        if self.set.len() > MAX_BOUND_CANDIDATES {
+            self.prune();
+        }
+    }
+
+    /// Discard the tallest (and then heaviest) Bound, but never the
+    /// narrowest, so that it can still be displayed as narrowly as before.
+    fn prune(&mut self) {
+        let narrowest = (0..self.set.len())
+            .min_by_key(|&i| self.set[i].0.width)
+            .expect("BoundSet::prune - empty set");
+        let tallest = (0..self.set.len())
+            .filter(|&i| i != narrowest)
+            .max_by_key(|&i| (self.set[i].0.height, self.set[i].1))
+            .expect("BoundSet::prune - singleton set");
+        self.set.remove(tallest);
+        self.truncated = true;
     }
 
     /// Combine two boundsets. Produces a boundset whose elements are
     /// `(f(b1, b2), g(t1, t2))`
     /// for all `(b1, t1)` in `set1` and all `(b2, t2)` in `set2`.
+    /// Their weights are added.
     pub(super) fn combine<F, G>(set1: &BoundSet<T>, set2: &BoundSet<T>, f: F, g: G)
                                 -> BoundSet<T>
         where F: Fn(Bound, Bound) -> Bound, G: Fn(T, T) -> T
     {
         let mut set = BoundSet::new();
-        for (bound1, val1) in set1.into_iter() {
-            for (bound2, val2) in set2.into_iter() {
+        for (bound1, weight1, val1) in set1.weighted() {
+            for (bound2, weight2, val2) in set2.weighted() {
                 let bound = f(bound1, bound2);
                 let val = g(val1.clone(), val2);
-                set.insert(bound, val);
+                set.insert(bound, weight1 + weight2, val);
             }
         }
-        set
+        set.inherit_truncation(set1).inherit_truncation(set2)
+    }
+
+    /// Iterate over the Bounds, together with their weights.
+    pub(super) fn weighted(&self) -> impl Iterator<Item = (Bound, Weight, T)> + '_ {
+        self.set.iter().cloned()
     }
 
     /// Pick the best (i.e., smallest) Bound that fits within the
@@ -82,22 +160,27 @@ impl<T> BoundSet<T> where T: Clone {
         self.fit_bound(bound)
     }
 
+    pub(super) fn len(&self) -> usize {
+        self.set.len()
+    }
+
     #[cfg(test)]
     pub(super) fn first(&self) -> (Bound, T) {
-        self.set[0].clone()
+        let (bound, _, val) = self.set[0].clone();
+        (bound, val)
     }
 }
 
 impl<T> fmt::Debug for BoundSet<T> where T: Clone {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let set: Vec<&Bound> = self.set.iter().map(|(bound, _)| bound).collect();
+        let set: Vec<&Bound> = self.set.iter().map(|(bound, _, _)| bound).collect();
         write!(f, "{:?}", set)
     }
 }
 
 /// Iterator over Bounds in a BoundSet.
 pub struct BoundIter<'a, T: 'a> where T: Clone {
-    set: &'a Vec<(Bound, T)>,
+    set: &'a Vec<(Bound, Weight, T)>,
     i: usize
 }
 
@@ -108,7 +191,8 @@ impl<'a, T> Iterator for BoundIter<'a, T> where T: Clone {
             None
         } else {
             self.i += 1;
-            Some(self.set[self.i - 1].clone())
+            let (bound, _, val) = &self.set[self.i - 1];
+            Some((*bound, val.clone()))
         }
     }
 }
@@ -132,7 +216,21 @@ impl<T> iter::FromIterator<(Bound, T)> for BoundSet<T>
     {
         let mut set = BoundSet::new();
         for (bound, val) in iter.into_iter() {
-            set.insert(bound, val);
+            set.insert(bound, 0, val);
+        }
+        set
+    }
+}
+
+impl<T> iter::FromIterator<(Bound, Weight, T)> for BoundSet<T>
+    where T: Clone
+{
+    fn from_iter<I>(iter: I) -> BoundSet<T>
+        where I: iter::IntoIterator<Item = (Bound, Weight, T)>
+    {
+        let mut set = BoundSet::new();
+        for (bound, weight, val) in iter.into_iter() {
+            set.insert(bound, weight, val);
         }
         set
     }
@@ -2,32 +2,68 @@ use std::cmp;
 use std::fmt;
 
 use super::boundset::BoundSet;
-use crate::geometry::{Col, Pos, Bound, Region};
+use crate::geometry::{Row, Col, Pos, Bound, Region, WidthFn, CharCount};
 use crate::style::Style;
-use crate::notation::Notation;
+use crate::notation::{Notation, OverflowPolicy, Weight};
 
 use self::Layout::*;
 
 
+/// What a notation is laid out into: e.g. just its `Bound`, or a full
+/// `LayoutRegion` to display.
 pub trait Lay where Self: Clone {
     fn empty() -> Self;
-    fn literal(s: &str, style: Style) -> Self;
+    /// A single-line literal that takes up `width` columns. Its style
+    /// never affects its width: styling is rendered separately, and takes
+    /// up no columns.
+    fn measured_literal(s: &str, width: Col, style: Style) -> Self;
     fn concat(&self, other: Self) -> Self;
     fn horz(&self, other: Self) -> Self;
     fn vert(&self, other: Self) -> Self;
     fn text(child: Bound, style: Style) -> Self;
     fn child(i: usize, child: Bound) -> Self;
+    fn choose(&self, decision: ChoiceDecision) -> Self;
+    /// Treat this as being at least `width` columns wide, when deciding
+    /// whether it fits. Only its `Bound` grows: a displayed layout keeps
+    /// the size of its actual content.
+    fn reserve_width(&self, width: Col) -> Self;
+    /// Treat this single-line layout as always fitting, though it may be
+    /// clipped when displayed. Only its `Bound` shrinks to nothing: a
+    /// displayed layout keeps its real width, so that whatever follows it
+    /// is displayed after it, rather than over it.
+    fn clip(&self) -> Self;
+
+    /// A single-line literal, one column per character.
+    fn literal(s: &str, style: Style) -> Self {
+        Self::measured_literal(s, s.chars().count() as Col, style)
+    }
+
+    /// A literal that may span multiple lines. Each line is displayed
+    /// below the previous one, and measured with `width_fn`.
+    fn multiline(s: &str, style: Style, width_fn: &dyn WidthFn) -> Self {
+        let line = |line: &str| Self::measured_literal(line, width_fn.width(line) as Col, style);
+        let mut lines = s.split('\n');
+        let first = line(lines.next().unwrap_or(""));
+        lines.fold(first, |lay, l| lay.vert(line(l)))
+    }
 }
 
 
 impl Lay for () {
     fn empty()                            {}
-    fn literal(_s: &str, _style: Style)   {}
+    fn measured_literal(_s: &str, _width: Col, _style: Style) {}
     fn concat(&self, _other: ())          {}
     fn horz(&self, _other: ())            {}
     fn vert(&self, _other: ())            {}
     fn text(_child: Bound, _style: Style) {}
     fn child(_i: usize, _child: Bound)    {}
+    fn choose(&self, _decision: ChoiceDecision) {}
+    fn reserve_width(&self, _width: Col) {}
+    fn clip(&self) {}
+
+    // The literal's Bound is measured separately (see `lay`), so there is
+    // no need to measure it again.
+    fn multiline(_s: &str, _style: Style, _width_fn: &dyn WidthFn) {}
 }
 
 
@@ -40,8 +76,7 @@ impl Lay for Bound {
         }
     }
 
-    fn literal(s: &str, _style: Style) -> Bound {
-        let width = s.chars().count() as Col;
+    fn measured_literal(_s: &str, width: Col, _style: Style) -> Bound {
         Bound {
             width:  width,
             indent: width,
@@ -85,6 +120,21 @@ impl Lay for Bound {
     fn child(_i: usize, child: Bound) -> Bound {
         child
     }
+
+    fn choose(&self, _decision: ChoiceDecision) -> Bound {
+        *self
+    }
+
+    fn reserve_width(&self, width: Col) -> Bound {
+        Bound {
+            width: cmp::max(self.width, width),
+            ..*self
+        }
+    }
+
+    fn clip(&self) -> Bound {
+        Bound::empty()
+    }
 }
 
 
@@ -96,7 +146,19 @@ impl Lay for Bound {
 #[derive(Clone, PartialEq, Eq)]
 pub struct LayoutRegion {
     pub layout: Layout,
-    pub region: Region
+    pub region: Region,
+    /// The `Choice`s that were resolved to produce this layout.
+    pub choices: Vec<ChoiceDecision>
+}
+
+/// Records which branch of a `Choice` was taken in a `LayoutRegion`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChoiceDecision {
+    /// Identifies the `Choice`: it is the `choice`th `Choice` in a
+    /// pre-order traversal of the (expanded) notation.
+    pub choice: usize,
+    /// 0 if the first notation was chosen, 1 if the second was.
+    pub branch: usize
 }
 
 /// The enum for a LayoutRegion.
@@ -124,6 +186,100 @@ impl LayoutRegion {
         self.region.pos = self.region.pos + pos;
         self.layout.shift_by(pos);
     }
+
+    /// Which branch was taken for each `Choice` in this layout, in
+    /// pre-order. `Choice`s that were not reached (because they were
+    /// inside of a branch that was not taken) are not included. Neither
+    /// are the choices made within child nodes.
+    pub fn choice_trace(&self) -> Vec<ChoiceDecision> {
+        let mut trace = self.choices.clone();
+        trace.sort_by_key(|decision| decision.choice);
+        trace
+    }
+
+    /// Prepend a gutter of right-aligned line numbers to this layout,
+    /// one per row, counting up from `start`. The numbers are displayed
+    /// in `style`, and the gutter widens the layout by the width of the
+    /// largest number plus one space.
+    pub fn with_line_numbers(&self, start: usize, style: Style) -> LayoutRegion {
+        let rows = self.region.height() as usize + 1;
+        let digits = format!("{}", start + rows - 1).len();
+        let number = |n: usize| LayoutRegion::literal(&format!("{:>1$} ", n, digits), style);
+        let gutter = (start + 1 .. start + rows)
+            .fold(number(start), |gutter, n| gutter.vert(number(n)));
+        let mut lay = gutter.horz(self.clone());
+        lay.choices = self.choices.clone();
+        lay
+    }
+
+    /// If this layout is more than `max_rows` tall, keep its first and
+    /// last rows and replace the ones in between with a single marker
+    /// row, like "… 7 lines hidden …", displayed in `marker_style`. The
+    /// result is exactly `max_rows` tall. Text and children that
+    /// straddle the hidden rows are dropped along with them.
+    pub fn elide_middle(&self, max_rows: usize, marker_style: Style) -> LayoutRegion {
+        let rows = self.region.height() as usize + 1;
+        if rows <= max_rows || max_rows == 0 {
+            return self.clone();
+        }
+        let top = max_rows / 2;
+        let bottom = max_rows - 1 - top;
+        let hidden = rows - top - bottom;
+        let start = self.region.pos.row as usize;
+        let bottom_start = start + rows - bottom;
+
+        let mut marker = LayoutRegion::literal(&format!("… {} lines hidden …", hidden), marker_style);
+        marker.shift_by(Pos { row: (start + top) as Row, col: self.region.pos.col });
+        let mut above = vec!();
+        let mut below = vec!();
+        for mut piece in self.pieces() {
+            let first_row = piece.region.pos.row as usize;
+            let last_row = first_row + piece.region.height() as usize;
+            if last_row < start + top {
+                above.push(piece);
+            } else if first_row >= bottom_start {
+                piece.region.pos.row -= (hidden - 1) as Row;
+                below.push(piece);
+            }
+        }
+
+        let region = Region {
+            pos: self.region.pos,
+            bound: Bound {
+                width: cmp::max(self.region.width(), marker.region.width()),
+                indent: if bottom == 0 { marker.region.width() } else { self.region.bound.indent },
+                height: (max_rows - 1) as Row
+            }
+        };
+        let pieces = above.into_iter().chain(Some(marker.clone())).chain(below);
+        let mut lay = pieces.fold(LayoutRegion::empty(), |lay1, lay2| LayoutRegion {
+            layout: Layout::Concat(Box::new(lay1), Box::new(lay2)),
+            region,
+            choices: vec!()
+        });
+        lay.region = region;
+        lay.choices = self.choices.clone();
+        lay
+    }
+
+    /// The literals, text, and children in this layout, in order.
+    fn pieces(&self) -> Vec<LayoutRegion> {
+        match &self.layout {
+            Empty => vec!(),
+            Concat(lay1, lay2) | Horz(lay1, lay2) | Vert(lay1, lay2) => {
+                let mut pieces = lay1.pieces();
+                pieces.extend(lay2.pieces());
+                pieces
+            }
+            _ => vec!(self.clone())
+        }
+    }
+
+    fn merge_choices(&self, other: &LayoutRegion) -> Vec<ChoiceDecision> {
+        let mut choices = self.choices.clone();
+        choices.extend(other.choices.iter().cloned());
+        choices
+    }
 }
 
 impl Layout {
@@ -188,17 +344,19 @@ impl Lay for LayoutRegion {
                 pos:   Pos::zero(),
                 bound: Bound::empty()
             },
-            layout: Layout::Empty
+            layout: Layout::Empty,
+            choices: vec!()
         }
     }
 
-    fn literal(s: &str, style: Style) -> LayoutRegion {
+    fn measured_literal(s: &str, width: Col, style: Style) -> LayoutRegion {
         LayoutRegion {
             region: Region {
                 pos:   Pos::zero(),
-                bound: Bound::literal(&s, style)
+                bound: Bound::measured_literal(s, width, style)
             },
-            layout: Layout::Literal(s.to_string(), style)
+            layout: Layout::Literal(s.to_string(), style),
+            choices: vec!()
         }
     }
 
@@ -211,6 +369,7 @@ impl Lay for LayoutRegion {
                 pos:   self.region.pos,
                 bound: self.region.bound.concat(other.region.bound)
             },
+            choices: self.merge_choices(&other),
             layout: Layout::Concat(Box::new(self_lay), Box::new(other_lay))
         }
     }
@@ -228,6 +387,7 @@ impl Lay for LayoutRegion {
                 pos:   self.region.pos,
                 bound: self.region.bound.horz(other.region.bound)
             },
+            choices: self.merge_choices(&other),
             layout: Layout::Horz(Box::new(self_lay), Box::new(other_lay))
         }
     }
@@ -245,6 +405,7 @@ impl Lay for LayoutRegion {
                 pos:   self.region.pos,
                 bound: self.region.bound.vert(other.region.bound)
             },
+            choices: self.merge_choices(&other),
             layout: Layout::Vert(Box::new(self_lay), Box::new(other_lay))
         }
     }
@@ -255,7 +416,8 @@ impl Lay for LayoutRegion {
                 pos:   Pos::zero(),
                 bound: Bound::text(child, style)
             },
-            layout: Layout::Text(style)
+            layout: Layout::Text(style),
+            choices: vec!()
         }
     }
 
@@ -265,9 +427,26 @@ impl Lay for LayoutRegion {
                 pos:   Pos::zero(),
                 bound: Bound::child(i, child)
             },
-            layout: Layout::Child(i)
+            layout: Layout::Child(i),
+            choices: vec!()
         }
     }
+
+    fn choose(&self, decision: ChoiceDecision) -> LayoutRegion {
+        let mut lay = self.clone();
+        lay.choices.push(decision);
+        lay
+    }
+
+    fn reserve_width(&self, _width: Col) -> LayoutRegion {
+        // Its key in the `BoundSet` is what reserves the width.
+        self.clone()
+    }
+
+    fn clip(&self) -> LayoutRegion {
+        // Its key in the `BoundSet` is what makes it fit.
+        self.clone()
+    }
 }
 
 #[derive(Clone)]
@@ -278,6 +457,57 @@ impl Bounds {
         Bounds(BoundSet::new())
     }
 
+    /// The narrowest width at which these Bounds can be displayed, or
+    /// `None` if there are no Bounds (e.g. because they were all wider
+    /// than `MAX_WIDTH`).
+    pub fn min_width(&self) -> Option<Col> {
+        self.0.into_iter().map(|(bound, ())| bound.width).min()
+    }
+
+    /// Are there no Bounds at all? (E.g. for a child that is absent.)
+    pub fn is_empty(&self) -> bool {
+        self.0.into_iter().next().is_none()
+    }
+
+    /// Were some Bounds discarded while computing these (or the Bounds
+    /// of a child), because there were more than `MAX_BOUND_CANDIDATES`
+    /// of them? If so, the layout picked may not be the best one.
+    pub fn is_truncated(&self) -> bool {
+        self.0.is_truncated()
+    }
+
+    /// Do these Bounds display nothing at all? (Either because there are
+    /// none, or because the only one is the empty Bound.)
+    pub(crate) fn is_blank(&self) -> bool {
+        self.0.into_iter().all(|(bound, ())| bound == Bound::empty())
+    }
+
+    /// Can any of these Bounds be displayed within the given width?
+    pub fn fits_width(&self, width: Col) -> bool {
+        match self.min_width() {
+            Some(min_width) => min_width <= width,
+            None => false
+        }
+    }
+
+    /// If these were the first option of a `Choice`, and `other` the
+    /// second, would every one of `other`'s Bounds be discarded (because
+    /// one of these is at least as small, and weighs no more)? If
+    /// `first` is false, ask the same about the first option instead:
+    /// then `self` is the second option, and does not win ties.
+    pub(crate) fn shadows(&self, other: &Bounds, first: bool) -> bool {
+        other.0.weighted().all(|(bound, weight, ())| {
+            self.0.weighted().any(|(b, w, ())| {
+                b.dominates(bound) && w <= weight && (first || (b, w) != (bound, weight))
+            })
+        })
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+
     #[cfg(test)]
     pub(crate) fn first(&self) -> Bound {
         self.0.first().0
@@ -285,6 +515,29 @@ impl Bounds {
 }
 
 impl Layouts {
+    /// The number of candidate layouts, after dominated ones have been
+    /// pruned.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Are there no candidate layouts at all?
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Were some candidate layouts discarded, because there were more
+    /// than `MAX_BOUND_CANDIDATES` of them? (See `Bounds::is_truncated`.)
+    pub fn is_truncated(&self) -> bool {
+        self.0.is_truncated()
+    }
+
+    /// The narrowest width at which any of these layouts can be
+    /// displayed, or `None` if there are none.
+    pub fn min_width(&self) -> Option<Col> {
+        self.0.into_iter().map(|(bound, _)| bound.width).min()
+    }
+
     #[cfg(test)]
     pub(crate) fn fit_width(&self, width: Col) -> LayoutRegion {
         self.0.fit_width(width).1
@@ -293,75 +546,678 @@ impl Layouts {
     pub fn fit_bound(&self, bound: Bound) -> LayoutRegion {
         self.0.fit_bound(bound).1
     }
+
+    /// The widest layout that fits within `width`, rather than the
+    /// preferred one (as `fit_bound` gives). If several are equally wide,
+    /// the shortest is picked.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no layout fits.
+    pub fn widest_fit(&self, width: Col) -> LayoutRegion {
+        self.0.widest_fit(width).1
+    }
+
+    /// The narrowest width at which the preferred layout (the one
+    /// `fit_bound` would pick) takes up at most `max_height` lines, or
+    /// `None` if there is no such width.
+    pub fn min_width_for_height(&self, max_height: usize) -> Option<usize> {
+        // The preferred layout only changes at the width of some layout.
+        let mut widths: Vec<Col> = self.0.into_iter().map(|(bound, _)| bound.width).collect();
+        widths.sort();
+        widths.dedup();
+        widths.into_iter().find(|&width| {
+            match self.try_fit_bound(Bound::infinite_scroll(width)) {
+                Some(lay) => (lay.region.height() as usize) < max_height,
+                None => false
+            }
+        }).map(|width| width as usize)
+    }
+
+    /// Like `fit_bound`, but returns `None` instead of panicking if no
+    /// layout fits.
+    pub fn try_fit_bound(&self, bound: Bound) -> Option<LayoutRegion> {
+        self.0.try_fit_bound(bound).map(|(_, lay)| lay)
+    }
+
+    /// Like `try_fit_bound`, but picking among the layouts that fit
+    /// according to `strategy`.
+    pub fn try_fit_bound_with(&self, bound: Bound, strategy: LayoutStrategy)
+                              -> Option<LayoutRegion>
+    {
+        match strategy {
+            LayoutStrategy::Greedy => self.try_fit_bound(bound),
+            LayoutStrategy::Optimal =>
+                self.0.try_fit_bound_shortest(bound).map(|(_, lay)| lay)
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct Layouts(BoundSet<LayoutRegion>);
 
-// If the node is texty, `child_bounds` should be a singleton vec of the text bounds.
+/// The ways in which laying out a notation can fail. See
+/// `try_compute_layouts`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayoutError {
+    /// The notation is nested `depth` levels deep (see `Notation::depth`),
+    /// which is more than the limit. Laying it out could overflow the
+    /// stack.
+    TooDeep { depth: usize },
+    /// The notation contains `count` `Choice`s (counting those in each
+    /// element of a repeat), which is more than the limit.
+    TooManyChoices { count: usize },
+    /// The notation contains a combinator that must be expanded away
+    /// before laying out (such as a `Repeat`, or a `Star` outside of one),
+    /// or a streamed repeat with fewer than two elements.
+    Unexpanded(&'static str),
+    /// The notation displays the `index`th child, but there is no such
+    /// child.
+    MissingChild { index: usize },
+    /// The notation displays text, but there are no text bounds.
+    MissingText,
+    /// The weights of some layout could add up to more than `Weight`
+    /// can hold.
+    WeightOverflow
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayoutError::TooDeep { depth } =>
+                write!(f, "notation is too deeply nested to lay out (depth {})", depth),
+            LayoutError::TooManyChoices { count } =>
+                write!(f, "notation has too many choices to lay out ({})", count),
+            LayoutError::Unexpanded(combinator) =>
+                write!(f, "notation contains an unexpanded {}", combinator),
+            LayoutError::MissingChild { index } =>
+                write!(f, "notation displays child {}, which does not exist", index),
+            LayoutError::MissingText =>
+                write!(f, "notation displays text, but there is none"),
+            LayoutError::WeightOverflow =>
+                write!(f, "notation's layout weights are too large")
+        }
+    }
+}
+
+/// The deepest notation that `try_compute_layouts` will lay out. Laying out
+/// recurses once per level, and an unoptimized build takes about 4KiB of
+/// stack per level: this leaves room for the caller on a 2MiB thread (the
+/// default for spawned threads), which overflows at around 480 levels.
+pub const MAX_LAYOUT_DEPTH: usize = 256;
+/// The most `Choice`s that `try_compute_layouts` will lay out.
+pub const MAX_LAYOUT_CHOICES: usize = 10_000;
+
+/// How to pick among the layouts that fit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayoutStrategy {
+    /// Pick the layout with the least weight. Among equally weighted
+    /// layouts, the first `Choice` that fits wins. This is what
+    /// `fit_bound` does.
+    Greedy,
+    /// Pick the layout that takes the fewest rows, even if it is not the
+    /// preferred `Choice`. Weight only breaks ties.
+    Optimal
+}
+
+/// Every way of laying out `notation` that might be picked, given the
+/// Bounds of the node's children. This builds a full `LayoutRegion` for
+/// each, copying every literal into it, so only use it to display the
+/// node. To decide how things will fit (e.g. when reflowing), use
+/// `compute_bounds` instead.
+///
+/// If `notation` displays text, the text bounds should come last in
+/// `child_bounds`: for a texty node, it should be a singleton vec of the
+/// text bounds, and for a mixed node, the text bounds should come after
+/// those of its children. (To lay out a mixed node whose notation doesn't
+/// display its text, use `compute_layouts_with`.)
 pub fn compute_layouts(child_bounds: &Vec<Bounds>, notation: &Notation) -> Layouts {
-    Layouts(lay(child_bounds, notation))
+    let bounds = ChildBounds::split(child_bounds.as_slice(), notation);
+    compute_layouts_with(bounds.children, bounds.text, notation, &CharCount)
 }
 
-// If the node is texty, `child_bounds` should be a singleton vec of the text bounds.
+/// The Bounds of every layout that `compute_layouts` would give, and
+/// nothing else. This is the fast path: it measures each literal once,
+/// and never copies literals or styles, or builds `LayoutRegion`s.
+///
+/// `child_bounds` is as for `compute_layouts`.
 pub fn compute_bounds(child_bounds: &Vec<Bounds>, notation: &Notation) -> Bounds {
-    Bounds(lay(child_bounds, notation))
+    let bounds = ChildBounds::split(child_bounds.as_slice(), notation);
+    compute_bounds_with(bounds.children, bounds.text, notation, &CharCount)
 }
 
 pub fn text_bounds(text: &str) -> Bounds {
-    Bounds(BoundSet::singleton(Bound::literal(text, Style::plain()), ()))
+    text_bounds_with(text, &CharCount)
+}
+
+/// Like `compute_layouts`, but measuring literals with `width_fn`, and
+/// with the Bounds of the node's text (if it has any) passed separately
+/// from those of its children.
+pub fn compute_layouts_with(child_bounds: &[Bounds], text_bounds: Option<&Bounds>,
+                            notation: &Notation, width_fn: &dyn WidthFn) -> Layouts {
+    let bounds = ChildBounds { children: child_bounds, text: text_bounds };
+    Layouts(lay(&bounds, notation, &mut 0, width_fn))
+}
+
+/// Like `compute_bounds`, but measuring literals with `width_fn`, and with
+/// the text bounds passed separately (see `compute_layouts_with`).
+pub fn compute_bounds_with(child_bounds: &[Bounds], text_bounds: Option<&Bounds>,
+                           notation: &Notation, width_fn: &dyn WidthFn) -> Bounds {
+    let bounds = ChildBounds { children: child_bounds, text: text_bounds };
+    Bounds(lay(&bounds, notation, &mut 0, width_fn))
+}
+
+// The Bounds of a node's children, kept apart from those of its text, so
+// that the text is never mistaken for a child.
+struct ChildBounds<'a> {
+    children: &'a [Bounds],
+    text: Option<&'a Bounds>
+}
+
+impl<'a> ChildBounds<'a> {
+    // Split `child_bounds`, as passed to `compute_layouts`.
+    fn split(child_bounds: &'a [Bounds], notation: &Notation) -> ChildBounds<'a> {
+        match child_bounds.split_last() {
+            Some((text, children)) if notation.uses_text() => ChildBounds {
+                children,
+                text: Some(text)
+            },
+            _ => ChildBounds {
+                children: child_bounds,
+                text: None
+            }
+        }
+    }
+}
+
+/// Like `compute_layouts`, but return an error instead of panicking (or
+/// overflowing the stack) on any notation that can't be laid out with
+/// these `child_bounds`. This is the entry point to use for notations
+/// that come from untrusted sources, like plugins or a fuzzer. Notations
+/// more than `MAX_LAYOUT_DEPTH` deep, or with more than
+/// `MAX_LAYOUT_CHOICES` choices, are rejected.
+pub fn try_compute_layouts(child_bounds: &Vec<Bounds>, notation: &Notation)
+                           -> Result<Layouts, LayoutError> {
+    try_compute_layouts_to_depth(child_bounds, notation, MAX_LAYOUT_DEPTH)
+}
+
+/// Like `compute_bounds`, but return an error instead of panicking (see
+/// `try_compute_layouts`).
+pub fn try_compute_bounds(child_bounds: &Vec<Bounds>, notation: &Notation)
+                          -> Result<Bounds, LayoutError> {
+    try_compute_bounds_to_depth(child_bounds, notation, MAX_LAYOUT_DEPTH)
+}
+
+/// Like `try_compute_layouts`, but with a depth limit of `max_depth`
+/// instead of `MAX_LAYOUT_DEPTH`.
+pub fn try_compute_layouts_to_depth(child_bounds: &Vec<Bounds>, notation: &Notation,
+                                    max_depth: usize)
+                                    -> Result<Layouts, LayoutError> {
+    check_layable(&ChildBounds::split(child_bounds, notation), notation, max_depth)?;
+    Ok(compute_layouts(child_bounds, notation))
+}
+
+/// Like `try_compute_bounds`, but with a depth limit of `max_depth`
+/// instead of `MAX_LAYOUT_DEPTH`.
+pub fn try_compute_bounds_to_depth(child_bounds: &Vec<Bounds>, notation: &Notation,
+                                   max_depth: usize)
+                                   -> Result<Bounds, LayoutError> {
+    check_layable(&ChildBounds::split(child_bounds, notation), notation, max_depth)?;
+    Ok(compute_bounds(child_bounds, notation))
+}
+
+// Check, without recursing, for everything that would make `lay` panic.
+fn check_layable(child_bounds: &ChildBounds, notation: &Notation, max_depth: usize)
+                 -> Result<(), LayoutError> {
+    let depth = notation.depth();
+    if depth > max_depth {
+        return Err(LayoutError::TooDeep { depth });
+    }
+    let max_weight = |bounds: &Bounds| -> u64 {
+        bounds.0.weighted().map(|(_, weight, ())| weight as u64).max().unwrap_or(0)
+    };
+    let num_children = child_bounds.children.len() as u64;
+    let all_children_weight = child_bounds.children.iter().map(max_weight)
+        .fold(0, u64::saturating_add);
+    // An upper bound on the total weight of any layout. Each entry on the
+    // stack says whether it's inside a repeat, and how many times it's
+    // laid out.
+    let mut weight: u64 = 0;
+    let mut add_weight = |w: u64, times: u64| weight = weight.saturating_add(w.saturating_mul(times));
+    let mut stack = vec!((notation, false, 1u64));
+    while let Some((notation, in_repeat, times)) = stack.pop() {
+        match notation {
+            Notation::Rep(_) => return Err(LayoutError::Unexpanded("Repeat")),
+            Notation::Star if !in_repeat => return Err(LayoutError::Unexpanded("Star")),
+            Notation::Star => add_weight(all_children_weight, times),
+            Notation::IfEmptyText(_, _) => return Err(LayoutError::Unexpanded("IfEmptyText")),
+            Notation::MapText(_, _) => return Err(LayoutError::Unexpanded("MapText")),
+            Notation::Count(..) => return Err(LayoutError::Unexpanded("Count")),
+            Notation::ChildParens(..) => return Err(LayoutError::Unexpanded("ChildParens")),
+            Notation::Child(index) => match child_bounds.children.get(*index) {
+                None => return Err(LayoutError::MissingChild { index: *index }),
+                Some(bounds) => add_weight(max_weight(bounds), times)
+            },
+            // An absent optional child is not laid out at all.
+            Notation::OptionalChild(index, _)
+                if child_bounds.children.get(*index).is_none_or(|bounds| bounds.is_empty()) =>
+                    continue,
+            Notation::Text(_) => match child_bounds.text {
+                None => return Err(LayoutError::MissingText),
+                Some(bounds) => add_weight(max_weight(bounds), times)
+            },
+            Notation::StreamedRep(repeat, len) => {
+                if *len < 2 {
+                    return Err(LayoutError::Unexpanded("StreamedRep"));
+                }
+                if *len > child_bounds.children.len() {
+                    return Err(LayoutError::MissingChild { index: len - 1 });
+                }
+                let times = times.saturating_mul(*len as u64);
+                for part in &[&repeat.first, &repeat.middle, &repeat.last] {
+                    stack.push((part, true, times));
+                }
+                continue;
+            }
+            Notation::Weighted(_, extra) => add_weight(*extra as u64, times),
+            Notation::WidthSwitch(_, _, _) => add_weight(1, times),
+            Notation::NoWrap(_, policy) if *policy != OverflowPolicy::Strict =>
+                add_weight(1, times),
+            Notation::Table(rows, sep) => {
+                for (left, right) in rows {
+                    stack.push((left, in_repeat, times));
+                    stack.push((right, in_repeat, times));
+                }
+                stack.push((sep, in_repeat, times.saturating_mul(rows.len() as u64)));
+                continue;
+            }
+            Notation::JoinPresent(sep) => {
+                add_weight(all_children_weight, times);
+                stack.push((sep, in_repeat, times.saturating_mul(num_children)));
+                continue;
+            }
+            _ => ()
+        }
+        stack.extend(notation.parts().into_iter().map(|part| (part, in_repeat, times)));
+    }
+    if weight > Weight::MAX as u64 {
+        return Err(LayoutError::WeightOverflow);
+    }
+    let count = notation.count_choices();
+    if count > MAX_LAYOUT_CHOICES {
+        return Err(LayoutError::TooManyChoices { count });
+    }
+    Ok(())
+}
+
+/// Like `text_bounds`, but measuring the text with `width_fn`.
+pub fn text_bounds_with(text: &str, width_fn: &dyn WidthFn) -> Bounds {
+    Bounds(BoundSet::singleton(Bound::measure(text, width_fn), ()))
+}
+
+// Keep only the layouts in `set` that are at most `width` wide, and pad
+// the last line of each with spaces, out to `width` plus `gap`.
+fn pad_to<L: Lay>(set: &BoundSet<L>, width: Col, gap: Col) -> BoundSet<L> {
+    set.weighted().filter(|(bound, _, _)| {
+        bound.width <= width
+    }).map(|(bound, weight, val)| {
+        let padding = width + gap - bound.indent;
+        let spaces = " ".repeat(padding as usize);
+        (bound.concat(Bound::measured_literal(&spaces, padding, Style::plain())),
+         weight,
+         val.concat(L::measured_literal(&spaces, padding, Style::plain())))
+    }).collect::<BoundSet<L>>().inherit_truncation(set)
+}
+
+// Flatten a chain of `Concat`s.
+fn concat_parts<'a>(notation: &'a Notation, parts: &mut Vec<&'a Notation>) {
+    match notation {
+        Notation::Concat(note1, note2) => {
+            concat_parts(note1, parts);
+            concat_parts(note2, parts);
+        }
+        _ => parts.push(notation)
+    }
 }
 
-fn lay<L: Lay>(child_bounds: &Vec<Bounds>, notation: &Notation) -> BoundSet<L> {
+// Lay out a chain of `Concat`s that has no `SoftBreak`s in it, as it is
+// parenthesized. (Unlike `lay`, this doesn't look for `SoftBreak`s again
+// at every level of the chain.)
+fn lay_concats<L: Lay>(child_bounds: &ChildBounds, notation: &Notation, choices: &mut usize,
+                       width_fn: &dyn WidthFn)
+                       -> BoundSet<L>
+{
+    match notation {
+        Notation::Concat(note1, note2) => {
+            BoundSet::combine(&lay_concats(child_bounds, note1, choices, width_fn),
+                              &lay_concats(child_bounds, note2, choices, width_fn),
+                              |b1, b2| b1.concat(b2),
+                              |v1, v2| v1.concat(v2))
+        }
+        _ => lay(child_bounds, notation, choices, width_fn)
+    }
+}
+
+// Lay out a chain of `Concat`s, from left to right, laying out each part
+// only once. Each `SoftBreak` in the chain either joins what comes
+// before it to what comes after it, or breaks the line, so that what
+// comes after it starts at the column where the chain started.
+fn lay_concat_chain<L: Lay>(child_bounds: &ChildBounds, parts: &[&Notation],
+                            choices: &mut usize, width_fn: &dyn WidthFn)
+                            -> BoundSet<L>
+{
+    let concat = |set1: &BoundSet<L>, set2: &BoundSet<L>| {
+        BoundSet::combine(set1, set2, |b1, b2| b1.concat(b2), |v1, v2| v1.concat(v2))
+    };
+    let mut chain: Option<BoundSet<L>> = None;
+    for part in parts {
+        let before = chain.take();
+        chain = Some(match part {
+            Notation::SoftBreak(joiner, prefix) => {
+                let choice = *choices;
+                *choices += 1;
+                let before = before.unwrap_or_else(|| {
+                    BoundSet::singleton(Bound::empty(), L::empty())
+                });
+                let joiner: BoundSet<L> = lay(child_bounds, joiner, choices, width_fn);
+                let prefix: BoundSet<L> = lay(child_bounds, prefix, choices, width_fn);
+                let joined = concat(&before, &joiner);
+                let broken = BoundSet::combine(&before, &prefix,
+                                               |b1, b2| b1.vert(b2),
+                                               |v1, v2| v1.vert(v2));
+                let decide = |branch| ChoiceDecision {
+                    choice,
+                    branch
+                };
+                joined.weighted().map(|(bound, weight, val)| (bound, weight, val.choose(decide(0))))
+                    .chain(broken.weighted().map(|(bound, weight, val)| {
+                        (bound, weight, val.choose(decide(1)))
+                    }))
+                    .collect::<BoundSet<L>>()
+                    .inherit_truncation(&joined)
+                    .inherit_truncation(&broken)
+            }
+            _ => {
+                let set = lay(child_bounds, part, choices, width_fn);
+                match before {
+                    None => set,
+                    Some(before) => concat(&before, &set)
+                }
+            }
+        });
+    }
+    chain.expect("lay_concat_chain: empty chain")
+}
+
+// `choices` counts the `Choice`s seen so far, to give each one an id.
+fn lay<L: Lay>(child_bounds: &ChildBounds, notation: &Notation, choices: &mut usize,
+               width_fn: &dyn WidthFn)
+               -> BoundSet<L>
+{
     match notation {
         Notation::Empty => {
             BoundSet::singleton(Bound::empty(),
                                 L::empty())
         }
         Notation::Literal(s, style) => {
-            BoundSet::singleton(Bound::literal(s, *style),
-                                L::literal(s, *style))
+            BoundSet::singleton(Bound::measure(s, width_fn),
+                                L::multiline(s, *style, width_fn))
         }
         Notation::Text(style) => {
-            child_bounds[0].0.into_iter().map(|(bound, ())| {
-                (bound, L::text(bound, *style))
-            }).collect()
+            let text = &child_bounds.text.expect("lay: missing text bounds").0;
+            text.weighted().map(|(bound, weight, ())| {
+                (bound, weight, L::text(bound, *style))
+            }).collect::<BoundSet<L>>().inherit_truncation(text)
         }
         Notation::Child(index) => {
-            child_bounds[*index].0.into_iter().map(|(bound, ())| {
-                (bound, L::child(*index, bound))
-            }).collect()
+            let child = &child_bounds.children[*index].0;
+            child.weighted().map(|(bound, weight, ())| {
+                (bound, weight, L::child(*index, bound))
+            }).collect::<BoundSet<L>>().inherit_truncation(child)
         }
-        Notation::Concat(note1, note2) => {
-            BoundSet::combine(&lay(child_bounds, note1),
-                              &lay(child_bounds, note2),
-                              |b1, b2| b1.concat(b2),
-                              |v1, v2| v1.concat(v2))
+        Notation::Concat(_, _) => {
+            let mut parts = vec!();
+            concat_parts(notation, &mut parts);
+            if parts.iter().any(|part| matches!(part, Notation::SoftBreak(_, _))) {
+                lay_concat_chain(child_bounds, &parts, choices, width_fn)
+            } else {
+                lay_concats(child_bounds, notation, choices, width_fn)
+            }
+        }
+        Notation::SoftBreak(_, _) => {
+            lay_concat_chain(child_bounds, &[notation], choices, width_fn)
         }
+        Notation::Group(note) => lay(child_bounds, note, choices, width_fn),
         Notation::Horz(note1, note2) => {
-            BoundSet::combine(&lay(child_bounds, note1),
-                              &lay(child_bounds, note2),
+            BoundSet::combine(&lay(child_bounds, note1, choices, width_fn),
+                              &lay(child_bounds, note2, choices, width_fn),
                               |b1, b2| b1.horz(b2),
                               |v1, v2| v1.horz(v2))
         }
         Notation::Vert(note1, note2) => {
-            BoundSet::combine(&lay(child_bounds, note1),
-                              &lay(child_bounds, note2),
+            BoundSet::combine(&lay(child_bounds, note1, choices, width_fn),
+                              &lay(child_bounds, note2, choices, width_fn),
                               |b1, b2| b1.vert(b2),
                               |v1, v2| v1.vert(v2))
         }
-        Notation::NoWrap(note) => {
-            let set = lay(child_bounds, note);
-            set.into_iter().filter(|(bound, _)| {
-                bound.height == 0
-            }).collect()
+        Notation::OptionalChild(index, note) => {
+            let present = child_bounds.children.get(*index)
+                .is_some_and(|bounds| !bounds.is_empty());
+            if present {
+                lay(child_bounds, note, choices, width_fn)
+            } else {
+                // Keep the ids of any later `Choice`s the same either way.
+                *choices += note.count_choices();
+                BoundSet::singleton(Bound::empty(), L::empty())
+            }
+        }
+        Notation::NoWrap(note, policy) => {
+            let set = lay(child_bounds, note, choices, width_fn);
+            let flat = set.weighted().filter(|(bound, _, _)| bound.height == 0);
+            let result: BoundSet<L> = match policy {
+                OverflowPolicy::Strict => flat.collect(),
+                OverflowPolicy::Clip => flat.flat_map(|(bound, weight, val)| {
+                    let clipped = (bound.clip(), weight + 1, val.clip());
+                    vec!((bound, weight, val), clipped)
+                }).collect(),
+                OverflowPolicy::Fallback => set.weighted().map(|(bound, weight, val)| {
+                    if bound.height == 0 {
+                        (bound, weight, val)
+                    } else {
+                        (bound, weight + 1, val)
+                    }
+                }).collect()
+            };
+            result.inherit_truncation(&set)
+        }
+        Notation::Joined(note1, note2) => {
+            let set1: BoundSet<L> = lay(child_bounds, note1, choices, width_fn);
+            let set1: BoundSet<L> = set1.weighted().filter(|(bound, _, _)| {
+                bound.height == 0 || bound.indent > 0
+            }).map(|(bound, weight, val)| {
+                (bound.concat(Bound::measured_literal(" ", 1, Style::plain())),
+                 weight,
+                 val.concat(L::measured_literal(" ", 1, Style::plain())))
+            }).collect::<BoundSet<L>>().inherit_truncation(&set1);
+            BoundSet::combine(&set1,
+                              &lay(child_bounds, note2, choices, width_fn),
+                              |b1, b2| b1.concat(b2),
+                              |v1, v2| v1.concat(v2))
+        }
+        Notation::Weighted(note, extra) => {
+            let set: BoundSet<L> = lay(child_bounds, note, choices, width_fn);
+            set.weighted().map(|(bound, weight, val)| {
+                (bound, weight + extra, val)
+            }).collect::<BoundSet<L>>().inherit_truncation(&set)
+        }
+        Notation::MinWidth(cols, note) => {
+            let cols = *cols as Col;
+            let set: BoundSet<L> = lay(child_bounds, note, choices, width_fn);
+            set.weighted().map(|(bound, weight, val)| {
+                if bound.indent >= cols {
+                    return (bound, weight, val);
+                }
+                let padding = cols - bound.indent;
+                let spaces = " ".repeat(padding as usize);
+                (bound.concat(Bound::measured_literal(&spaces, padding, Style::plain())),
+                 weight,
+                 val.concat(L::measured_literal(&spaces, padding, Style::plain())))
+            }).collect::<BoundSet<L>>().inherit_truncation(&set)
+        }
+        Notation::WidthSwitch(threshold, wide, narrow) => {
+            let threshold = *threshold as Col;
+            let wide: BoundSet<L> = lay(child_bounds, wide, choices, width_fn);
+            let narrow: BoundSet<L> = lay(child_bounds, narrow, choices, width_fn);
+            wide.weighted().map(|(bound, weight, val)| {
+                (bound.reserve_width(threshold), weight, val.reserve_width(threshold))
+            }).chain(narrow.weighted().map(|(bound, weight, val)| {
+                (bound, weight + 1, val)
+            })).collect::<BoundSet<L>>().inherit_truncation(&wide).inherit_truncation(&narrow)
         }
         Notation::Choice(note1, note2) => {
-            let set1 = lay(child_bounds, note1);
-            let set2 = lay(child_bounds, note2);
-            set1.into_iter().chain(set2.into_iter()).collect()
+            let choice = *choices;
+            *choices += 1;
+            let set1: BoundSet<L> = lay(child_bounds, note1, choices, width_fn);
+            let set2: BoundSet<L> = lay(child_bounds, note2, choices, width_fn);
+            let decide = |branch| ChoiceDecision {
+                choice,
+                branch
+            };
+            set1.weighted().map(|(bound, weight, val)| (bound, weight, val.choose(decide(0))))
+                .chain(set2.weighted().map(|(bound, weight, val)| {
+                    (bound, weight, val.choose(decide(1)))
+                }))
+                .collect::<BoundSet<L>>()
+                .inherit_truncation(&set1)
+                .inherit_truncation(&set2)
+        }
+        Notation::Table(rows, sep) => {
+            // First measure the left column: it must be as wide as the
+            // widest left cell (in that cell's narrowest layout).
+            let lefts: Vec<BoundSet<L>> = rows.iter().map(|(left, _)| {
+                lay(child_bounds, left, choices, width_fn)
+            }).collect();
+            let width = lefts.iter().map(|set| {
+                set.into_iter().map(|(bound, _)| bound.width).min().unwrap_or(0)
+            }).max().unwrap_or(0);
+            // Then pad each left cell out to that width, and align the
+            // separators and right cells after it.
+            let mut table = None;
+            for (left, (_, right)) in lefts.iter().zip(rows) {
+                let padded = pad_to(left, width, 0);
+                let sep: BoundSet<L> = lay(child_bounds, sep, choices, width_fn);
+                let right: BoundSet<L> = lay(child_bounds, right, choices, width_fn);
+                let row = BoundSet::combine(&BoundSet::combine(&padded, &sep,
+                                                               |b1, b2| b1.concat(b2),
+                                                               |v1, v2| v1.concat(v2)),
+                                            &right,
+                                            |b1, b2| b1.concat(b2),
+                                            |v1, v2| v1.concat(v2));
+                table = Some(match table {
+                    None => row,
+                    Some(table) => BoundSet::combine(&table, &row,
+                                                     |b1, b2| b1.vert(b2),
+                                                     |v1, v2| v1.vert(v2))
+                });
+            }
+            table.unwrap_or_else(|| BoundSet::singleton(Bound::empty(), L::empty()))
+        }
+        Notation::Grid(rows) => {
+            // Each column but the last must be as wide as its widest cell
+            // (in that cell's narrowest layout).
+            let cells: Vec<Vec<BoundSet<L>>> = rows.iter().map(|row| {
+                row.iter().map(|cell| lay(child_bounds, cell, choices, width_fn)).collect()
+            }).collect();
+            let num_cols = rows.iter().map(Vec::len).max().unwrap_or(0);
+            let widths: Vec<Col> = (0..num_cols).map(|col| {
+                cells.iter().filter_map(|row| row.get(col)).map(|set| {
+                    set.into_iter().map(|(bound, _)| bound.width).min().unwrap_or(0)
+                }).max().unwrap_or(0)
+            }).collect();
+            // Then pad each cell out to its column's width, plus a space
+            // to separate it from the next one.
+            let mut grid = None;
+            for row in &cells {
+                let mut line = None;
+                for (col, cell) in row.iter().enumerate() {
+                    let cell = if col + 1 == row.len() {
+                        cell.clone()
+                    } else {
+                        pad_to(cell, widths[col], 1)
+                    };
+                    line = Some(match line {
+                        None => cell,
+                        Some(line) => BoundSet::combine(&line, &cell,
+                                                        |b1, b2| b1.concat(b2),
+                                                        |v1, v2| v1.concat(v2))
+                    });
+                }
+                let line = line.unwrap_or_else(|| BoundSet::singleton(Bound::empty(), L::empty()));
+                grid = Some(match grid {
+                    None => line,
+                    Some(grid) => BoundSet::combine(&grid, &line,
+                                                    |b1, b2| b1.vert(b2),
+                                                    |v1, v2| v1.vert(v2))
+                });
+            }
+            grid.unwrap_or_else(|| BoundSet::singleton(Bound::empty(), L::empty()))
         }
         Notation::IfEmptyText(_, _) => panic!("lay_out: unexpected IfEmptyText"),
+        Notation::MapText(_, _) => panic!("lay_out: unexpected MapText"),
+        Notation::Count(..) => panic!("lay_out: unexpected Count"),
+        Notation::ChildParens(..) => panic!("lay_out: unexpected ChildParens"),
+        Notation::StreamedRep(repeat, len) => {
+            // Lay out one element at a time, from last to first, folding
+            // each into the BoundSet of the elements after it. This
+            // associates the same way as the expanded sequence would, so
+            // it gives exactly the same layouts. Only one element's
+            // notation and BoundSet, plus the accumulated BoundSet, are
+            // alive at any time.
+            //
+            // Each element's `Choice`s are numbered as if it had been
+            // laid out in order.
+            let len = *len;
+            let first_choice = |i: usize| match i {
+                0 => *choices,
+                _ => *choices + repeat.first.count_choices()
+                    + (i - 1) * repeat.middle.count_choices()
+            };
+            let lay_element = |i: usize| -> BoundSet<L> {
+                lay(child_bounds, &repeat.element(i, len), &mut first_choice(i), width_fn)
+            };
+            let mut set = lay_element(len - 1);
+            for i in (0 .. len - 1).rev() {
+                set = BoundSet::combine(&lay_element(i), &set,
+                                        |b1, b2| b1.concat(b2),
+                                        |v1, v2| v1.concat(v2));
+            }
+            *choices = first_choice(len - 1) + repeat.last.count_choices();
+            set
+        }
+        Notation::JoinPresent(sep) => {
+            // Every separator is laid out with the same `Choice` ids.
+            let first_choice = *choices;
+            *choices += sep.count_choices();
+            let mut present = (0 .. child_bounds.children.len()).rev()
+                .filter(|&i| !child_bounds.children[i].is_blank());
+            let last = match present.next() {
+                None => return BoundSet::singleton(Bound::empty(), L::empty()),
+                Some(last) => last
+            };
+            let mut set = lay(child_bounds, &Notation::Child(last), choices, width_fn);
+            for i in present {
+                let sep: BoundSet<L> = lay(child_bounds, sep, &mut first_choice.clone(), width_fn);
+                let elem = BoundSet::combine(&lay(child_bounds, &Notation::Child(i),
+                                                  choices, width_fn),
+                                             &sep,
+                                             |b1, b2| b1.concat(b2),
+                                             |v1, v2| v1.concat(v2));
+                set = BoundSet::combine(&elem, &set,
+                                        |b1, b2| b1.concat(b2),
+                                        |v1, v2| v1.concat(v2));
+            }
+            set
+        }
         Notation::Rep(_) => panic!("lay_out: unexpected Repeat"),
         Notation::Star   => panic!("lay_out: unexpected Star")
     }
@@ -1,16 +1,21 @@
 mod boundset;
 mod layout;
 
-pub use self::layout::{Lay, LayoutRegion, Layout,
-                       compute_bounds, compute_layouts, text_bounds,
+pub use self::layout::{Lay, LayoutRegion, Layout, ChoiceDecision, LayoutStrategy,
+                       LayoutError, compute_bounds, compute_layouts, text_bounds,
+                       try_compute_bounds, try_compute_layouts,
+                       try_compute_bounds_to_depth, try_compute_layouts_to_depth,
+                       MAX_LAYOUT_DEPTH, MAX_LAYOUT_CHOICES,
+                       compute_bounds_with, compute_layouts_with, text_bounds_with,
                        Bounds, Layouts};
+pub use self::boundset::MAX_BOUND_CANDIDATES;
 
 
 #[cfg(test)]
 mod layout_tests {
     use super::*;
     use crate::style::Style;
-    use crate::geometry::Bound;
+    use crate::geometry::{Bound, Pos, CharCount};
     use crate::notation::*;
 
     impl Notation {
@@ -55,10 +60,66 @@ mod layout_tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_bound_from_multiline() {
+        let sty = Style::plain();
+        let actual = Bound::from_multiline("abc\nSchrödinger\nme too");
+        let expected = Bound {
+            width:  11,
+            indent: 6,
+            height: 2
+        };
+        assert_eq!(actual, expected);
+        assert_eq!(Bound::from_multiline("abc"), Bound::literal("abc", sty));
+        assert_eq!(Bound::from_multiline(""), Bound::empty());
+    }
+
+    #[test]
+    fn test_style_does_not_affect_width() {
+        use crate::style::{Color, Emph, Shade};
+
+        let plain = Style::plain();
+        let fancy = Style::new(Color::Base08, Emph::underlined(), Shade(1), true);
+        assert_eq!(Bound::literal("abc", fancy), Bound::literal("abc", plain));
+        assert_eq!(LayoutRegion::literal("abc", fancy).region,
+                   LayoutRegion::literal("abc", plain).region);
+
+        let syn = literal("ab", fancy) + text(fancy) + literal("c\nd", fancy);
+        let plain_syn = lit("ab") + text(plain) + lit("c\nd");
+        let text = || vec!(text_bounds("xyz"));
+        assert_eq!(syn.bound(text(), 3).first(), plain_syn.bound(text(), 3).first());
+        assert_eq!(syn.layouts(text(), 3).fit_width(80).region,
+                   plain_syn.layouts(text(), 3).fit_width(80).region);
+    }
+
+    #[test]
+    fn test_multiline_literal() {
+        let syn = lit("x") + lit("ab\ncde\nf") + lit("y");
+        let lay = syn.layouts(vec!(), 0).fit_width(80);
+        assert_eq!(format!("{:?}", lay), "xab\n cde\n fy");
+        let bound = syn.bound(vec!(), 0).first();
+        assert_eq!(bound, Bound{ width: 4, indent: 3, height: 2 });
+        assert!(!no_wrap(lit("ab\ncd")).bound(vec!(), 0).fits_width(80));
+    }
+
     fn lit(s: &str) -> Notation {
         literal(s, Style::plain())
     }
 
+    /// The position, text, and style of every literal in `lay`.
+    fn literals(lay: &LayoutRegion, out: &mut Vec<(Pos, String, Style)>) {
+        match &lay.layout {
+            Layout::Literal(s, style) => out.push((lay.region.pos, s.clone(), *style)),
+            Layout::Concat(lay1, lay2)
+                | Layout::Horz(lay1, lay2)
+                | Layout::Vert(lay1, lay2) => {
+                    literals(lay1, out);
+                    literals(lay2, out);
+                }
+            _ => ()
+        }
+    }
+
     fn example_notation() -> Notation {
         lit("if ") + lit("true")
             ^ lit("  ")
@@ -133,6 +194,360 @@ mod layout_tests {
         assert_eq!(format!("{:?}", lay), "abcdef\n   g");
     }
 
+    #[test]
+    fn test_choice_trace() {
+        let syn = (lit("abc") + lit("def") | lit("abc") ^ lit("def"))
+            + (lit("g") | lit("h"));
+        let wide = syn.layouts(vec!(), 0).fit_width(80);
+        let narrow = syn.layouts(vec!(), 0).fit_width(4);
+        assert_eq!(format!("{:?}", wide), "abcdefg");
+        assert_eq!(format!("{:?}", narrow), "abc\ndefg");
+        assert_eq!(wide.choice_trace(),
+                   vec!(ChoiceDecision{ choice: 0, branch: 0 },
+                        ChoiceDecision{ choice: 1, branch: 0 }));
+        assert_eq!(narrow.choice_trace(),
+                   vec!(ChoiceDecision{ choice: 0, branch: 1 },
+                        ChoiceDecision{ choice: 1, branch: 0 }));
+    }
+
+    #[test]
+    fn test_widest_fit() {
+        let syn = (lit("ab") ^ lit("cd")) | lit("abcd");
+        let lays = syn.layouts(vec!(), 0);
+        assert_eq!(format!("{:?}", lays.fit_width(80)), "ab\ncd");
+        assert_eq!(format!("{:?}", lays.widest_fit(80)), "abcd");
+        assert_eq!(format!("{:?}", lays.widest_fit(3)), "ab\ncd");
+        // Ties go to the shortest
+        let syn = (lit("abc") ^ lit("d") ^ lit("e")) | (lit("abc") ^ lit("de"));
+        let lays = syn.layouts(vec!(), 0);
+        assert_eq!(format!("{:?}", lays.fit_width(80)), "abc\nd\ne");
+        assert_eq!(format!("{:?}", lays.widest_fit(80)), "abc\nde");
+    }
+
+    #[test]
+    fn test_max_bound_candidates() {
+        // Without a cap, this would have over 800 candidate bounds.
+        let note = |i: u32| {
+            weighted(lit("abcd"), i % 5)
+                | weighted(lit("ab") ^ lit("cd"), i * 7 % 11)
+                | (lit("a") ^ lit("bcd"))
+        };
+        let mut syn = note(0);
+        for i in 0..40 {
+            syn = syn + note(i);
+        }
+        let bounds = syn.bound(vec!(), 0);
+        assert_eq!(bounds.len(), MAX_BOUND_CANDIDATES);
+        assert!(bounds.is_truncated());
+        let min_width = bounds.min_width().unwrap();
+        let lays = syn.layouts(vec!(), 0);
+        assert!(lays.is_truncated());
+        lays.fit_width(min_width);
+        // Truncation is remembered by everything built from the Bounds.
+        let lays = compute_layouts(&vec!(bounds), &(lit("x") + child(0)).expand(1));
+        assert!(lays.is_truncated());
+
+        // Below the cap, nothing is discarded, so the layout picked is
+        // the preferred one that fits.
+        let note = || lit("abcd") | (lit("ab") ^ lit("cd"));
+        let mut syn = note();
+        for _ in 0..3 {
+            syn = syn + note();
+        }
+        let bounds = syn.bound(vec!(), 0);
+        assert!(bounds.len() < MAX_BOUND_CANDIDATES);
+        assert!(!bounds.is_truncated());
+        let lays = syn.layouts(vec!(), 0);
+        assert!(!lays.is_truncated());
+        assert_eq!(format!("{:?}", lays.fit_width(16)), "abcdabcdabcdabcd");
+        assert_eq!(format!("{:?}", lays.fit_width(10)),
+                   "abcdab\n    cdab\n      cdab\n        cd");
+    }
+
+    #[test]
+    fn test_width_fn() {
+        use crate::geometry::WidthFn;
+
+        struct DoubleWidth;
+        impl WidthFn for DoubleWidth {
+            fn width(&self, s: &str) -> usize {
+                2 * s.chars().count()
+            }
+        }
+
+        let syn = (lit("abc") | lit("a") ^ lit("bc")).expand(0);
+        let lay = compute_layouts(&vec!(), &syn).fit_width(4);
+        assert_eq!(format!("{:?}", lay), "abc");
+        let lay = compute_layouts_with(&[], None, &syn, &DoubleWidth).fit_width(4);
+        assert_eq!(format!("{:?}", lay), "a\nbc");
+        let bound = compute_bounds_with(&[], None, &syn, &DoubleWidth).first();
+        assert_eq!(bound, Bound{ width: 6, indent: 6, height: 0 });
+        let text = text_bounds_with("a\nbcd", &DoubleWidth).first();
+        assert_eq!(text, Bound{ width: 6, indent: 6, height: 1 });
+    }
+
+    #[test]
+    fn test_bounds_fast_path() {
+        use std::cell::Cell;
+        use crate::geometry::WidthFn;
+
+        // Count how many lines get measured.
+        struct Counter(Cell<usize>);
+        impl WidthFn for Counter {
+            fn width(&self, s: &str) -> usize {
+                self.0.set(self.0.get() + 1);
+                s.chars().count()
+            }
+        }
+
+        let syn = ((lit("abc") + lit("de")) | (lit("abc") ^ lit("de\nf"))).expand(0);
+        let counter = Counter(Cell::new(0));
+        let bounds = compute_bounds_with(&[], None, &syn, &counter);
+        assert_eq!(counter.0.get(), 5);
+
+        let counter = Counter(Cell::new(0));
+        let layouts = compute_layouts_with(&[], None, &syn, &counter);
+        assert_eq!(counter.0.get(), 10);
+
+        // Both paths agree on the shape.
+        assert_eq!(bounds.len(), layouts.len());
+        assert_eq!(bounds.min_width(), layouts.min_width());
+        assert_eq!(bounds.first(), layouts.fit_width(80).region.bound);
+    }
+
+    #[test]
+    fn test_line_numbers() {
+        let syn = (lit("abc") ^ lit("de") ^ lit("f")).expand(0);
+        let lay = compute_layouts(&vec!(), &syn).fit_width(80);
+        let gutter_style = Style::color(crate::style::Color::Base03);
+        let numbered = lay.with_line_numbers(1, gutter_style);
+        assert_eq!(numbered.region.bound, Bound{ width: 5, indent: 3, height: 2 });
+
+        let mut out = vec!();
+        literals(&numbered, &mut out);
+        let pos = |row, col| Pos{ row, col };
+        assert_eq!(out, vec!(
+            (pos(0, 0), "1 ".to_string(), gutter_style),
+            (pos(1, 0), "2 ".to_string(), gutter_style),
+            (pos(2, 0), "3 ".to_string(), gutter_style),
+            (pos(0, 2), "abc".to_string(), Style::plain()),
+            (pos(1, 2), "de".to_string(), Style::plain()),
+            (pos(2, 2), "f".to_string(), Style::plain())));
+
+        // Numbers are right-aligned to the widest one.
+        let numbered = lay.with_line_numbers(9, gutter_style);
+        out.clear();
+        literals(&numbered, &mut out);
+        assert_eq!(out[0].1, " 9 ");
+        assert_eq!(out[2].1, "11 ");
+        assert_eq!(numbered.region.bound.width, 6);
+    }
+
+    #[test]
+    fn test_coalesce_literals() {
+        let syn = lit("f") + lit("(") + (lit("") + child(0))
+            + lit(",") + lit(" ") + child(1) + lit(")");
+        let coalesced = syn.coalesce_literals();
+        match &coalesced {
+            Notation::Concat(box Notation::Literal(s, _), _) => assert_eq!(s, "f("),
+            _ => panic!("expected a concatenation")
+        }
+        assert_eq!(format!("{:?}", coalesced),
+                   format!("{:?}", lit("f(") + (child(0) + (lit(", ") + (child(1) + lit(")"))))));
+
+        // Literals of different styles are kept separate.
+        let styled = lit("a") + literal("b", Style::color(crate::style::Color::Base08));
+        assert_eq!(format!("{:?}", styled.coalesce_literals()), format!("{:?}", styled));
+
+        // Rendering is unchanged.
+        let syn = choice(syn, vert(lit("f(") + child(0),
+                                   lit(" ") + lit(" ") + child(1) + lit(")")));
+        let children = || vec!(text_bounds("abc"), text_bounds("de"));
+        for width in &[5, 9, 10, 80] {
+            assert_eq!(format!("{:?}", syn.layouts(children(), 0).fit_width(*width)),
+                       format!("{:?}", syn.coalesce_literals()
+                               .layouts(children(), 0).fit_width(*width)));
+        }
+    }
+
+    #[test]
+    fn test_nest() {
+        let block = |body| vert(nest(2, vert(lit("{"), body)), lit("}"));
+
+        let syn = block(vert(lit("a;"), lit("b;")));
+        let lay = syn.layouts(vec!(), 0).fit_width(80);
+        assert_eq!(format!("{:?}", lay), "{\n  a;\n  b;\n}");
+
+        let syn = block(vert(lit("a;"), block(lit("c;"))));
+        let lay = syn.layouts(vec!(), 0).fit_width(80);
+        assert_eq!(format!("{:?}", lay), "{\n  a;\n  {\n    c;\n  }\n}");
+
+        // Breaks after a `Concat` are aligned to the end of its left side.
+        let syn = block(lit("x = ") + block(lit("c;")));
+        let lay = syn.layouts(vec!(), 0).fit_width(80);
+        assert_eq!(format!("{:?}", lay), "{\n  x = {\n        c;\n      }\n}");
+
+        let syn = nest(2, vert(lit("a"), nest(2, vert(lit("b"), lit("c")))));
+        let lay = syn.layouts(vec!(), 0).fit_width(80);
+        assert_eq!(format!("{:?}", lay), "a\n  b\n    c");
+        let bound = syn.bound(vec!(), 0).first();
+        assert_eq!(bound, Bound{ width: 5, indent: 5, height: 2 });
+    }
+
+    #[test]
+    fn test_width_switch() {
+        let switch = || width_switch(6, lit("a b"), vert(lit("a"), lit("b")));
+        let lay = |prefix: &str| {
+            let syn = lit(prefix) + switch();
+            format!("{:?}", syn.layouts(vec!(), 0).fit_width(10))
+        };
+        // Starting at column 4 leaves 6 columns, so it's wide.
+        assert_eq!(lay("xxxx"), "xxxxa b");
+        // Starting at column 5 leaves only 5, so it's narrow, even though
+        // the wide version would fit.
+        assert_eq!(lay("xxxxx"), "xxxxxa\n     b");
+
+        let syn = vert(lit("xxxx") + switch(), lit("yyyyy") + switch());
+        let lay = syn.layouts(vec!(), 0).fit_width(10);
+        assert_eq!(format!("{:?}", lay), "xxxxa b\nyyyyya\n     b");
+        assert_eq!(lay.region.bound, Bound{ width: 7, indent: 6, height: 2 });
+
+        // The reserved columns are counted from where it starts, and what
+        // follows it on the line doesn't move.
+        let syn = lit("xxxx") + switch() + lit("zz");
+        assert_eq!(syn.bound(vec!(), 0).first(), Bound{ width: 10, indent: 9, height: 0 });
+        let lay = syn.layouts(vec!(), 0).fit_width(10);
+        assert_eq!(format!("{:?}", lay), "xxxxa bzz");
+        assert_eq!(lay.region.bound, Bound{ width: 9, indent: 9, height: 0 });
+    }
+
+    #[test]
+    fn test_min_width() {
+        let syn = min_width(10, lit("short"));
+        assert_eq!(syn.bound(vec!(), 0).first(), Bound{ width: 10, indent: 10, height: 0 });
+        let lay = (syn + lit("x")).layouts(vec!(), 0).fit_width(80);
+        assert_eq!(format!("{:?}", lay), "short     x");
+
+        // Content that is already wide enough is left alone.
+        let syn = min_width(3, lit("longer"));
+        assert_eq!(syn.bound(vec!(), 0).first(), Bound{ width: 6, indent: 6, height: 0 });
+
+        // Only the last line is padded.
+        let syn = min_width(4, lit("abcdef") ^ lit("x"));
+        assert_eq!(syn.bound(vec!(), 0).first(), Bound{ width: 6, indent: 4, height: 1 });
+    }
+
+    #[test]
+    fn test_vcat() {
+        let syn = vcat(vec!(lit("ab"), lit("c") + lit("de"), lit("f")));
+        let lay = syn.layouts(vec!(), 0).fit_width(80);
+        assert_eq!(format!("{:?}", lay), "ab\ncde\nf");
+        let bound = syn.bound(vec!(), 0).first();
+        assert_eq!(bound, Bound{ width: 3, indent: 1, height: 2 });
+
+        let bound = vcat(vec!(lit("ab"))).bound(vec!(), 0).first();
+        assert_eq!(bound, Bound{ width: 2, indent: 2, height: 0 });
+        let bound = vcat(vec!()).bound(vec!(), 0).first();
+        assert_eq!(bound, Bound::empty());
+    }
+
+    #[test]
+    fn test_table() {
+        let syn = table(vec!((lit("a"), lit("1")),
+                             (lit("bbb"), lit("22")),
+                             (lit("cc"), lit("3"))),
+                        lit(": "));
+        let lay = syn.layouts(vec!(), 0).fit_width(80);
+        assert_eq!(format!("{:?}", lay), "a  : 1\nbbb: 22\ncc : 3");
+        let bound = syn.bound(vec!(), 0).first();
+        assert_eq!(bound, Bound{ width: 7, indent: 6, height: 2 });
+    }
+
+    #[test]
+    fn test_grid() {
+        let syn = grid(vec!(vec!(lit("a"), lit("bbb"), lit("c")),
+                            vec!(lit("dd"), lit("e"), lit("ffff"))));
+        let lay = syn.layouts(vec!(), 0).fit_width(80);
+        assert_eq!(format!("{:?}", lay), "a  bbb c\ndd e   ffff");
+        let bound = syn.bound(vec!(), 0).first();
+        assert_eq!(bound, Bound{ width: 11, indent: 11, height: 1 });
+
+        // A cell that wraps makes its row taller. The rest of the row
+        // follows its last line, still aligned.
+        let syn = grid(vec!(vec!(lit("x") ^ lit("yy"), lit("1")),
+                            vec!(lit("zzz"), lit("2"))));
+        let lay = syn.layouts(vec!(), 0).fit_width(80);
+        assert_eq!(format!("{:?}", lay), "x\nyy  1\nzzz 2");
+
+        // A column is only as wide as its cells' narrowest layouts, so
+        // here the wider layout of the first cell does not fit.
+        let syn = grid(vec!(vec!(lit("ab") | (lit("a") ^ lit("b")), lit("1")),
+                            vec!(lit("c"), lit("2"))));
+        let lay = syn.layouts(vec!(), 0).fit_width(80);
+        assert_eq!(format!("{:?}", lay), "a\nb 1\nc 2");
+    }
+
+    #[test]
+    fn test_weighted_choice() {
+        // Both fit, so a plain Choice takes the first
+        let lays = choice(lit("a") ^ lit("b"), lit("ab")).layouts(vec!(), 0);
+        assert_eq!(format!("{:?}", lays.fit_width(80)), "a\nb");
+        // But a weighted choice takes the lighter one
+        let syn = weighted_choice(lit("a") ^ lit("b"), 1, lit("ab"), 0);
+        let lays = syn.layouts(vec!(), 0);
+        assert_eq!(format!("{:?}", lays.fit_width(80)), "ab");
+        assert_eq!(format!("{:?}", lays.fit_width(1)), "a\nb");
+        // Children's weights count towards their parent's. Here, two
+        // narrow children side by side would fit in width 2, but two wide
+        // children stacked weigh less.
+        let ab = syn.bound(vec!(), 0);
+        let syn = choice(child(0) + child(1), child(0) ^ child(1));
+        let lays = syn.layouts(vec!(ab.clone(), ab), 2);
+        assert_eq!(format!("{:?}", lays.fit_width(80)), "0011");
+        assert_eq!(format!("{:?}", lays.fit_width(2)), "00\n11");
+        assert_eq!(format!("{:?}", lays.fit_width(1)), "0\n0\n1\n1");
+    }
+
+    #[test]
+    fn test_softline() {
+        let syn = lit("a") + softline() + lit("b");
+        let lays = syn.layouts(vec!(), 0);
+        assert_eq!(format!("{:?}", lays.fit_width(80)), "a b");
+        assert_eq!(format!("{:?}", lays.fit_width(2)), "a\nb");
+
+        let syn = lit("a") + softbreak() + lit("b");
+        let lays = syn.layouts(vec!(), 0);
+        assert_eq!(format!("{:?}", lays.fit_width(80)), "ab");
+        assert_eq!(format!("{:?}", lays.fit_width(1)), "a\nb");
+
+        // The line breaks are independent
+        let syn = lit("a") + softline() + lit("bb") + softline() + lit("c");
+        let lays = syn.layouts(vec!(), 0);
+        assert_eq!(format!("{:?}", lays.fit_width(80)), "a bb c");
+        assert_eq!(format!("{:?}", lays.fit_width(4)), "a bb\nc");
+        assert_eq!(format!("{:?}", lays.fit_width(3)), "a\nbb\nc");
+
+        // They break back to the start of the chain, however it's
+        // parenthesized, unless they're in a group.
+        let syn = lit("x = ") + (lit("aaa") + softline() + lit("bbb"));
+        assert_eq!(format!("{:?}", syn.layouts(vec!(), 0).fit_width(8)), "x = aaa\nbbb");
+        let syn = lit("x = ") + group(lit("aaa") + softline() + lit("bbb"));
+        assert_eq!(format!("{:?}", syn.layouts(vec!(), 0).fit_width(8)), "x = aaa\n    bbb");
+        let syn = nest(2, lit("aaa") + softline() + lit("bbb"));
+        assert_eq!(format!("{:?}", syn.layouts(vec!(), 0).fit_width(5)), "aaa\n  bbb");
+
+        // Each one is a single choice, and the parts between them are
+        // shared by both of its branches.
+        let mut syn = lit("x");
+        for _ in 0..20 {
+            syn = syn + softline() + lit("x");
+        }
+        assert_eq!(syn.count_choices(), 20);
+        let lays = syn.layouts(vec!(), 0);
+        let lay = lays.try_fit_bound_with(Bound::infinite_scroll(9), LayoutStrategy::Optimal);
+        assert_eq!(format!("{:?}", lay.unwrap()), ["x x x x x"; 4].join("\n") + "\nx");
+    }
+
     #[test]
     fn test_expand_notation() {
         let r = (lit("abc") ^ lit("de")).bound(vec!(), 0);
@@ -161,4 +576,343 @@ mod layout_tests {
         assert_eq!(format!("{:?}", four),
                    "[000\n 00,\n 111\n 11,\n 222\n 22,\n 333\n 33]");
     }
+
+    #[test]
+    fn test_elide_middle() {
+        let lines: Vec<Notation> = (0..10).map(|i| lit(&format!("line{}", i))).collect();
+        let syn = vcat(lines).expand(0);
+        let lay = compute_layouts(&vec!(), &syn).fit_width(80);
+        assert_eq!(lay.region.height(), 9);
+        let marker_style = Style::color(crate::style::Color::Base03);
+
+        let elided = lay.elide_middle(5, marker_style);
+        assert_eq!(elided.region.bound, Bound{ width: 18, indent: 5, height: 4 });
+        let mut out = vec!();
+        literals(&elided, &mut out);
+        let pos = |row, col| Pos{ row, col };
+        assert_eq!(out, vec!(
+            (pos(0, 0), "line0".to_string(), Style::plain()),
+            (pos(1, 0), "line1".to_string(), Style::plain()),
+            (pos(2, 0), "… 6 lines hidden …".to_string(), marker_style),
+            (pos(3, 0), "line8".to_string(), Style::plain()),
+            (pos(4, 0), "line9".to_string(), Style::plain())));
+
+        // Short enough layouts are left alone.
+        assert_eq!(lay.elide_middle(10, marker_style), lay);
+    }
+
+    #[test]
+    fn test_joined() {
+        // A minus sign that could be followed by a line break.
+        let minus = || lit("-") | (lit("-") ^ empty());
+        let space = || lit(" ");
+
+        let syn = lit("foo") + softline() + joined(minus(), lit("x")).expand(0);
+        let lay = compute_layouts(&vec!(), &syn).fit_width(4);
+        let mut out = vec!();
+        literals(&lay, &mut out);
+        let rows: Vec<(u32, String)> = out.into_iter()
+            .filter(|(_, s, _)| !s.is_empty())
+            .map(|(pos, s, _)| (pos.row, s))
+            .collect();
+        assert_eq!(rows, vec!(
+            (0, "foo".to_string()),
+            (1, "-".to_string()),
+            (1, " ".to_string()),
+            (1, "x".to_string())));
+
+        // Without `joined`, the minus may be split from its operand.
+        let narrow = Bound::infinite_scroll(2);
+        let split = (minus() + space() + lit("x")).expand(0);
+        assert!(compute_layouts(&vec!(), &split).try_fit_bound(narrow).is_some());
+        let unsplit = joined(minus(), lit("x")).expand(0);
+        assert!(compute_layouts(&vec!(), &unsplit).try_fit_bound(narrow).is_none());
+    }
+
+    #[test]
+    fn test_layout_strategy() {
+        let syn = ((lit("a") ^ lit("b")) | lit("ab")).expand(0);
+        let lays = compute_layouts(&vec!(), &syn);
+        let space = Bound::infinite_scroll(80);
+        // Greedy takes the first choice, since it fits.
+        let greedy = lays.try_fit_bound_with(space, LayoutStrategy::Greedy).unwrap();
+        assert_eq!(format!("{:?}", greedy), "a\nb");
+        assert_eq!(greedy, lays.fit_bound(space));
+        // Optimal takes whichever is shortest.
+        let optimal = lays.try_fit_bound_with(space, LayoutStrategy::Optimal).unwrap();
+        assert_eq!(format!("{:?}", optimal), "ab");
+        // They agree when only one fits.
+        let narrow = Bound::infinite_scroll(1);
+        assert_eq!(lays.try_fit_bound_with(narrow, LayoutStrategy::Optimal),
+                   lays.try_fit_bound_with(narrow, LayoutStrategy::Greedy));
+        assert!(lays.try_fit_bound_with(Bound::empty(), LayoutStrategy::Optimal).is_none());
+    }
+
+    #[test]
+    fn test_optional_child() {
+        let syn = (lit("if") + optional_child(0, lit(" else ") + child(0))
+                   + (lit(";") | lit(";;"))).expand(0);
+        let present = compute_layouts(&vec!(text_bounds("x")), &syn).fit_width(80);
+        assert_eq!(format!("{:?}", present), "if else 0;");
+        let missing = compute_layouts(&vec!(), &syn).fit_width(80);
+        assert_eq!(format!("{:?}", missing), "if;");
+        let empty = compute_layouts(&vec!(Bounds::empty()), &syn).fit_width(80);
+        assert_eq!(format!("{:?}", empty), "if;");
+        assert_eq!(compute_bounds(&vec!(), &syn).first(),
+                   Bound{ width: 3, indent: 3, height: 0 });
+        // The trailing choice has the same id either way.
+        assert_eq!(missing.choice_trace(), present.choice_trace());
+
+        let syn = optional_child(1, child(1)).expand(0);
+        assert_eq!(format!("{:?}", compute_layouts(&vec!(text_bounds("x")), &syn).fit_width(80)), "");
+    }
+
+    #[test]
+    fn test_streamed_repeat() {
+        let list = repeat(Repeat{
+            empty:  lit("[]"),
+            lone:   lit("[") + star() + lit("]"),
+            first:  (lit("[") + star() + lit(",")) ^ empty(),
+            middle: (star() + lit(", ")) | ((star() + lit(",")) ^ empty()),
+            last:   star() + lit("]")
+        });
+        let children = |len| -> Vec<Bounds> { (0..len).map(|_| text_bounds("xyz")).collect() };
+        let count_children = |note: &Notation| format!("{:?}", note).matches("Child(").count();
+
+        // The eager expansion mentions every child; the streamed one
+        // doesn't grow with the length of the list.
+        assert!(count_children(&list.expand(1000)) >= 1000);
+        let streamed = list.expand_streaming(1000, &[]);
+        assert_eq!(count_children(&streamed), 0);
+        assert_eq!(format!("{:?}", streamed).len(),
+                   format!("{:?}", list.expand_streaming(9999, &[])).len());
+
+        // At its narrowest, the list has one element per line.
+        let bounds = compute_bounds(&children(1000), &streamed);
+        assert_eq!(bounds.min_width(), Some(5));
+        assert!(!bounds.fits_width(4));
+
+        // Same layouts as the eager expansion.
+        let eager = compute_layouts(&children(30), &list.expand(30));
+        let streamed = compute_layouts(&children(30), &list.expand_streaming(30, &[]));
+        for width in &[5, 20, 80] {
+            let lay = streamed.fit_width(*width);
+            assert_eq!(format!("{:?}", lay), format!("{:?}", eager.fit_width(*width)));
+            assert_eq!(lay.choice_trace(), eager.fit_width(*width).choice_trace());
+        }
+        assert_eq!(streamed.fit_width(5).region.bound,
+                   Bound{ width: 5, indent: 4, height: 29 });
+    }
+
+    #[test]
+    fn test_overflow_policy() {
+        // 17 columns wide on one line, or 10 if wrapped.
+        let content = || lit("aaaa") + group(lit("bbbbbb") + softline() + lit("cccccc"));
+        let pane = Bound::infinite_scroll(12);
+        let roomy = Bound::infinite_scroll(20);
+        let lays = |policy| compute_layouts(&vec!(), &no_wrap_with(content(), policy).expand(0));
+
+        let strict = lays(OverflowPolicy::Strict);
+        assert!(strict.try_fit_bound(pane).is_none());
+        assert_eq!(format!("{:?}", strict.fit_bound(roomy)), "aaaabbbbbb cccccc");
+
+        let fallback = lays(OverflowPolicy::Fallback);
+        let lay = fallback.fit_bound(pane);
+        assert_eq!(format!("{:?}", lay), "aaaabbbbbb\n    cccccc");
+        assert_eq!(lay.region.bound.height, 1);
+        assert_eq!(format!("{:?}", fallback.fit_bound(roomy)), "aaaabbbbbb cccccc");
+
+        let clip = lays(OverflowPolicy::Clip);
+        let lay = clip.fit_bound(pane);
+        assert_eq!(format!("{:?}", lay), "aaaabbbbbb cccccc");
+        // It fits, but keeps its real width.
+        assert_eq!(lay.region.bound, Bound{ width: 17, indent: 17, height: 0 });
+        let lay = clip.fit_bound(roomy);
+        assert_eq!(lay.region.bound, Bound{ width: 17, indent: 17, height: 0 });
+
+        // What follows clipped text starts after it, instead of over it.
+        let syn = (no_wrap_with(content(), OverflowPolicy::Clip) + lit("!")) ^ lit("next");
+        let lay = compute_layouts(&vec!(), &syn.expand(0)).fit_bound(pane);
+        assert_eq!(format!("{:?}", lay), "aaaabbbbbb cccccc!\nnext");
+        assert_eq!(lay.region.bound, Bound{ width: 18, indent: 4, height: 1 });
+        match &lay.layout {
+            Layout::Vert(line, _) => match &line.layout {
+                Layout::Concat(_, bang) => assert_eq!(bang.region.pos, Pos{ row: 0, col: 17 }),
+                _ => panic!("expected a concat")
+            },
+            _ => panic!("expected a vert")
+        }
+    }
+
+    #[test]
+    fn test_join_present() {
+        let syn = (lit("(") + join_present(lit(", ")) + lit(")")).expand(5);
+        let show = |children: Vec<Bounds>| {
+            format!("{:?}", compute_layouts(&children, &syn).fit_width(80))
+        };
+        let x = || text_bounds("x");
+        let blank = || text_bounds("");
+        assert_eq!(show(vec!(x(), x(), x())), "(0, 1, 2)");
+        assert_eq!(show(vec!(blank(), x(), Bounds::empty(), x(), blank())), "(1, 3)");
+        assert_eq!(show(vec!(blank(), blank(), x())), "(2)");
+        assert_eq!(show(vec!(blank(), Bounds::empty())), "()");
+        assert_eq!(show(vec!()), "()");
+
+        // Each separator is chosen separately, but they share choice ids.
+        let syn = join_present(lit(", ") | (lit(",") ^ empty())).expand(3);
+        let lay = compute_layouts(&vec!(x(), blank(), x(), x()), &syn).fit_width(5);
+        assert_eq!(format!("{:?}", lay), "0, 2,\n    3");
+        let mut trace: Vec<_> = lay.choice_trace().iter()
+            .map(|decision| (decision.choice, decision.branch)).collect();
+        trace.sort();
+        assert_eq!(trace, vec!((0, 0), (0, 1)));
+        assert_eq!((syn + (lit(";") | lit(";;"))).count_choices(), 2);
+    }
+
+    #[test]
+    fn test_join_present_mixed() {
+        // A mixed node's text is not one of its children.
+        let syn = (text(Style::plain()) + lit("(") + join_present(lit(", ")) + lit(")"))
+            .expand(2);
+        let (x, f) = (text_bounds("x"), text_bounds("f"));
+        let lay = compute_layouts_with(&[x.clone(), x.clone()], Some(&f), &syn, &CharCount);
+        assert_eq!(format!("{:?}", lay.fit_width(80)), "t(0, 1)");
+        let lay = compute_layouts(&vec!(x.clone(), x.clone(), f.clone()), &syn);
+        assert_eq!(format!("{:?}", lay.fit_width(80)), "t(0, 1)");
+
+        // Nor is it ever taken for a present child, or counted as one.
+        let syn = text(Style::plain()) + optional_child(1, lit("!"));
+        let lay = compute_layouts(&vec!(x.clone(), f.clone()), &syn);
+        assert_eq!(format!("{:?}", lay.fit_width(80)), "t");
+        let syn = text(Style::plain()) + child(1);
+        assert_eq!(try_compute_layouts(&vec!(x, f), &syn).err(),
+                   Some(LayoutError::MissingChild { index: 1 }));
+    }
+
+    #[test]
+    fn test_bound_indent_marker() {
+        let bound = (lit("abc") ^ lit("de")).bound(vec!(), 0).first();
+        assert_eq!(format!("{:?}", bound), "***\n**");
+        assert_eq!(format!("{:#?}", bound), "***\n**|");
+        let bound = (lit("abc") ^ empty()).bound(vec!(), 0).first();
+        assert_eq!(format!("{:#?}", bound), "***\n|");
+        let bound = lit("abc").bound(vec!(), 0).first();
+        assert_eq!(format!("{:#?}", bound), "***|");
+    }
+
+    #[test]
+    fn test_layout_depth_limit() {
+        let syn = (lit("a") + (lit("b") | (lit("c") ^ lit("d")))).expand(0);
+        assert_eq!(syn.depth(), 4);
+        assert_eq!(try_compute_bounds_to_depth(&vec!(), &syn, 4).unwrap().first(),
+                   compute_bounds(&vec!(), &syn).first());
+        assert_eq!(try_compute_bounds_to_depth(&vec!(), &syn, 3).err(),
+                   Some(LayoutError::TooDeep { depth: 4 }));
+
+        // Far too deep to lay out, but not to check.
+        let mut deep = lit("x");
+        for _ in 0..10_000 {
+            deep = lit("x") + deep;
+        }
+        assert_eq!(deep.depth(), 10_001);
+        let err = try_compute_layouts(&vec!(), &deep).err().unwrap();
+        assert_eq!(err, LayoutError::TooDeep { depth: 10_001 });
+        assert_eq!(format!("{}", err),
+                   "notation is too deeply nested to lay out (depth 10001)");
+    }
+
+    #[test]
+    fn test_layout_at_depth_limit() {
+        // The deepest notation that is accepted must actually lay out,
+        // on a thread with the default stack size.
+        let deepest = |make: fn(Notation) -> Notation| {
+            let mut deep = lit("x");
+            while deep.depth() < MAX_LAYOUT_DEPTH - 1 {
+                deep = make(deep);
+            }
+            deep
+        };
+        let deep_concat = deepest(|deep| lit("x") + deep);
+        let deep_vert = deepest(|deep| lit("x") ^ deep);
+        let deep_choice = deepest(|deep| if deep.depth() % 2 == 0 {
+            lit("x") + deep
+        } else {
+            deep | lit("y")
+        });
+        let handle = std::thread::spawn(move || {
+            for deep in &[deep_concat, deep_vert, deep_choice] {
+                assert_eq!(deep.depth(), MAX_LAYOUT_DEPTH - 1);
+                assert!(try_compute_layouts(&vec!(), deep).is_ok());
+                assert!(try_compute_bounds(&vec!(), deep).is_ok());
+            }
+        });
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_try_compute_layouts_errors() {
+        use crate::notation::Repeat;
+
+        let check = |child_bounds: &Vec<Bounds>, notation: Notation| {
+            try_compute_layouts(child_bounds, &notation).err()
+        };
+        let children = vec!(text_bounds("a"), text_bounds("bc"));
+        let list = Repeat {
+            empty:  lit("[]"),
+            lone:   lit("[") + star() + lit("]"),
+            first:  lit("[") + star(),
+            middle: lit(", ") + star(),
+            last:   lit(", ") + star() + lit("]")
+        };
+
+        // Well-formed notations lay out exactly as `compute_layouts` would.
+        let ok = ((child(0) + lit(" ") + child(1)) | (child(0) ^ child(1))).expand(2);
+        assert_eq!(try_compute_layouts(&children, &ok).unwrap().fit_width(80).region,
+                   compute_layouts(&children, &ok).fit_width(80).region);
+        let streamed = repeat(list.clone()).expand_streaming(2, &[]);
+        assert_eq!(check(&children, streamed.clone()), None);
+
+        let unexpanded = |name| Some(LayoutError::Unexpanded(name));
+        assert_eq!(check(&children, repeat(list.clone())), unexpanded("Repeat"));
+        assert_eq!(check(&children, lit("x") + star()), unexpanded("Star"));
+        assert_eq!(check(&children, if_empty_text(lit("a"), lit("b"))),
+                   unexpanded("IfEmptyText"));
+        assert_eq!(check(&children, map_text(Style::plain(), str::to_uppercase)),
+                   unexpanded("MapText"));
+        assert_eq!(check(&children, child_parenthesized(0, 1, lit("("), lit(")"))),
+                   unexpanded("ChildParens"));
+        assert_eq!(check(&children, Notation::StreamedRep(Box::new(list.clone()), 1)),
+                   unexpanded("StreamedRep"));
+
+        assert_eq!(check(&children, child(0) ^ child(2)),
+                   Some(LayoutError::MissingChild { index: 2 }));
+        assert_eq!(check(&children[..1].to_vec(), streamed),
+                   Some(LayoutError::MissingChild { index: 1 }));
+        assert_eq!(check(&vec!(), text(Style::plain())), Some(LayoutError::MissingText));
+
+        // An optional child may be missing, or empty, but only where it is
+        // optional.
+        let optional = optional_child(0, lit("else ") + child(0)).expand(0);
+        assert_eq!(check(&vec!(), optional.clone()), None);
+        assert_eq!(format!("{:?}", try_compute_layouts(&vec!(), &optional).unwrap().fit_width(80)),
+                   "");
+        assert_eq!(check(&vec!(Bounds::empty()), optional.clone()), None);
+        assert_eq!(check(&children, optional.clone()), None);
+        assert_eq!(check(&vec!(), optional + child(0)),
+                   Some(LayoutError::MissingChild { index: 0 }));
+
+        let heavy = weighted(lit("a"), Weight::MAX) + weighted(lit("b"), 1);
+        assert_eq!(check(&children, heavy), Some(LayoutError::WeightOverflow));
+        assert_eq!(check(&children, weighted(lit("a"), Weight::MAX)), None);
+
+        // Shallow, but with 2^14 - 1 choices.
+        let mut branchy = lit("x");
+        for _ in 0..14 {
+            branchy = branchy.clone() | branchy;
+        }
+        assert_eq!(check(&children, branchy.clone()),
+                   Some(LayoutError::TooManyChoices { count: 16383 }));
+        assert_eq!(try_compute_layouts_to_depth(&children, &branchy, 10).err(),
+                   Some(LayoutError::TooDeep { depth: 15 }));
+    }
 }
@@ -6,13 +6,26 @@
 mod style;
 mod geometry;
 mod notation;
+mod lint;
 mod layout;
 mod pretty;
 
-pub use self::style::{Style, ColorTheme, Color, Rgb};
-pub use self::geometry::{Row, Col, Pos, Bound, Region, MAX_WIDTH};
+pub use self::style::{Style, Emph, Shade, ColorTheme, Color, Rgb};
+pub use self::geometry::{Row, Col, Pos, Bound, Region, MAX_WIDTH, WidthFn, CharCount,
+                         Direction};
 pub use self::notation::{Notation, Repeat,
-                         empty, literal, text, no_wrap, horz, vert,
-                         child, repeat, star, if_empty_text, choice};
-//pub use self::pretty::{??};
+                         empty, literal, text, map_text, no_wrap, no_wrap_with, OverflowPolicy,
+                         joined, horz, vert,
+                         child, repeat, star, if_empty_text, choice, table, grid,
+                         summary_count, softline, softbreak, group,
+                         weighted, weighted_choice, Weight,
+                         child_parenthesized, optional_child, join_present, Precedence,
+                         nest, hang, width_switch, min_width, vcat};
+pub use self::lint::NotationWarning;
+pub use self::layout::{Bounds, Layouts, LayoutError, LayoutStrategy, Lay, ChoiceDecision,
+                       MAX_LAYOUT_DEPTH, MAX_LAYOUT_CHOICES, MAX_BOUND_CANDIDATES,
+                       try_compute_bounds, try_compute_layouts,
+                       try_compute_bounds_to_depth, try_compute_layouts_to_depth,
+                       compute_bounds_with, compute_layouts_with, text_bounds_with};
+pub use self::pretty::{PrettyDocument, BoundsCache, PrettyScreen, PlainText, IndentStyle, SpanText};
 
@@ -0,0 +1,270 @@
+use std::collections::BTreeSet;
+
+use crate::notation::{Notation, Repeat};
+use crate::layout::{Bounds, compute_bounds, compute_layouts, text_bounds};
+
+use crate::notation::Notation::*;
+
+
+/// A likely mistake in a `Notation`, found by `Notation::lint`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NotationWarning {
+    /// One branch of a `Choice` can never be picked, because the other
+    /// branch can always be displayed at least as compactly. `choice`
+    /// counts the `Choice`s in pre-order, as written (visiting the parts
+    /// of a `Repeat` in the order empty, lone, first, middle, last), and
+    /// `branch` is 0 for the first branch or 1 for the second.
+    DominatedBranch { choice: usize, branch: usize },
+    /// An empty literal is concatenated onto something, so it has no
+    /// effect. (An empty literal on a line of its own, as in `a ^
+    /// empty()`, is fine: it makes a blank line.)
+    EmptyLiteral,
+    /// The notation displays some child after this one, but never this
+    /// one.
+    UnusedChild(usize)
+}
+
+impl Notation {
+    /// Look for likely mistakes in this notation. This is an aid for
+    /// writing notations; none of the warnings stop it from being
+    /// displayed.
+    ///
+    /// To decide whether a branch of a `Choice` is dominated, every child
+    /// (and text) is assumed to be displayed as a single character. A
+    /// `Repeat` is assumed to have two elements.
+    pub fn lint(&self) -> Vec<NotationWarning> {
+        let mut linter = Linter {
+            warnings: vec!(),
+            children: BTreeSet::new(),
+            choices: 0,
+            child_bounds: vec!()
+        };
+        linter.collect_children(self);
+        let num_children = linter.children.iter().next_back().map_or(0, |i| i + 1);
+        linter.child_bounds = (0..num_children.max(2)).map(|_| text_bounds("x")).collect();
+        linter.lint(self, false);
+        for i in 0..num_children {
+            if !linter.children.contains(&i) {
+                linter.warnings.push(NotationWarning::UnusedChild(i));
+            }
+        }
+        linter.warnings
+    }
+}
+
+impl Notation {
+    /// The number of distinct layouts this notation has (see
+    /// `Layouts::len`), for a node with `arity` children, assuming every
+    /// child (and text) is displayed as a single character. This is a
+    /// rough measure of how expensive the notation is to lay out: it grows
+    /// with the number of independent `Choice`s, though pruning keeps it
+    /// from growing without limit.
+    pub fn layout_complexity(&self, arity: usize) -> usize {
+        let mut child_bounds: Vec<Bounds> = (0..arity).map(|_| text_bounds("x")).collect();
+        let notation = if self.uses_text() {
+            child_bounds.push(text_bounds("x"));
+            if arity == 0 {
+                self.expand_text("x")
+            } else {
+                self.expand_mixed("x", &vec!(None; arity))
+            }
+        } else {
+            self.expand(arity)
+        };
+        compute_layouts(&child_bounds, &notation).len()
+    }
+}
+
+struct Linter {
+    warnings: Vec<NotationWarning>,
+    children: BTreeSet<usize>,
+    choices: usize,
+    child_bounds: Vec<Bounds>
+}
+
+impl Linter {
+    // Record every child index that `notation` displays.
+    fn collect_children(&mut self, notation: &Notation) {
+        match notation {
+            Child(i) => {
+                self.children.insert(*i);
+            }
+            ChildParens(i, _, a, b) => {
+                self.children.insert(*i);
+                self.collect_children(a);
+                self.collect_children(b);
+            }
+            OptionalChild(i, a) => {
+                self.children.insert(*i);
+                self.collect_children(a);
+            }
+            Concat(a, b) | Horz(a, b) | Vert(a, b) | Joined(a, b) | Choice(a, b)
+                | SoftBreak(a, b) | WidthSwitch(_, a, b) | IfEmptyText(a, b) => {
+                self.collect_children(a);
+                self.collect_children(b);
+            }
+            NoWrap(a, _) | Weighted(a, _) | MinWidth(_, a) | JoinPresent(a) | Group(a) =>
+                self.collect_children(a),
+            Rep(repeat) | StreamedRep(repeat, _) => {
+                let Repeat { empty, lone, first, middle, last } = &**repeat;
+                for part in &[empty, lone, first, middle, last] {
+                    self.collect_children(part);
+                }
+            }
+            Table(rows, sep) => {
+                for (left, right) in rows {
+                    self.collect_children(left);
+                    self.collect_children(right);
+                }
+                self.collect_children(sep);
+            }
+            Grid(rows) => {
+                for cell in rows.iter().flatten() {
+                    self.collect_children(cell);
+                }
+            }
+            Empty | Literal(_, _) | Text(_) | MapText(_, _) | Star
+                | Count(..) => ()
+        }
+    }
+
+    // `in_repeat` says whether `Star`s may appear in `notation`.
+    fn lint(&mut self, notation: &Notation, in_repeat: bool) {
+        match notation {
+            Concat(a, b) => {
+                for note in &[a, b] {
+                    if let Literal(s, _) = &***note {
+                        if s.is_empty() {
+                            self.warnings.push(NotationWarning::EmptyLiteral);
+                        }
+                    }
+                }
+                self.lint(a, in_repeat);
+                self.lint(b, in_repeat);
+            }
+            Choice(a, b) => {
+                let choice = self.choices;
+                self.choices += 1;
+                let bounds1 = self.bounds(a, in_repeat);
+                let bounds2 = self.bounds(b, in_repeat);
+                if bounds1.shadows(&bounds2, true) {
+                    self.warnings.push(NotationWarning::DominatedBranch { choice, branch: 1 });
+                } else if bounds2.shadows(&bounds1, false) {
+                    self.warnings.push(NotationWarning::DominatedBranch { choice, branch: 0 });
+                }
+                self.lint(a, in_repeat);
+                self.lint(b, in_repeat);
+            }
+            SoftBreak(a, b) => {
+                self.choices += 1;
+                self.lint(a, in_repeat);
+                self.lint(b, in_repeat);
+            }
+            Rep(repeat) => {
+                let Repeat { empty, lone, first, middle, last } = &**repeat;
+                for part in &[empty, lone, first, middle, last] {
+                    self.lint(part, true);
+                }
+            }
+            Horz(a, b) | Vert(a, b) | Joined(a, b) | WidthSwitch(_, a, b)
+                | IfEmptyText(a, b) | ChildParens(_, _, a, b) => {
+                self.lint(a, in_repeat);
+                self.lint(b, in_repeat);
+            }
+            NoWrap(a, _) | Weighted(a, _) | MinWidth(_, a) | OptionalChild(_, a)
+                | JoinPresent(a) | Group(a) =>
+                self.lint(a, in_repeat),
+            StreamedRep(repeat, _) => self.lint(&Rep(repeat.clone()), in_repeat),
+            Table(rows, sep) => {
+                for (left, right) in rows {
+                    self.lint(left, in_repeat);
+                    self.lint(right, in_repeat);
+                }
+                self.lint(sep, in_repeat);
+            }
+            Grid(rows) => {
+                for cell in rows.iter().flatten() {
+                    self.lint(cell, in_repeat);
+                }
+            }
+            Empty | Literal(_, _) | Text(_) | MapText(_, _) | Child(_) | Star
+                | Count(..) => ()
+        }
+    }
+
+    fn bounds(&self, notation: &Notation, in_repeat: bool) -> Bounds {
+        let notation = if in_repeat {
+            notation.replace_star(0).expand(2)
+        } else {
+            notation.expand(2)
+        };
+        compute_bounds(&self.child_bounds, &notation)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::*;
+    use crate::style::Style;
+
+    fn lit(s: &str) -> Notation {
+        literal(s, Style::plain())
+    }
+
+    #[test]
+    fn test_lint_dominated_branch() {
+        assert_eq!((lit("ab") | lit("abc")).lint(),
+                   vec!(NotationWarning::DominatedBranch{ choice: 0, branch: 1 }));
+        assert_eq!((lit("abc") | lit("ab")).lint(),
+                   vec!(NotationWarning::DominatedBranch{ choice: 0, branch: 0 }));
+        let spaced = |n| child(0) + lit(&" ".repeat(n)) + child(1);
+        assert_eq!((lit("[") + (spaced(1) | spaced(2))).lint(),
+                   vec!(NotationWarning::DominatedBranch{ choice: 0, branch: 1 }));
+        // Neither branch of a softline is always better, but of two
+        // identical branches, the second is never picked.
+        assert_eq!((lit("a") + softline() + lit("b") + (lit("c") | lit("c"))).lint(),
+                   vec!(NotationWarning::DominatedBranch{ choice: 1, branch: 1 }));
+        let list = repeat(Repeat{
+            empty:  lit("[]"),
+            lone:   lit("[") + star() + lit("]"),
+            first:  lit("[") + star(),
+            middle: lit(",") + softline() + star(),
+            last:   lit(",") + star() + lit("]")
+        });
+        assert_eq!(list.lint(), vec!());
+    }
+
+    #[test]
+    fn test_lint_empty_literal_and_unused_child() {
+        assert_eq!((lit("") + child(0)).lint(), vec!(NotationWarning::EmptyLiteral));
+        assert_eq!((lit("a") ^ empty()).lint(), vec!());
+        assert_eq!((child(0) + lit(" ") + child(2)).lint(),
+                   vec!(NotationWarning::UnusedChild(1)));
+        assert_eq!((child(0) + optional_child(1, child(1))).lint(), vec!());
+    }
+
+    #[test]
+    fn test_layout_complexity() {
+        let plus = child(0) + lit(" + ") + child(1);
+        assert_eq!(plus.layout_complexity(2), 1);
+        assert_eq!(text(Style::plain()).layout_complexity(0), 1);
+
+        let plus = (child(0) + lit(" + ") + child(1)) | (child(0) ^ (lit("+ ") + child(1)));
+        assert_eq!(plus.layout_complexity(2), 2);
+
+        let list = repeat(Repeat{
+            empty:  lit("[]"),
+            lone:   lit("[") + star() + lit("]"),
+            first:  lit("[") + star(),
+            middle: lit(",") + softline() + star(),
+            last:   lit(",") + softline() + star() + lit("]")
+        });
+        assert_eq!(list.layout_complexity(0), 1);
+        let simple = plus.layout_complexity(2);
+        let complex = list.layout_complexity(6);
+        assert!(complex > simple);
+        assert!(complex < list.layout_complexity(12));
+    }
+}
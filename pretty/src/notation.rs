@@ -5,15 +5,35 @@ use crate::style::Style;
 use self::Notation::*;
 
 
+/// The penalty for picking a layout. Lower is preferred.
+pub type Weight = u32;
+
+/// How tightly a node binds, for deciding whether it needs parentheses.
+/// Higher binds tighter (e.g. `*` might have a higher precedence than `+`).
+pub type Precedence = u32;
+
 /// Describes how to display a syntactic construct.
-#[derive(Clone, Debug)]
+// Comparing the functions in `MapText` by address is good enough: at
+// worst, two identical notations compare unequal.
+#[allow(unpredictable_function_pointer_comparisons)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Notation {
     /// Display Nothing
     Empty,
-    /// Display a literal string.
+    /// Display a literal string. If it contains newlines, each of its
+    /// lines is displayed below the previous one.
     Literal(String, Style),
-    /// Display a piece of text. Must be used on a texty node.
+    /// Display the number of children this node has, between the two
+    /// strings, e.g. `[3 items]`. Meant for the summary notations of
+    /// collections, which are shown in place of the whole collection.
+    /// (See `summary_count`.) Must be used on a foresty node.
+    Count(String, String, Style),
+    /// Display a piece of text. Must be used on a node that has text: a
+    /// texty node, or a mixed node (which has children as well).
     Text(Style),
+    /// Display a piece of text, as transformed by the function. Must be
+    /// used on a node that has text. (See `map_text`.)
+    MapText(Style, fn(&str) -> String),
     /// Display the second notation after the first (standard concatenation).
     Concat(Box<Notation>, Box<Notation>),
     /// Display the second notation to the right of the first (horizontal
@@ -21,10 +41,34 @@ pub enum Notation {
     Horz(Box<Notation>, Box<Notation>),
     /// Display the second notation below the first (vertical concatenation).
     Vert(Box<Notation>, Box<Notation>),
-    /// Display this notation, not permitting flushes/newlines.
-    NoWrap(Box<Notation>),
+    /// Display this notation, not permitting flushes/newlines. The policy
+    /// says what to do if it does not fit on one line.
+    NoWrap(Box<Notation>, OverflowPolicy),
+    /// Display the first notation, then a space, then the second, never
+    /// with a line break between the two. (See `joined`.)
+    Joined(Box<Notation>, Box<Notation>),
     /// Display either the first notation, or the second, whichever is Best.
     Choice(Box<Notation>, Box<Notation>),
+    /// Within a chain of `Concat`s, display the first notation between
+    /// what comes before it and what comes after it, or else break the
+    /// line, and start the new line with the second notation. The new
+    /// line starts at the column where the chain started. Counts as a
+    /// `Choice`. (See `softline`.)
+    SoftBreak(Box<Notation>, Box<Notation>),
+    /// Display this notation as its own chain of `Concat`s, even inside
+    /// of another one. (See `group`.)
+    Group(Box<Notation>),
+    /// Display this notation, but with a penalty. When several layouts
+    /// fit, the one with the least total penalty is picked. (See
+    /// `weighted_choice`.)
+    Weighted(Box<Notation>, Weight),
+    /// Display the first notation if there are at least this many columns
+    /// left on the line where it starts, otherwise the second. (See
+    /// `width_switch`.)
+    WidthSwitch(usize, Box<Notation>, Box<Notation>),
+    /// Display this notation, padding its last line with spaces out to at
+    /// least this many columns. (See `min_width`.)
+    MinWidth(usize, Box<Notation>),
     /// Display the first notation in case this tree has empty text,
     /// otherwise show the second notation.
     IfEmptyText(Box<Notation>, Box<Notation>),
@@ -32,19 +76,59 @@ pub enum Notation {
     /// Must be used on a foresty node.
     /// `i` must be less than the node's arity number.
     Child(usize),
+    /// Display the `i`th child, wrapped in the first and second
+    /// notations if it binds more loosely than this node: that is, if the
+    /// child has a precedence, and it is strictly lower than the given
+    /// one. Children without a precedence are never wrapped.
+    ChildParens(usize, Precedence, Box<Notation>, Box<Notation>),
+    /// Display the notation if the `i`th child is present, and nothing
+    /// otherwise. (See `optional_child`.)
+    OptionalChild(usize, Box<Notation>),
     /// Determines what to display based on the arity of this node.
     /// Used for syntactic constructs that have extendable arity.
     // TODO: Does this need to be boxed?
     Rep(Box<Repeat>),
+    /// A `Rep` over a sequence of the given length (at least 2), whose
+    /// expansion has been put off until it is laid out. (See
+    /// `Notation::expand_streaming`.)
+    StreamedRep(Box<Repeat>, usize),
+    /// Display every child of this node in order, with the separator
+    /// between each pair of children that are not empty. (See
+    /// `join_present`.)
+    JoinPresent(Box<Notation>),
     /// A node with extendable arity can have more children than its
     /// arity number. Within a `Rep`, `Star` represents the children
     /// beyond this arity. It does not make sense outside of a `Rep`.
-    Star
+    Star,
+    /// Display each row vertically, as its left cell, then the separator,
+    /// then its right cell. The left cells are padded so that the
+    /// separators all line up.
+    Table(Vec<(Notation, Notation)>, Box<Notation>),
+    /// Display each row vertically, as its cells from left to right, with
+    /// the cells in each column aligned. (See `grid`.)
+    Grid(Vec<Vec<Notation>>)
+}
+
+/// What a `NoWrap` does when its notation is too wide to fit on one line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Don't display it at all. (If nothing else can be displayed
+    /// instead, the document will not fit.)
+    Strict,
+    /// Display it on one line anyway, and let the screen clip whatever
+    /// runs off its edge. While clipped, it is treated as fitting however
+    /// wide it is; anything after it on the same line is displayed after
+    /// it, and so is clipped too. Clipping counts as a weight of 1 (see
+    /// `weighted`).
+    Clip,
+    /// Let it wrap onto multiple lines after all. Wrapping counts as a
+    /// weight of 1 (see `weighted`).
+    Fallback
 }
 
 /// Describes how to display the extra children of a syntactic
 /// construct with extendable arity.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Repeat {
     /// If the sequence is empty, use this notation.
     pub empty:  Notation,
@@ -74,9 +158,43 @@ pub fn text(style: Style) -> Notation {
     Text(style)
 }
 
-/// Construct a `NoWrap`.
+/// Like `text`, but display `f` of the node's text instead of the text
+/// itself, e.g. to show keywords in uppercase. This only changes how the
+/// text is displayed: the node's text is untouched. The transformed text
+/// is measured and displayed like a `Literal`.
+pub fn map_text(style: Style, f: fn(&str) -> String) -> Notation {
+    MapText(style, f)
+}
+
+/// Display the node's number of children as `prefix`, then the count,
+/// then `suffix`, e.g. `summary_count("[", " items]", style)`. This is
+/// displayed like a `Literal`. It is meant for summary notations (see
+/// `PrettyDocument::summary_notation`), so that a folded collection
+/// still shows how big it is.
+pub fn summary_count(prefix: &str, suffix: &str, style: Style) -> Notation {
+    Count(prefix.to_string(), suffix.to_string(), style)
+}
+
+/// Construct a `NoWrap`, with the `Strict` overflow policy.
 pub fn no_wrap(note: Notation) -> Notation {
-    NoWrap(Box::new(note))
+    no_wrap_with(note, OverflowPolicy::Strict)
+}
+
+/// Construct a `NoWrap` with the given overflow policy.
+pub fn no_wrap_with(note: Notation, policy: OverflowPolicy) -> Notation {
+    NoWrap(Box::new(note), policy)
+}
+
+/// Join two notations with a space that the line can never be broken
+/// at, e.g. a unary minus and its operand. Each side may still break
+/// internally.
+///
+/// The second notation always starts on the line where the first one
+/// ends, so the only way a break could fall at the join is if the first
+/// one ends with a line break (leaving an empty last line). Such layouts
+/// of `note1` are discarded.
+pub fn joined(note1: Notation, note2: Notation) -> Notation {
+    Joined(Box::new(note1), Box::new(note2))
 }
 
 /// Construct a `Child`.
@@ -84,16 +202,140 @@ pub fn child(index: usize) -> Notation {
     Child(index)
 }
 
+/// Construct a `ChildParens`, which displays the `index`th child
+/// between `open` and `close` only if its precedence is lower than
+/// `parent_prec`.
+pub fn child_parenthesized(index: usize, parent_prec: Precedence,
+                           open: Notation, close: Notation) -> Notation {
+    ChildParens(index, parent_prec, Box::new(open), Box::new(close))
+}
+
+/// Display `present` (which will typically include `child(index)`) if
+/// the `index`th child is present, and nothing otherwise, e.g. for an
+/// optional `else` branch. A child is absent if it has no Bounds: either
+/// `child_bounds` is too short to include it, or its Bounds are
+/// `Bounds::empty()`.
+pub fn optional_child(index: usize, present: Notation) -> Notation {
+    OptionalChild(index, Box::new(present))
+}
+
 /// Construct a `Repeat`.
 pub fn repeat(repeat: Repeat) -> Notation {
     Rep(Box::new(repeat))
 }
 
+/// Display every child in order, separated by `sep`, like a `Repeat`
+/// whose `first` and `middle` are `star() + sep`, but skipping the
+/// children that are empty: those that have no Bounds, or that display
+/// nothing at all. So there are never leading, trailing, or doubled
+/// separators. If every child is empty, this displays nothing.
+///
+/// The separators all share the ids of the `Choice`s in `sep` (see
+/// `ChoiceDecision`), as if `sep` had only been laid out once.
+pub fn join_present(sep: Notation) -> Notation {
+    JoinPresent(Box::new(sep))
+}
+
 /// Construct a `Star` (for use in `Repeat`).
 pub fn star() -> Notation {
     Star
 }
 
+/// Construct a `Table`.
+pub fn table(rows: Vec<(Notation, Notation)>, sep: Notation) -> Notation {
+    Table(rows, Box::new(sep))
+}
+
+/// Display the rows one below the other, with their cells lined up in
+/// columns. Each column is as wide as its widest cell (in that cell's
+/// narrowest layout), and columns are separated by one space. Rows may
+/// have different numbers of cells.
+///
+/// A cell may span several lines. Then its row grows taller, and the
+/// rest of the row continues after the cell's last line (still aligned
+/// to its columns), as with a `Concat`.
+pub fn grid(rows: Vec<Vec<Notation>>) -> Notation {
+    Grid(rows)
+}
+
+// Apply `f` to every cell of a `Grid`.
+fn map_cells<F>(rows: &[Vec<Notation>], f: F) -> Vec<Vec<Notation>>
+    where F: Fn(&Notation) -> Notation
+{
+    rows.iter().map(|row| row.iter().map(&f).collect()).collect()
+}
+
+/// A space, or a line break if there isn't room, as in
+/// `left + softline() + right`. (Like Wadler's `line`.) Unlike a line
+/// break inside of `right`, which would be indented to where `left`
+/// ended, the line after a `softline` starts at the column where the
+/// chain of `Concat`s that it is in started, however the chain is
+/// parenthesized. To start a new chain part way along a line, use
+/// `group` (or `hang`).
+///
+/// Each `softline` is a single choice between the two, so a chain of
+/// them stays small, and each part of it is only laid out once.
+pub fn softline() -> Notation {
+    soft_break(literal(" ", Style::plain()), empty())
+}
+
+/// Nothing, or a line break if there isn't room, as in
+/// `left + softbreak() + right`. (Like Wadler's `softbreak`.) See
+/// `softline`.
+pub fn softbreak() -> Notation {
+    soft_break(empty(), empty())
+}
+
+fn soft_break(joiner: Notation, prefix: Notation) -> Notation {
+    SoftBreak(Box::new(joiner), Box::new(prefix))
+}
+
+/// Display `note` as a chain of `Concat`s of its own, so that the
+/// `softline`s and `softbreak`s in it break back to the column where it
+/// starts, rather than to where the chain around it started. For
+/// example, `lit("x = ") + group(a + softline() + b)` puts `b` under
+/// `a`.
+pub fn group(note: Notation) -> Notation {
+    Group(Box::new(note))
+}
+
+/// Display `anchor`, then `body`, with every line break in `body`
+/// indented to the column where `anchor` ended (a "hanging" indent),
+/// e.g. to line a call's arguments up under its opening paren:
+/// `hang(literal("call(", style), args)`.
+///
+/// This is just a `Concat`, which always aligns breaks in its right side
+/// this way, with `body` as a `group` so that its `softline`s do too. It
+/// is spelled out for readability, and in contrast to `nest`, which
+/// indents by a fixed amount.
+pub fn hang(anchor: Notation, body: Notation) -> Notation {
+    concat(anchor, group(body))
+}
+
+/// Indent every line break in `body` by `indent` more columns than it
+/// would otherwise be (like Wadler's `nest`). The first line is not
+/// indented, so a block is typically written as
+/// `vert(nest(2, vert(open, body)), close)`. Nests are cumulative: a
+/// break inside of two nests is indented by both.
+///
+/// Breaks in the right side of a `Concat` are aligned to where its left
+/// side ended, which is already on an indented line, so they are not
+/// indented again. Neither are breaks inside of children, or between the
+/// rows of a `Table` or `Grid`.
+pub fn nest(indent: usize, body: Notation) -> Notation {
+    body.nested(&literal(&" ".repeat(indent), Style::plain()))
+}
+
+/// Stack the notations vertically, with a line break between each one
+/// (but not after the last). An empty list displays nothing.
+pub fn vcat(items: Vec<Notation>) -> Notation {
+    let mut items = items.into_iter();
+    match items.next() {
+        None => empty(),
+        Some(first) => items.fold(first, vert)
+    }
+}
+
 /// Construct an `IfEmptyText`.
 pub fn if_empty_text(note1: Notation, note2: Notation) -> Notation {
     IfEmptyText(Box::new(note1), Box::new(note2))
@@ -119,6 +361,43 @@ pub fn choice(note1: Notation, note2: Notation) -> Notation {
     Choice(Box::new(note1), Box::new(note2))
 }
 
+/// Construct a `Weighted`.
+pub fn weighted(note: Notation, weight: Weight) -> Notation {
+    Weighted(Box::new(note), weight)
+}
+
+/// Display either `note1` or `note2`, preferring whichever has the lower
+/// weight when both fit. (A plain `Choice` prefers the first.)
+pub fn weighted_choice(note1: Notation, weight1: Weight,
+                       note2: Notation, weight2: Weight) -> Notation {
+    choice(weighted(note1, weight1), weighted(note2, weight2))
+}
+
+/// Display `body`, but never narrower than `cols` columns: if its last
+/// line is shorter than that, it is padded on the right with spaces, so
+/// that whatever follows starts at column `cols` (counting from where
+/// `body` started). This is handy for fixed-width columns, like the
+/// opcodes in assembly. If the last line is already at least `cols`
+/// columns wide, nothing changes. Earlier lines are not padded.
+pub fn min_width(cols: usize, body: Notation) -> Notation {
+    MinWidth(cols, Box::new(body))
+}
+
+/// Display `wide` if the column at which it starts leaves at least
+/// `threshold` columns before the edge of the screen, and otherwise
+/// `narrow`. Unlike a `Choice`, this depends on where the notation is
+/// placed, and not just on whether `wide` fits.
+///
+/// The start column need not be known when laying out: `wide`'s Bounds
+/// are treated as being at least `threshold` wide (from the start column,
+/// without moving what follows), so that they only fit if that many
+/// columns are free. The displayed layout is only as wide as its actual
+/// content. To keep `narrow` from crowding `wide` out, it counts as a
+/// weight of 1 (see `weighted`).
+pub fn width_switch(threshold: usize, wide: Notation, narrow: Notation) -> Notation {
+    WidthSwitch(threshold, Box::new(wide), Box::new(narrow))
+}
+
 impl Add<Notation> for Notation {
     ///
     type Output = Notation;
@@ -146,24 +425,58 @@ impl BitOr<Notation> for Notation {
     }
 }
 
-struct NotationExpander {
-    len: usize
+struct NotationExpander<'a> {
+    len: usize,
+    precedences: &'a [Option<Precedence>],
+    streaming: bool,
+    // The node's text, if it has text and the text is known.
+    text: Option<&'a str>
 }
 
-impl NotationExpander {
+impl<'a> NotationExpander<'a> {
     fn expand(&self, notation: &Notation) -> Notation {
         match notation {
             &Empty         => notation.clone(),
             &Literal(ref s, style) => Literal(s.clone(), style),
             &Text(_)       => notation.clone(),
+            Count(prefix, suffix, style) =>
+                literal(&format!("{}{}{}", prefix, self.len, suffix), *style),
+            MapText(style, f) => match self.text {
+                Some(text) => literal(&f(text), *style),
+                None => notation.clone()
+            },
             &Child(_)      => notation.clone(),
-            &NoWrap(ref s) => no_wrap(self.expand(s)),
+            &ChildParens(i, parent_prec, ref open, ref close) =>
+                match self.precedences.get(i) {
+                    Some(&Some(prec)) if prec < parent_prec =>
+                        self.expand(open) + Child(i) + self.expand(close),
+                    _ => Child(i)
+                },
+            OptionalChild(i, a) => optional_child(*i, self.expand(a)),
+            NoWrap(s, policy) => no_wrap_with(self.expand(s), *policy),
+            Joined(a, b) => joined(self.expand(a), self.expand(b)),
             &Concat(ref a, ref b) => self.expand(a) + self.expand(b),
             &Horz(ref a, ref b)   => horz(self.expand(a), self.expand(b)),
             &Vert(ref a, ref b)   => self.expand(a) ^ self.expand(b),
             &Choice(ref a, ref b) => self.expand(a) | self.expand(b),
-            &IfEmptyText(ref a, ref b) =>
-                self.expand(if self.len == 0 { a } else { b }),
+            SoftBreak(a, b) => soft_break(self.expand(a), self.expand(b)),
+            Group(a) => group(self.expand(a)),
+            &Weighted(ref a, weight) => weighted(self.expand(a), weight),
+            MinWidth(cols, a) => min_width(*cols, self.expand(a)),
+            &WidthSwitch(threshold, ref a, ref b) =>
+                width_switch(threshold, self.expand(a), self.expand(b)),
+            &IfEmptyText(ref a, ref b) => {
+                let is_empty = match self.text {
+                    Some(text) => text.is_empty(),
+                    None => self.len == 0
+                };
+                self.expand(if is_empty { a } else { b })
+            }
+            &Table(ref rows, ref sep) =>
+                table(rows.iter().map(|(left, right)| {
+                    (self.expand(left), self.expand(right))
+                }).collect(), self.expand(sep)),
+            Grid(rows) => grid(map_cells(rows, |cell| self.expand(cell))),
             &Rep(ref repeat) => {
                 let &Repeat{ ref empty,
                              ref lone,
@@ -171,6 +484,7 @@ impl NotationExpander {
                 match self.len {
                     0 => empty.clone(),
                     1 => lone.clone().replace_star(0),
+                    len if self.streaming => StreamedRep(repeat.clone(), len),
                     _ => {
                         let mut note = last.replace_star(self.len - 1);
                         for i in (1 .. self.len - 1).rev() {
@@ -181,26 +495,377 @@ impl NotationExpander {
                     }
                 }
             },
+            StreamedRep(..) => notation.clone(),
+            JoinPresent(sep) => join_present(self.expand(sep)),
             &Star{..} => panic!("Invalid notation: star found outside of repeat")
         }
         
     }
 }
 
+impl Repeat {
+    fn map<F>(&self, f: F) -> Repeat
+        where F: Fn(&Notation) -> Notation
+    {
+        Repeat {
+            empty:  f(&self.empty),
+            lone:   f(&self.lone),
+            first:  f(&self.first),
+            middle: f(&self.middle),
+            last:   f(&self.last)
+        }
+    }
+
+    // The notation for the `i`th of `len` elements, when `len >= 2`.
+    pub(crate) fn element(&self, i: usize, len: usize) -> Notation {
+        let part = if i == 0 {
+            &self.first
+        } else if i + 1 == len {
+            &self.last
+        } else {
+            &self.middle
+        };
+        part.replace_star(i)
+    }
+}
+
 impl Notation {
     // Eliminate any Repeats.
     // If the node is texty, `len` is the length of the text.
     pub(crate) fn expand(&self, len: usize) -> Notation {
+        self.expand_with_precedences(len, &[])
+    }
+
+    // Like `expand`, but also resolve `ChildParens`, given the precedence
+    // of each child.
+    pub(crate) fn expand_with_precedences(&self, len: usize,
+                                          precedences: &[Option<Precedence>])
+                                          -> Notation
+    {
+        NotationExpander{
+            len: len,
+            precedences,
+            streaming: false,
+            text: None
+        }.expand(self)
+    }
+
+    // Like `expand_with_precedences`, but leave each `Repeat` over two or
+    // more children as a `StreamedRep`. Its elements are then expanded
+    // one at a time as it is laid out, so that the expanded notation does
+    // not grow with the number of children.
+    pub(crate) fn expand_streaming(&self, len: usize,
+                                   precedences: &[Option<Precedence>])
+                                   -> Notation
+    {
         NotationExpander{
-            len: len
+            len,
+            precedences,
+            streaming: true,
+            text: None
         }.expand(self)
     }
 
-    fn replace_star(&self, child: usize) -> Notation {
+    // Like `expand_streaming`, for a texty node with the given text. This
+    // also resolves `MapText`s, by applying them to the text.
+    pub(crate) fn expand_text(&self, text: &str) -> Notation {
+        NotationExpander{
+            len: text.chars().count(),
+            precedences: &[],
+            streaming: true,
+            text: Some(text)
+        }.expand(self)
+    }
+
+    // Like `expand_streaming`, for a mixed node (one with both text and
+    // children) with the given text. `Repeat`s are over its children, and
+    // `MapText`s and `IfEmptyText`s refer to its text.
+    pub(crate) fn expand_mixed(&self, text: &str, precedences: &[Option<Precedence>])
+                               -> Notation
+    {
+        NotationExpander{
+            len: precedences.len(),
+            precedences,
+            streaming: true,
+            text: Some(text)
+        }.expand(self)
+    }
+
+    /// Merge adjacent `Literal`s that have the same style into a single
+    /// `Literal`, dropping empty ones. This does not change how the
+    /// notation is displayed, but leaves fewer combinators to lay out.
+    pub fn coalesce_literals(&self) -> Notation {
+        match self {
+            Empty | Literal(_, _) | Text(_) | MapText(_, _) | Child(_) | Star
+                | Count(..) => self.clone(),
+            ChildParens(i, prec, open, close) =>
+                child_parenthesized(*i, *prec, open.coalesce_literals(), close.coalesce_literals()),
+            Concat(_, _) => {
+                let mut parts = vec!();
+                self.concat_parts(&mut parts);
+                let mut merged: Vec<Notation> = vec!();
+                for part in parts {
+                    match (merged.last_mut(), part) {
+                        (_, Literal(ref s, _)) if s.is_empty() => (),
+                        (Some(Literal(ref mut s1, style1)), Literal(ref s2, style2))
+                            if *style1 == style2 => s1.push_str(s2),
+                        (_, part) => merged.push(part)
+                    }
+                }
+                match merged.pop() {
+                    None => empty(),
+                    Some(last) => merged.into_iter().rev()
+                        .fold(last, |note, part| part + note)
+                }
+            }
+            OptionalChild(i, a) => optional_child(*i, a.coalesce_literals()),
+            NoWrap(a, policy) => no_wrap_with(a.coalesce_literals(), *policy),
+            Joined(a, b) => joined(a.coalesce_literals(), b.coalesce_literals()),
+            Horz(a, b) => horz(a.coalesce_literals(), b.coalesce_literals()),
+            Vert(a, b) => vert(a.coalesce_literals(), b.coalesce_literals()),
+            Choice(a, b) => choice(a.coalesce_literals(), b.coalesce_literals()),
+            SoftBreak(a, b) => soft_break(a.coalesce_literals(), b.coalesce_literals()),
+            Group(a) => group(a.coalesce_literals()),
+            Weighted(a, weight) => weighted(a.coalesce_literals(), *weight),
+            MinWidth(cols, a) => min_width(*cols, a.coalesce_literals()),
+            WidthSwitch(threshold, a, b) =>
+                width_switch(*threshold, a.coalesce_literals(), b.coalesce_literals()),
+            IfEmptyText(a, b) =>
+                if_empty_text(a.coalesce_literals(), b.coalesce_literals()),
+            Rep(repeat) => Rep(Box::new(repeat.map(Notation::coalesce_literals))),
+            StreamedRep(repeat, len) =>
+                StreamedRep(Box::new(repeat.map(Notation::coalesce_literals)), *len),
+            JoinPresent(sep) => join_present(sep.coalesce_literals()),
+            Table(rows, sep) =>
+                table(rows.iter().map(|(left, right)| {
+                    (left.coalesce_literals(), right.coalesce_literals())
+                }).collect(), sep.coalesce_literals()),
+            Grid(rows) => grid(map_cells(rows, Notation::coalesce_literals))
+        }
+    }
+
+    /// Replace the style of every `Literal` and `Text` (and `MapText` and
+    /// `Count`) in this notation with `f` of its style, e.g. to recolor
+    /// it with a different theme.
+    pub fn map_styles<F>(&self, f: &F) -> Notation
+        where F: Fn(Style) -> Style
+    {
+        match self {
+            Empty | Child(_) | Star => self.clone(),
+            Literal(s, style) => Literal(s.clone(), f(*style)),
+            Text(style) => Text(f(*style)),
+            MapText(style, g) => MapText(f(*style), *g),
+            Count(prefix, suffix, style) => Count(prefix.clone(), suffix.clone(), f(*style)),
+            ChildParens(i, prec, open, close) =>
+                child_parenthesized(*i, *prec, open.map_styles(f), close.map_styles(f)),
+            Concat(a, b) => a.map_styles(f) + b.map_styles(f),
+            Horz(a, b) => horz(a.map_styles(f), b.map_styles(f)),
+            Vert(a, b) => vert(a.map_styles(f), b.map_styles(f)),
+            OptionalChild(i, a) => optional_child(*i, a.map_styles(f)),
+            NoWrap(a, policy) => no_wrap_with(a.map_styles(f), *policy),
+            Joined(a, b) => joined(a.map_styles(f), b.map_styles(f)),
+            Choice(a, b) => choice(a.map_styles(f), b.map_styles(f)),
+            SoftBreak(a, b) => soft_break(a.map_styles(f), b.map_styles(f)),
+            Group(a) => group(a.map_styles(f)),
+            Weighted(a, weight) => weighted(a.map_styles(f), *weight),
+            MinWidth(cols, a) => min_width(*cols, a.map_styles(f)),
+            WidthSwitch(threshold, a, b) =>
+                width_switch(*threshold, a.map_styles(f), b.map_styles(f)),
+            IfEmptyText(a, b) => if_empty_text(a.map_styles(f), b.map_styles(f)),
+            Rep(repeat) => Rep(Box::new(repeat.map(|part| part.map_styles(f)))),
+            StreamedRep(repeat, len) =>
+                StreamedRep(Box::new(repeat.map(|part| part.map_styles(f))), *len),
+            JoinPresent(sep) => join_present(sep.map_styles(f)),
+            Table(rows, sep) =>
+                table(rows.iter().map(|(left, right)| {
+                    (left.map_styles(f), right.map_styles(f))
+                }).collect(), sep.map_styles(f)),
+            Grid(rows) => grid(map_cells(rows, |cell| cell.map_styles(f)))
+        }
+    }
+
+    // Flatten a chain of `Concat`s, coalescing each of its parts.
+    fn concat_parts(&self, parts: &mut Vec<Notation>) {
+        match self {
+            Concat(a, b) => {
+                a.concat_parts(parts);
+                b.concat_parts(parts);
+            }
+            _ => parts.push(self.coalesce_literals())
+        }
+    }
+
+    // Put `indent` at the start of every line but the first. A chain of
+    // `Vert`s is treated as a whole, so that each line in it is indented
+    // once, no matter how the chain is associated.
+    fn nested(&self, indent: &Notation) -> Notation {
+        match self {
+            Empty | Literal(_, _) | Text(_) | MapText(_, _) | Child(_) | Star
+                | Count(..) => self.clone(),
+            Vert(_, _) => {
+                let mut lines = vec!();
+                self.vert_parts(&mut lines);
+                let mut lines = lines.into_iter().map(|line| line.nested(indent));
+                let first = lines.next().expect("vert_parts: no lines");
+                lines.fold(first, |note, line| vert(note, indent.clone() + line))
+            }
+            ChildParens(i, prec, open, close) =>
+                child_parenthesized(*i, *prec, open.nested(indent), close.nested(indent)),
+            Concat(a, b) => a.nested(indent) + b.nested_soft_breaks(indent),
+            SoftBreak(_, _) => self.nested_soft_breaks(indent),
+            Horz(a, b) => horz(a.nested(indent), b.nested(indent)),
+            OptionalChild(i, a) => optional_child(*i, a.nested(indent)),
+            NoWrap(a, policy) => no_wrap_with(a.nested(indent), *policy),
+            Joined(a, b) => joined(a.nested(indent), (**b).clone()),
+            Choice(a, b) => choice(a.nested(indent), b.nested(indent)),
+            Group(a) => group(a.nested(indent)),
+            Weighted(a, weight) => weighted(a.nested(indent), *weight),
+            MinWidth(cols, a) => min_width(*cols, a.nested(indent)),
+            WidthSwitch(threshold, a, b) =>
+                width_switch(*threshold, a.nested(indent), b.nested(indent)),
+            IfEmptyText(a, b) => if_empty_text(a.nested(indent), b.nested(indent)),
+            Rep(repeat) => Rep(Box::new(repeat.map(|part| part.nested(indent)))),
+            StreamedRep(repeat, len) =>
+                StreamedRep(Box::new(repeat.map(|part| part.nested(indent))), *len),
+            JoinPresent(sep) => join_present(sep.nested(indent)),
+            Table(rows, sep) =>
+                table(rows.iter().map(|(left, right)| {
+                    (left.nested(indent), right.nested(indent))
+                }).collect(), sep.nested(indent)),
+            Grid(rows) => grid(map_cells(rows, |cell| cell.nested(indent)))
+        }
+    }
+
+    // Put `indent` at the start of the line after every `SoftBreak` in
+    // this chain of `Concat`s. (The rest of the chain's line breaks are
+    // relative to where their part of the chain starts.)
+    fn nested_soft_breaks(&self, indent: &Notation) -> Notation {
+        match self {
+            Concat(a, b) => a.nested_soft_breaks(indent) + b.nested_soft_breaks(indent),
+            SoftBreak(a, b) => soft_break((**a).clone(), indent.clone() + (**b).clone()),
+            _ => self.clone()
+        }
+    }
+
+    // The number of `Choice`s (and `SoftBreak`s) in this notation.
+    pub(crate) fn count_choices(&self) -> usize {
+        match self {
+            Empty | Literal(_, _) | Text(_) | MapText(_, _) | Child(_) | Star
+                | Count(..) => 0,
+            Choice(a, b) | SoftBreak(a, b) => 1 + a.count_choices() + b.count_choices(),
+            Concat(a, b) | Horz(a, b) | Vert(a, b) | Joined(a, b)
+                | WidthSwitch(_, a, b) | IfEmptyText(a, b) | ChildParens(_, _, a, b) =>
+                a.count_choices() + b.count_choices(),
+            NoWrap(a, _) | Weighted(a, _) | MinWidth(_, a) | OptionalChild(_, a)
+                | JoinPresent(a) | Group(a) => a.count_choices(),
+            Rep(repeat) =>
+                repeat.empty.count_choices() + repeat.lone.count_choices()
+                + repeat.first.count_choices() + repeat.middle.count_choices()
+                + repeat.last.count_choices(),
+            StreamedRep(repeat, len) =>
+                repeat.first.count_choices() + repeat.last.count_choices()
+                + (len - 2) * repeat.middle.count_choices(),
+            Table(rows, sep) =>
+                rows.iter().map(|(left, right)| {
+                    left.count_choices() + right.count_choices() + sep.count_choices()
+                }).sum(),
+            Grid(rows) => rows.iter().flatten().map(Notation::count_choices).sum()
+        }
+    }
+
+    /// The number of levels of nesting in this notation: 1 for a notation
+    /// with no sub-notations, and one more than its deepest sub-notation
+    /// otherwise. Unlike laying out, this does not recurse, so it is safe
+    /// to call on notations that are too deep to lay out.
+    pub fn depth(&self) -> usize {
+        let mut max_depth = 0;
+        let mut stack = vec!((1, self));
+        while let Some((depth, notation)) = stack.pop() {
+            max_depth = max_depth.max(depth);
+            stack.extend(notation.parts().into_iter().map(|part| (depth + 1, part)));
+        }
+        max_depth
+    }
+
+    /// Whether this notation displays the node's text (with `Text` or
+    /// `MapText`), in any of its choices.
+    pub fn uses_text(&self) -> bool {
+        let mut stack = vec!(self);
+        while let Some(notation) = stack.pop() {
+            match notation {
+                Text(_) | MapText(_, _) => return true,
+                _ => stack.extend(notation.parts())
+            }
+        }
+        false
+    }
+
+    /// The indices of the children this notation displays by index (with
+    /// `Child`, `ChildParens`, or `OptionalChild`), in any of its choices,
+    /// in increasing order. Children displayed by a `Repeat` are not
+    /// included.
+    pub fn child_indices(&self) -> Vec<usize> {
+        let mut indices = vec!();
+        let mut stack = vec!(self);
+        while let Some(notation) = stack.pop() {
+            match notation {
+                Child(i) | ChildParens(i, _, _, _) | OptionalChild(i, _) => indices.push(*i),
+                _ => ()
+            }
+            stack.extend(notation.parts());
+        }
+        indices.sort();
+        indices.dedup();
+        indices
+    }
+
+    // The immediate sub-notations of this notation.
+    pub(crate) fn parts(&self) -> Vec<&Notation> {
+        match self {
+            Empty | Literal(_, _) | Text(_) | MapText(_, _) | Child(_) | Star
+                | Count(..) => vec!(),
+            Concat(a, b) | Horz(a, b) | Vert(a, b) | Joined(a, b) | Choice(a, b)
+                | SoftBreak(a, b) | WidthSwitch(_, a, b) | IfEmptyText(a, b)
+                | ChildParens(_, _, a, b) =>
+                vec!(a, b),
+            NoWrap(a, _) | Weighted(a, _) | MinWidth(_, a) | OptionalChild(_, a)
+                | JoinPresent(a) | Group(a) => vec!(a),
+            Rep(repeat) | StreamedRep(repeat, _) =>
+                vec!(&repeat.empty, &repeat.lone, &repeat.first, &repeat.middle, &repeat.last),
+            Table(rows, sep) => {
+                let mut parts: Vec<&Notation> = rows.iter()
+                    .flat_map(|(left, right)| vec!(left, right))
+                    .collect();
+                parts.push(sep);
+                parts
+            }
+            Grid(rows) => rows.iter().flatten().collect()
+        }
+    }
+
+    // Flatten a chain of `Vert`s.
+    fn vert_parts<'a>(&'a self, parts: &mut Vec<&'a Notation>) {
+        match self {
+            Vert(a, b) => {
+                a.vert_parts(parts);
+                b.vert_parts(parts);
+            }
+            _ => parts.push(self)
+        }
+    }
+
+    pub(crate) fn replace_star(&self, child: usize) -> Notation {
         match self {
             &Empty => Empty,
-            &Literal(_, _) | &Text(_) | &Child(_) => self.clone(),
-            &NoWrap(ref s) => no_wrap(s.replace_star(child)),
+            &Literal(_, _) | &Count(..) | &Text(_) | &Child(_) => self.clone(),
+            MapText(_, _) => self.clone(),
+            &ChildParens(i, prec, ref open, ref close) =>
+                child_parenthesized(i, prec, open.replace_star(child), close.replace_star(child)),
+            OptionalChild(i, a) => optional_child(*i, a.replace_star(child)),
+            NoWrap(s, policy) => no_wrap_with(s.replace_star(child), *policy),
+            JoinPresent(sep) => join_present(sep.replace_star(child)),
+            Joined(a, b) =>
+                joined(a.replace_star(child), b.replace_star(child)),
             &Concat(ref a, ref b) =>
                 a.replace_star(child) + b.replace_star(child),
             &Horz(ref a, ref b) =>
@@ -211,8 +876,21 @@ impl Notation {
                 if_empty_text(a.replace_star(child), b.replace_star(child)),
             &Choice(ref a, ref b) =>
                 a.replace_star(child) | b.replace_star(child),
+            SoftBreak(a, b) => soft_break(a.replace_star(child), b.replace_star(child)),
+            Group(a) => group(a.replace_star(child)),
+            &Weighted(ref a, weight) =>
+                weighted(a.replace_star(child), weight),
+            MinWidth(cols, a) => min_width(*cols, a.replace_star(child)),
+            &WidthSwitch(threshold, ref a, ref b) =>
+                width_switch(threshold, a.replace_star(child), b.replace_star(child)),
             &Star => Child(child),
-            &Rep(_) => panic!("Invalid notation: nested repeats not allowed")
+            &Table(ref rows, ref sep) =>
+                table(rows.iter().map(|(left, right)| {
+                    (left.replace_star(child), right.replace_star(child))
+                }).collect(), sep.replace_star(child)),
+            Grid(rows) => grid(map_cells(rows, |cell| cell.replace_star(child))),
+            &Rep(_) | &StreamedRep(..) =>
+                panic!("Invalid notation: nested repeats not allowed")
         }
     }
 }
@@ -1,12 +1,16 @@
 use std::ops::Index;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 
 use crate::notation::*;
 use crate::style::{Style, Color};
+use crate::geometry::{WidthFn, CharCount};
 use crate::layout::Bounds;
-use crate::pretty::pretty_doc::PrettyDocument;
+use crate::pretty::pretty_doc::{PrettyDocument, refresh_bounds};
 
-use self::ExampleNode::{Branch, Leaf};
+use self::ExampleNode::{Branch, Leaf, Mixed};
 
 // TODO: test horz concat
 
@@ -15,12 +19,25 @@ pub struct ExampleTree {
     arity: usize,
     node: ExampleNode,
     notation: Notation,
-    bounds: Bounds
+    summary: Option<Notation>,
+    precedence: Option<Precedence>,
+    style_override: Option<Style>,
+    bounds: RefCell<Bounds>,
+    dirty: Cell<bool>,
+    recomputed: Cell<usize>,
+    hashed: bool,
+    // The hash of this subtree's contents, if it's been computed since
+    // the subtree was last edited.
+    hash: Cell<Option<u64>>,
+    // Only the root's is used.
+    width_fn: &'static dyn WidthFn
 }
 
 pub enum ExampleNode {
     Branch(Vec<ExampleTree>),
-    Leaf(String)
+    Leaf(String),
+    /// A node with both text and children.
+    Mixed(String, Vec<ExampleTree>)
 }
 
 #[derive(Clone)]
@@ -33,27 +50,155 @@ impl ExampleTree {
     pub fn new_branch(arity: usize, notation: Notation, children: Vec<ExampleTree>)
                       -> ExampleTree
     {
-        let mut tree = ExampleTree {
+        let tree = ExampleTree {
             arity: arity,
             node: Branch(children),
-            bounds: Bounds::empty(),
-            notation: notation
+            bounds: RefCell::new(Bounds::empty()),
+            dirty: Cell::new(true),
+            recomputed: Cell::new(0),
+            notation: notation,
+            summary: None,
+            precedence: None,
+            style_override: None,
+            hashed: false,
+            hash: Cell::new(None),
+            width_fn: &CharCount
         };
-        tree.bounds = Bounds::compute(&tree.as_ref());
+        refresh_bounds(&tree.as_ref());
         tree
     }
 
     pub fn new_leaf(notation: Notation, contents: &str) -> ExampleTree {
-        let mut tree = ExampleTree {
+        let tree = ExampleTree {
             arity: 0,
             node: Leaf(contents.to_string()),
-            bounds: Bounds::empty(),
-            notation: notation
+            bounds: RefCell::new(Bounds::empty()),
+            dirty: Cell::new(true),
+            recomputed: Cell::new(0),
+            notation: notation,
+            summary: None,
+            precedence: None,
+            style_override: None,
+            hashed: false,
+            hash: Cell::new(None),
+            width_fn: &CharCount
         };
-        tree.bounds = Bounds::compute(&tree.as_ref());
+        refresh_bounds(&tree.as_ref());
         tree
     }
 
+    pub fn new_mixed(notation: Notation, contents: &str, children: Vec<ExampleTree>)
+                     -> ExampleTree
+    {
+        let tree = ExampleTree {
+            arity: children.len(),
+            node: Mixed(contents.to_string(), children),
+            bounds: RefCell::new(Bounds::empty()),
+            dirty: Cell::new(true),
+            recomputed: Cell::new(0),
+            notation,
+            summary: None,
+            precedence: None,
+            style_override: None,
+            hashed: false,
+            hash: Cell::new(None),
+            width_fn: &CharCount
+        };
+        refresh_bounds(&tree.as_ref());
+        tree
+    }
+
+    pub fn with_summary(mut self, summary: Notation) -> ExampleTree {
+        self.summary = Some(summary);
+        self
+    }
+
+    pub fn with_precedence(mut self, precedence: Precedence) -> ExampleTree {
+        self.precedence = Some(precedence);
+        self
+    }
+
+    pub fn with_style_override(mut self, style: Style) -> ExampleTree {
+        self.style_override = Some(style);
+        self
+    }
+
+    /// Give this node and all of its descendants a `structural_hash`, so
+    /// that identical subtrees share their Bounds when refreshed.
+    pub fn with_structural_hash(mut self) -> ExampleTree {
+        self.hashed = true;
+        if let Branch(children) | Mixed(_, children) = &mut self.node {
+            for child in children.iter_mut() {
+                let tree = std::mem::replace(child, ExampleTree::new_leaf(empty(), ""));
+                *child = tree.with_structural_hash();
+            }
+        }
+        self
+    }
+
+    /// Measure the literals and text of the whole tree with `width_fn`,
+    /// recomputing its Bounds.
+    pub fn with_width_fn(mut self, width_fn: &'static dyn WidthFn) -> ExampleTree {
+        self.width_fn = width_fn;
+        self.mark_dirty();
+        refresh_bounds(&self.as_ref());
+        self
+    }
+
+    fn mark_dirty(&self) {
+        self.dirty.set(true);
+        if let Branch(children) | Mixed(_, children) = &self.node {
+            for child in children {
+                child.mark_dirty();
+            }
+        }
+    }
+
+    // A hash of this subtree's contents, computed from the (cached)
+    // hashes of its children.
+    fn content_hash(&self) -> u64 {
+        if let Some(hash) = self.hash.get() {
+            return hash;
+        }
+        let mut hasher = DefaultHasher::new();
+        format!("{:?}", self.notation).hash(&mut hasher);
+        format!("{:?}", self.summary).hash(&mut hasher);
+        self.precedence.hash(&mut hasher);
+        match &self.node {
+            Leaf(text) => text.hash(&mut hasher),
+            Branch(children) | Mixed(_, children) => {
+                if let Mixed(text, _) = &self.node {
+                    text.hash(&mut hasher);
+                }
+                children.len().hash(&mut hasher);
+                for child in children {
+                    child.content_hash().hash(&mut hasher);
+                }
+            }
+        }
+        let hash = hasher.finish();
+        self.hash.set(Some(hash));
+        hash
+    }
+
+    /// Replace the text of the leaf at `path`, marking it and its
+    /// ancestors dirty.
+    pub fn set_text(&mut self, path: &[usize], text: &str) {
+        self.dirty.set(true);
+        self.hash.set(None);
+        match (path.split_first(), &mut self.node) {
+            (None, Leaf(contents)) | (None, Mixed(contents, _)) => *contents = text.to_string(),
+            (Some((&i, path)), Branch(children)) | (Some((&i, path)), Mixed(_, children)) =>
+                children[i].set_text(path, text),
+            _ => panic!("set_text: path does not lead to a leaf")
+        }
+    }
+
+    /// The number of times this node's Bounds have been computed.
+    pub fn times_recomputed(&self) -> usize {
+        self.recomputed.get()
+    }
+
     pub fn as_ref(&self) -> ExampleTreeRef {
         ExampleTreeRef {
             root: self,
@@ -68,7 +213,8 @@ impl<'a> Index<&'a [usize]> for ExampleTree {
         match &path {
             &[] => self,
             &[i, path..] => match &self.node {
-                ExampleNode::Branch(children) => children[*i].index(path),
+                ExampleNode::Branch(children) | ExampleNode::Mixed(_, children) =>
+                    children[*i].index(path),
                 ExampleNode::Leaf(_) => panic!("leaf node")
             }
         }
@@ -110,7 +256,7 @@ impl<'t> PrettyDocument for ExampleTreeRef<'t> {
     // TODO: panic if index out of bounds
     fn child(&self, i: usize) -> ExampleTreeRef<'t> {
         match &self.tree().node {
-            ExampleNode::Branch(_) => {
+            ExampleNode::Branch(_) | ExampleNode::Mixed(_, _) => {
                 ExampleTreeRef {
                     root: self.root,
                     path: extend_path(self.path.clone(), i)
@@ -122,7 +268,7 @@ impl<'t> PrettyDocument for ExampleTreeRef<'t> {
     
     fn children(&self) -> Vec<ExampleTreeRef<'t>> {
         match &self.tree().node {
-            ExampleNode::Branch(children) => {
+            ExampleNode::Branch(children) | ExampleNode::Mixed(_, children) => {
                 children.iter().enumerate().map(|(i, _)| {
                     ExampleTreeRef {
                         root: self.root,
@@ -137,15 +283,50 @@ impl<'t> PrettyDocument for ExampleTreeRef<'t> {
     fn notation(&self) -> &Notation {
         &self.tree().notation
     }
+
+    fn summary_notation(&self) -> Option<&Notation> {
+        self.tree().summary.as_ref()
+    }
+
+    fn precedence(&self) -> Option<Precedence> {
+        self.tree().precedence
+    }
     
+    fn style_override(&self) -> Option<Style> {
+        self.tree().style_override
+    }
+
+    fn structural_hash(&self) -> Option<u64> {
+        let tree = self.tree();
+        if tree.hashed {
+            Some(tree.content_hash())
+        } else {
+            None
+        }
+    }
+
+    fn width_fn(&self) -> &dyn WidthFn {
+        self.root.width_fn
+    }
+
     fn bounds(&self) -> Bounds {
-        self.tree().bounds.clone()
+        self.tree().bounds.borrow().clone()
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.tree().dirty.get()
+    }
+
+    fn set_bounds(&self, bounds: Bounds) {
+        *self.tree().bounds.borrow_mut() = bounds;
+        self.tree().dirty.set(false);
+        self.tree().recomputed.set(self.tree().recomputed.get() + 1);
     }
 
     fn text(&self) -> Option<&str> {
         match &self.tree().node {
             ExampleNode::Branch(_) => None,
-            ExampleNode::Leaf(s)   => Some(s)
+            ExampleNode::Leaf(s) | ExampleNode::Mixed(s, _) => Some(s)
         }
     }
 }
@@ -4,15 +4,24 @@ mod pretty_formatter;
 mod example;
 //mod locate_cursor;
 
-//pub use self::pretty::*;
+pub use self::pretty_doc::{PrettyDocument, BoundsCache};
+pub use self::pretty_screen::PrettyScreen;
+pub use self::pretty_formatter::{PlainText, IndentStyle, SpanText};
 //pub use self::example::*;
 
 
 #[cfg(test)]
 mod tests {
-    use super::pretty_formatter::PlainText;
-    use super::pretty_doc::PrettyDocument;
+    use std::ops::Range;
+    use super::pretty_formatter::{PlainText, SpanText, IndentStyle};
+    use super::pretty_screen::PrettyScreen;
+    use crate::geometry::{Pos, Direction};
+    use super::pretty_doc::{PrettyDocument, BoundsCache, LayoutCache, refresh_bounds,
+                            pretty_print_with_cache};
     use super::example::{ExampleTree, make_example_tree};
+    use crate::notation::*;
+    use crate::style::{Style, Color, Emph, Shade};
+    use crate::layout::Layouts;
 
     impl ExampleTree {
         fn write(&self, width: usize) -> String {
@@ -22,6 +31,49 @@ mod tests {
         }
     }
 
+    /// Render `doc` at each width in `widths`, from widest to narrowest,
+    /// and return the breakpoints: each width at which the rendering
+    /// differs from the rendering at the next larger width, together with
+    /// the new rendering. (The widest width is always included.)
+    fn render_widths(doc: &ExampleTree, widths: Range<usize>) -> Vec<(usize, String)> {
+        let mut breakpoints: Vec<(usize, String)> = vec!();
+        for width in widths.rev() {
+            let rendering = doc.write(width);
+            if breakpoints.last().map(|(_, prev)| prev != &rendering).unwrap_or(true) {
+                breakpoints.push((width, rendering));
+            }
+        }
+        breakpoints
+    }
+
+    #[test]
+    fn test_render_widths() {
+        let doc = make_example_tree();
+        let breakpoints = render_widths(&doc, 12..81);
+        let widths: Vec<usize> = breakpoints.iter().map(|(width, _)| *width).collect();
+        assert_eq!(widths, vec!(80, 41, 32, 20, 19, 14));
+        for (width, rendering) in &breakpoints {
+            assert_eq!(rendering, &doc.write(*width));
+        }
+        assert_eq!(breakpoints[3].1,
+                   "func foo(abc, def) {
+  'abcdef'
+  + 'abcdef'
+}");
+        assert_eq!(render_widths(&doc, 33..42).len(), 1);
+    }
+
+    #[test]
+    fn test_min_width_for_height() {
+        let doc = make_example_tree();
+        let lays = Layouts::compute(&doc.as_ref());
+        assert_eq!(lays.min_width_for_height(1), Some(42));
+        assert_eq!(lays.min_width_for_height(2), Some(33));
+        assert_eq!(doc.write(33).lines().count(), 2);
+        assert_eq!(doc.write(32).lines().count(), 3);
+        assert_eq!(lays.min_width_for_height(0), None);
+    }
+
     #[test]
     fn test_lay_out() {
         let doc = make_example_tree();
@@ -75,4 +127,544 @@ mod tests {
   + 'abcdef'
 }");
     }
+
+    #[test]
+    fn test_rendered_height() {
+        let doc = make_example_tree();
+        assert_eq!(doc.as_ref().rendered_height(80), 1);
+        assert_eq!(doc.as_ref().rendered_height(41), 2);
+        assert_eq!(doc.as_ref().rendered_height(20), 4);
+        assert_eq!(doc.as_ref().rendered_height(14), 6);
+    }
+
+    #[test]
+    fn test_refresh_bounds() {
+        let mut doc = make_example_tree();
+        assert_eq!(refresh_bounds(&doc.as_ref()), 0);
+
+        // The leaf, `add`, and `function` nodes are recomputed; the other
+        // four nodes are not.
+        doc.set_text(&[2, 1], "xyz");
+        assert_eq!(refresh_bounds(&doc.as_ref()), 3);
+        assert_eq!(refresh_bounds(&doc.as_ref()), 0);
+        assert_eq!(doc.write(80),
+                   "func foo(abc, def) { 'abcdef' + 'xyz' }");
+
+        // Pretty-printing refreshes the bounds first.
+        doc.set_text(&[1, 0], "a_much_longer_argument_name");
+        assert_eq!(doc.write(45),
+                   "func foo(a_much_longer_argument_name,
+         def) { 'abcdef' + 'xyz' }");
+        assert_eq!(refresh_bounds(&doc.as_ref()), 0);
+    }
+
+    #[test]
+    fn test_child_bounds_cache() {
+        let mut doc = make_example_tree();
+        doc.set_text(&[2, 1], "xyz");
+        refresh_bounds(&doc.as_ref());
+        // The edited leaf was recomputed, but its sibling's bounds were
+        // taken from the cache.
+        assert_eq!(doc[&[2, 1]].times_recomputed(), 2);
+        assert_eq!(doc[&[2]].times_recomputed(), 2);
+        assert_eq!(doc[&[2, 0]].times_recomputed(), 1);
+        assert_eq!(doc[&[1]].times_recomputed(), 1);
+
+        // Bounds can also be kept outside of the document, by node id.
+        let cache = BoundsCache::new();
+        let doc = make_example_tree();
+        for (id, path) in [[1, 0], [1, 1], [2, 0]].iter().enumerate() {
+            cache.insert(id, doc[&path[..]].as_ref().bounds());
+        }
+        assert!(cache.contains(&1));
+        let widths: Vec<_> = cache.get_all(&[2, 0]).iter()
+            .map(|bounds| bounds.min_width())
+            .collect();
+        assert_eq!(widths, vec!(Some(8), Some(3)));
+        cache.invalidate(&1);
+        assert!(!cache.contains(&1));
+        assert!(cache.get(&1).is_none());
+    }
+
+    #[test]
+    fn test_render_with_highlights() {
+        use crate::geometry::{Bound, Region};
+        use crate::layout::{Layout, Lay, LayoutRegion};
+        use crate::style::{Color, Emph, Shade};
+
+        struct StyledScreen(Vec<(Pos, String, Style)>);
+
+        impl PrettyScreen for StyledScreen {
+            type Error = ();
+            fn size(&self) -> Result<Bound, ()> {
+                Ok(Bound::infinite_scroll(80))
+            }
+            fn print(&mut self, pos: Pos, text: &str, style: Style) -> Result<(), ()> {
+                self.0.push((pos, text.to_string(), style));
+                Ok(())
+            }
+            fn shade(&mut self, _region: Region, _shade: Shade) -> Result<(), ()> {
+                Ok(())
+            }
+            fn highlight(&mut self, _pos: Pos, _style: Style) -> Result<(), ()> {
+                Ok(())
+            }
+        }
+
+        let red = Style::color(Color::Base08);
+        let lay = LayoutRegion::literal("abc", red)
+            .concat(LayoutRegion::literal("def", red)
+                    .vert(LayoutRegion::literal("g", red)));
+        // Highlight the second half: "def" and "g"
+        let region = match &lay.layout {
+            Layout::Concat(_, second) => second.region,
+            _ => unreachable!()
+        };
+        let selected = Style::new(Color::Base00, Emph::underlined(), Shade(0), false);
+        let mut screen = StyledScreen(vec!());
+        let doc = ExampleTree::new_leaf(empty(), "");
+        lay.render_with_highlights(&doc.as_ref(), &[(region, selected)], &mut screen).unwrap();
+
+        let highlighted = red.overlay(selected);
+        assert_eq!(highlighted, Style::new(Color::Base08, Emph::underlined(), Shade(0), false));
+        let cells: Vec<(Pos, &str, Style)> = screen.0.iter()
+            .map(|(pos, text, style)| (*pos, text.as_str(), *style))
+            .collect();
+        assert_eq!(cells, vec!((Pos{ row: 0, col: 0 }, "a", red),
+                               (Pos{ row: 0, col: 1 }, "b", red),
+                               (Pos{ row: 0, col: 2 }, "c", red),
+                               (Pos{ row: 0, col: 3 }, "d", highlighted),
+                               (Pos{ row: 0, col: 4 }, "e", highlighted),
+                               (Pos{ row: 0, col: 5 }, "f", highlighted),
+                               (Pos{ row: 1, col: 3 }, "g", highlighted)));
+
+        // Highlights also apply to the text of leaves, inside of children.
+        let leaf = |s: &str| ExampleTree::new_leaf(text(red), s);
+        let doc = ExampleTree::new_branch(2, child(0) + literal("=", red) + child(1), vec!(
+            leaf("ab"),
+            leaf("cd")));
+        let lay = Layouts::compute(&doc.as_ref()).fit_width(80);
+        let region = Region {
+            pos: Pos{ row: 0, col: 1 },
+            bound: Bound{ width: 3, indent: 3, height: 0 }
+        };
+        let mut screen = StyledScreen(vec!());
+        lay.render_with_highlights(&doc.as_ref(), &[(region, selected)], &mut screen).unwrap();
+        let cells: Vec<(Pos, &str, Style)> = screen.0.iter()
+            .map(|(pos, text, style)| (*pos, text.as_str(), *style))
+            .collect();
+        assert_eq!(cells, vec!((Pos{ row: 0, col: 0 }, "a", red),
+                               (Pos{ row: 0, col: 1 }, "b", highlighted),
+                               (Pos{ row: 0, col: 2 }, "=", highlighted),
+                               (Pos{ row: 0, col: 3 }, "c", highlighted),
+                               (Pos{ row: 0, col: 4 }, "d", red)));
+    }
+
+    #[test]
+    fn test_summary() {
+        let lit = |s: &str| literal(s, Style::plain());
+        let string = ExampleTree::new_leaf(
+            lit("'") + text(Style::plain()) + lit("'"),
+            "a very long string literal");
+        let call = ExampleTree::new_branch(
+            1, lit("call(") + child(0) + lit(")"), vec!(string))
+            .with_summary(lit("call(…)"));
+        let doc = ExampleTree::new_branch(
+            1, lit("[") + child(0) + lit("]"), vec!(call));
+        assert_eq!(doc.write(80), "[call('a very long string literal')]");
+        assert_eq!(doc.write(36), "[call('a very long string literal')]");
+        assert_eq!(doc.write(35), "[call(…)]");
+        assert_eq!(doc.write(20), "[call(…)]");
+    }
+
+    #[test]
+    fn test_child_parenthesized() {
+        let lit = |s: &str| literal(s, Style::plain());
+        let id = |name: &str| ExampleTree::new_leaf(text(Style::plain()), name);
+        let binop = |op: &str, prec, left, right| {
+            let note = child_parenthesized(0, prec, lit("("), lit(")"))
+                + lit(op)
+                + child_parenthesized(1, prec, lit("("), lit(")"));
+            ExampleTree::new_branch(2, note, vec!(left, right)).with_precedence(prec)
+        };
+        let plus = |left, right| binop(" + ", 1, left, right);
+        let times = |left, right| binop(" * ", 2, left, right);
+
+        // A `plus` inside a `times` binds more loosely, so needs parens.
+        let doc = times(plus(id("a"), id("b")), id("c"));
+        assert_eq!(doc.write(80), "(a + b) * c");
+        // A `times` inside a `plus` does not.
+        let doc = plus(times(id("a"), id("b")), id("c"));
+        assert_eq!(doc.write(80), "a * b + c");
+        // Nor does a child with the same precedence, or no precedence.
+        let doc = times(id("a"), times(id("b"), id("c")));
+        assert_eq!(doc.write(80), "a * b * c");
+        let doc = times(plus(id("a"), times(plus(id("b"), id("c")), id("d"))), id("e"));
+        assert_eq!(doc.write(80), "(a + (b + c) * d) * e");
+    }
+
+    #[test]
+    fn test_wrap_marker() {
+        let mut screen = PlainText::new(10).with_wrap_marker('↩');
+        screen.print(Pos{ row: 0, col: 0 }, "short", Style::plain()).unwrap();
+        screen.print(Pos{ row: 1, col: 2 }, "abcdefghijklmnopqrstuvw", Style::plain()).unwrap();
+        screen.print(Pos{ row: 2, col: 0 }, "exactly 10", Style::plain()).unwrap();
+        assert_eq!(format!("{}", screen),
+                   "short
+  abcdefg↩
+hijklmnop↩
+qrstuvw
+exactly 10");
+        let mut screen = PlainText::new(10);
+        screen.print(Pos{ row: 0, col: 2 }, "abcdefghijklmnopqrstuvw", Style::plain()).unwrap();
+        assert_eq!(format!("{}", screen), "  abcdefghijklmnopqrstuvw");
+    }
+
+    #[test]
+    fn test_indent_with_tabs() {
+        let doc = make_example_tree();
+        let write = |indent_with| {
+            let mut screen = PlainText::new(19).with_indent(indent_with);
+            doc.as_ref().pretty_print(&mut screen).unwrap();
+            format!("{}", screen)
+        };
+        assert_eq!(write(IndentStyle::Spaces), doc.write(19));
+        assert_eq!(write(IndentStyle::Tabs(4)),
+                   "func foo(abc,
+\t\t def) {
+  'abcdef'
+  + 'abcdef'
+}");
+        assert_eq!(write(IndentStyle::Tabs(2)),
+                   "func foo(abc,
+\t\t\t\t def) {
+\t'abcdef'
+\t+ 'abcdef'
+}");
+
+        // Only the start of a wrapped line counts as indentation.
+        let mut screen = PlainText::new(6)
+            .with_wrap_marker('↩')
+            .with_indent(IndentStyle::Tabs(2));
+        screen.print(Pos{ row: 0, col: 3 }, "ab    cd", Style::plain()).unwrap();
+        assert_eq!(format!("{}", screen), "\t ab↩\n    cd");
+    }
+
+    #[test]
+    fn test_span_text() {
+        let op = |s: &str| literal(s, Style::color(Color::Base0A));
+        let id = |name: &str| ExampleTree::new_leaf(text(Style::color(Color::Base0D)), name);
+        let plus = |left, right| {
+            ExampleTree::new_branch(2, (child(0) + op(" + ") + child(1))
+                                    | (child(0) ^ (op("+ ") + child(1))),
+                                    vec!(left, right))
+        };
+        let span = |s: &str, color| (s.to_string(), Style::color(color));
+
+        let doc = plus(id("a"), plus(id("bc"), id("d")));
+        let mut screen = SpanText::new(80);
+        doc.as_ref().pretty_print(&mut screen).unwrap();
+        assert_eq!(screen.lines(), vec!(vec!(
+            span("a", Color::Base0D), span(" + ", Color::Base0A),
+            span("bc", Color::Base0D), span(" + ", Color::Base0A),
+            span("d", Color::Base0D))));
+
+        // Indentation is plain; adjacent text in the same style is merged.
+        let doc = ExampleTree::new_branch(
+            2, op("[") + (child(0) ^ child(1)) + op("]") + op(";"), vec!(id("ab"), id("cd")));
+        let mut screen = SpanText::new(80);
+        doc.as_ref().pretty_print(&mut screen).unwrap();
+        assert_eq!(screen.lines(), vec!(
+            vec!(span("[", Color::Base0A), span("ab", Color::Base0D)),
+            vec!((" ".to_string(), Style::plain()),
+                 span("cd", Color::Base0D), span("];", Color::Base0A))));
+    }
+
+    #[test]
+    fn test_hang() {
+        let lit = |s: &str| literal(s, Style::plain());
+        let id = |name: &str| ExampleTree::new_leaf(text(Style::plain()), name);
+        let args = child(1) + lit(",") + softline() + child(2) + lit(",") + softline() + child(3);
+        let call = hang(child(0) + lit("("), args + lit(")"));
+        let doc = ExampleTree::new_branch(
+            4, lit("x = ") + call, vec!(id("f"), id("alpha"), id("beta"), id("gamma")));
+        assert_eq!(doc.write(80), "x = f(alpha, beta, gamma)");
+        assert_eq!(doc.write(20), "x = f(alpha, beta,\n      gamma)");
+        assert_eq!(doc.write(15), "x = f(alpha,\n      beta,\n      gamma)");
+    }
+
+    #[test]
+    fn test_structural_hash_cache() {
+        let lit = |s: &str| literal(s, Style::plain());
+        let id = |name: &str| ExampleTree::new_leaf(text(Style::plain()), name);
+        let pair = || ExampleTree::new_branch(
+            2, child(0) + lit(" + ") + child(1), vec!(id("x"), id("x")));
+        let make_doc = || ExampleTree::new_branch(
+            3, child(0) + lit(", ") + child(1) + lit(", ") + child(2),
+            vec!(pair(), pair(), pair()));
+        let rename = |doc: &mut ExampleTree| {
+            for i in 0..3 {
+                for j in 0..2 {
+                    doc.set_text(&[i, j], "yy");
+                }
+            }
+        };
+
+        // Without hashes, all 10 nodes are recomputed.
+        let mut doc = make_doc();
+        rename(&mut doc);
+        assert_eq!(refresh_bounds(&doc.as_ref()), 10);
+        let expected = doc.write(80);
+        assert_eq!(expected, "yy + yy, yy + yy, yy + yy");
+
+        // With them, only one leaf, one pair, and the root are.
+        let mut doc = make_doc().with_structural_hash();
+        rename(&mut doc);
+        assert_eq!(refresh_bounds(&doc.as_ref()), 3);
+        assert!(!doc[&[2, 1]].as_ref().is_dirty());
+        assert_eq!(doc.write(80), expected);
+
+        // Subtrees that differ are not shared.
+        rename(&mut doc);
+        doc.set_text(&[1, 1], "a_longer_name");
+        assert_eq!(refresh_bounds(&doc.as_ref()), 5);
+        assert_eq!(doc.write(80), "yy + yy, yy + a_longer_name, yy + yy");
+
+        // Identical subtrees are laid out once: after the first pair and
+        // its first leaf, the other two pairs and five leaves reuse them.
+        let print = |doc: &ExampleTree| {
+            let mut cache = LayoutCache::new();
+            let mut screen = PlainText::new(80);
+            pretty_print_with_cache(&doc.as_ref(), &mut screen, &mut cache).unwrap();
+            (format!("{}", screen), cache.hits())
+        };
+        let mut doc = make_doc();
+        rename(&mut doc);
+        assert_eq!(print(&doc), (expected.clone(), 0));
+        let mut doc = make_doc().with_structural_hash();
+        rename(&mut doc);
+        assert_eq!(print(&doc), (expected, 7));
+        doc.set_text(&[1, 1], "a_longer_name");
+        assert_eq!(print(&doc), ("yy + yy, yy + a_longer_name, yy + yy".to_string(), 5));
+    }
+
+    #[test]
+    fn test_map_text() {
+        let keyword = ExampleTree::new_leaf(
+            map_text(Style::plain(), |s: &str| s.to_uppercase()), "select");
+        let word = ExampleTree::new_leaf(text(Style::plain()), "name");
+        let mut doc = ExampleTree::new_branch(
+            2, (child(0) + literal(" ", Style::plain()) + child(1))
+                | (child(0) ^ child(1)),
+            vec!(keyword, word));
+        assert_eq!(doc.write(80), "SELECT name");
+        assert_eq!(doc[&[0]].as_ref().text(), Some("select"));
+
+        // The transformed text is what gets measured.
+        doc.set_text(&[0], "straße");
+        assert_eq!(doc.write(12), "STRASSE name");
+        assert_eq!(doc.write(11), "STRASSE\nname");
+        assert_eq!(doc[&[0]].as_ref().text(), Some("straße"));
+    }
+
+    #[test]
+    fn test_to_lines() {
+        let doc = make_example_tree();
+        let doc = doc.as_ref();
+        assert_eq!(doc.to_lines(80), vec!("func foo(abc, def) { 'abcdef' + 'abcdef' }"));
+        assert_eq!(doc.to_lines(33), vec!("func foo(abc, def) { 'abcdef'",
+                                          "                     + 'abcdef' }"));
+        assert_eq!(doc.to_lines(21), vec!("func foo(abc, def) {",
+                                          "  'abcdef' + 'abcdef'",
+                                          "}"));
+        // Rendering again gives the same lines.
+        assert_eq!(doc.to_lines(21), doc.to_lines(21));
+        assert_eq!(doc.to_lines(21).len(), doc.rendered_height(21));
+
+        let empty = ExampleTree::new_leaf(text(Style::plain()), "");
+        assert_eq!(empty.as_ref().to_lines(80), vec!(""));
+    }
+
+    #[test]
+    fn test_soft_limit() {
+        let long = "x".repeat(90);
+        let doc = ExampleTree::new_branch(3, child(0) ^ child(1) ^ child(2), vec!(
+            ExampleTree::new_leaf(text(Style::plain()), "short"),
+            ExampleTree::new_leaf(text(Style::plain()), &long),
+            ExampleTree::new_leaf(text(Style::plain()), &"y".repeat(80))));
+
+        let mut screen = PlainText::new(100);
+        doc.as_ref().pretty_print(&mut screen).unwrap();
+        assert_eq!(screen.over_limit_lines(), Vec::<usize>::new());
+        screen.set_soft_limit(80);
+        assert_eq!(screen.over_limit_lines(), vec!(1));
+        // The soft limit doesn't change what's displayed.
+        assert_eq!(format!("{}", screen), format!("short\n{}\n{}", long, "y".repeat(80)));
+    }
+
+    #[test]
+    fn test_mixed_node() {
+        // A template string: text, with an interpolated child.
+        let note = literal("`", Style::plain()) + text(Style::plain())
+            + literal("${", Style::plain()) + child(0) + literal("}`", Style::plain());
+        let doc = ExampleTree::new_mixed(note, "hello ", vec!(
+            ExampleTree::new_leaf(text(Style::plain()), "name")));
+        assert_eq!(doc.write(80), "`hello ${name}`");
+
+        let note = if_empty_text(child(0), text(Style::plain()) ^ child(0));
+        let mut doc = ExampleTree::new_mixed(note, "", vec!(make_example_tree()));
+        assert_eq!(doc.write(80), make_example_tree().write(80));
+        doc.set_text(&[], "header");
+        assert_eq!(doc.write(80), format!("header\n{}", make_example_tree().write(80)));
+
+        // A call: the function name is the text, and the arguments that
+        // aren't blank are joined. The text is not an argument.
+        let note = text(Style::plain()) + literal("(", Style::plain())
+            + join_present(literal(", ", Style::plain())) + literal(")", Style::plain());
+        let arg = |name: &str| ExampleTree::new_leaf(text(Style::plain()), name);
+        let doc = ExampleTree::new_mixed(note, "f", vec!(arg("a"), arg(""), arg("b")));
+        assert_eq!(doc.write(80), "f(a, b)");
+    }
+
+    #[test]
+    fn test_style_override() {
+        let op = |s: &str| literal(s, Style::color(Color::Base0A));
+        let id = |name: &str| ExampleTree::new_leaf(text(Style::color(Color::Base0D)), name);
+        let plus = |left, right| {
+            ExampleTree::new_branch(2, child(0) + op(" + ") + child(1), vec!(left, right))
+        };
+        let error = Style::new(Color::Base08, Emph::underlined(), Shade::background(), true);
+        let span = |s: &str, style| (s.to_string(), style);
+        let spans = |doc: ExampleTree| {
+            let mut screen = SpanText::new(80);
+            doc.as_ref().pretty_print(&mut screen).unwrap();
+            screen.lines()
+        };
+
+        let doc = plus(id("a"), id("b").with_style_override(error));
+        assert_eq!(spans(doc), vec!(vec!(
+            span("a", Style::color(Color::Base0D)),
+            span(" + ", Style::color(Color::Base0A)),
+            span("b", Style::color(Color::Base0D).overlay(error)))));
+
+        // The override does not extend to the node's children.
+        let doc = plus(id("a"), id("b")).with_style_override(error);
+        assert_eq!(spans(doc), vec!(vec!(
+            span("a", Style::color(Color::Base0D)),
+            span(" + ", Style::color(Color::Base0A).overlay(error)),
+            span("b", Style::color(Color::Base0D)))));
+    }
+
+    #[test]
+    fn test_diff_render() {
+        let doc = make_example_tree();
+        assert_eq!(doc.as_ref().diff_render(&doc.as_ref(), 42),
+                   "func foo(abc, def) { 'abcdef' + 'abcdef' }   \
+                    func foo(abc, def) { 'abcdef' + 'abcdef' }");
+
+        let mut changed = make_example_tree();
+        changed.set_text(&[2, 1], "abc");
+        assert_eq!(doc.as_ref().diff_render(&changed.as_ref(), 20),
+                   "func foo(abc, def) {   func foo(abc, def) {
+  'abcdef'           |   'abcdef' + 'abc'
+  + 'abcdef'         <
+}                      }");
+        assert_eq!(changed.as_ref().diff_render(&doc.as_ref(), 20),
+                   "func foo(abc, def) {   func foo(abc, def) {
+  'abcdef' + 'abc'   |   'abcdef'
+                     >   + 'abcdef'
+}                      }");
+    }
+
+    #[test]
+    fn test_first_overflow() {
+        let lit = |s: &str| literal(s, Style::plain());
+        let leaf = |s: &str| ExampleTree::new_leaf(text(Style::plain()), s);
+        let doc = ExampleTree::new_branch(2, child(0) ^ child(1), vec!(
+            leaf("short"),
+            ExampleTree::new_branch(1, lit("(") + child(0) + lit(")"), vec!(
+                leaf("much too long")))));
+        assert_eq!(doc.as_ref().first_overflow(20), None);
+        assert_eq!(doc.as_ref().first_overflow(10), Some(vec!(1, 0)));
+        assert_eq!(doc.as_ref().first_overflow(4), Some(vec!(0)));
+
+        let doc = make_example_tree();
+        assert_eq!(doc.as_ref().first_overflow(42), None);
+        assert_eq!(doc.as_ref().first_overflow(9), Some(vec!(2, 0)));
+    }
+
+    #[test]
+    fn test_right_to_left() {
+        let lit = |s: &str| literal(s, Style::plain());
+        let leaf = |s: &str| ExampleTree::new_leaf(text(Style::plain()), s);
+        let doc = ExampleTree::new_branch(2, (lit("if ") + child(0)) ^ (lit("  ") + child(1)), vec!(
+            leaf("x"),
+            leaf("yz")));
+        let mut screen = PlainText::new(8).with_direction(Direction::Rtl);
+        doc.as_ref().pretty_print(&mut screen).unwrap();
+        assert_eq!(format!("{}", screen),
+                   "    x fi\n    zy  ");
+
+        // Text that's too wide is cut off at the left edge, rather than
+        // drawn over the start of the line.
+        let doc = ExampleTree::new_branch(1, lit("if ") + no_wrap_with(child(0), OverflowPolicy::Clip), vec!(
+            leaf("abcdefghij")));
+        let mut screen = PlainText::new(8).with_direction(Direction::Rtl);
+        doc.as_ref().pretty_print(&mut screen).unwrap();
+        assert_eq!(format!("{}", screen), "edcba fi");
+
+        let doc = make_example_tree();
+        let mut screen = PlainText::new(30).with_direction(Direction::Rtl);
+        doc.as_ref().pretty_print(&mut screen).unwrap();
+        let mirrored: Vec<String> = doc.write(30).lines()
+            .map(|line| format!("{:>30}", line.chars().rev().collect::<String>()))
+            .collect();
+        assert_eq!(format!("{}", screen), mirrored.join("\n"));
+    }
+
+    #[test]
+    fn test_summary_count() {
+        let lit = |s: &str| literal(s, Style::plain());
+        let string = |s: &str| ExampleTree::new_leaf(
+            lit("'") + text(Style::plain()) + lit("'"), s);
+        let list_notation = repeat(Repeat{
+            empty:  lit("[]"),
+            lone:   lit("[") + star() + lit("]"),
+            first:  lit("[") + star() + lit(", "),
+            middle: star() + lit(", "),
+            last:   star() + lit("]")
+        });
+        let list = ExampleTree::new_branch(
+            3, list_notation, vec!(string("apple"), string("banana"), string("cherry")))
+            .with_summary(summary_count("[", " items]", Style::plain()));
+        let doc = ExampleTree::new_branch(
+            1, lit("x = ") + child(0), vec!(list));
+        assert_eq!(doc.write(80), "x = ['apple', 'banana', 'cherry']");
+        assert_eq!(doc.write(20), "x = [3 items]");
+    }
+
+    #[test]
+    fn test_document_width_fn() {
+        use crate::geometry::WidthFn;
+
+        // Capital letters are two columns wide.
+        struct WideCaps;
+        impl WidthFn for WideCaps {
+            fn width(&self, s: &str) -> usize {
+                s.chars().map(|ch| if ch.is_uppercase() { 2 } else { 1 }).sum()
+            }
+        }
+
+        let leaf = |s: &str| ExampleTree::new_leaf(text(Style::plain()), s);
+        let note = (child(0) + literal(" ", Style::plain()) + child(1)) | (child(0) ^ child(1));
+        let doc = || ExampleTree::new_branch(2, note.clone(), vec!(leaf("AB"), leaf("cd")));
+        assert_eq!(doc().write(5), "AB cd");
+        assert_eq!(doc().as_ref().rendered_height(5), 1);
+        let wide = doc().with_width_fn(&WideCaps);
+        assert_eq!(wide.write(5), "AB\ncd");
+        assert_eq!(wide.as_ref().rendered_height(5), 2);
+        // `PlainText` gives every character one cell, so the gap is
+        // where the wide letters would have been.
+        assert_eq!(wide.write(7), "AB   cd");
+    }
 }
@@ -1,12 +1,20 @@
-use crate::notation::Notation;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::geometry::{Bound, Col, Pos, Region, WidthFn, CharCount};
+use crate::style::Style;
+use crate::notation::{Notation, Precedence};
 use crate::layout::{LayoutRegion, Layout, Bounds, Layouts,
-                    compute_bounds, compute_layouts, text_bounds};
+                    compute_bounds_with, compute_layouts_with, text_bounds_with};
 use super::pretty_screen::PrettyScreen;
+use super::pretty_formatter::PlainText;
 use self::Layout::*;
 
 
 pub trait PrettyDocument : Sized + Clone {
     /// The minimum number of children this node can have. (See `grammar::Arity`)
+    /// A node with text and a non-zero arity is mixed: it has both.
     fn arity(&self) -> usize;
     /// The node's parent (or `None` if this is the root node).
     fn parent(&self) -> Option<Self>;
@@ -16,26 +24,178 @@ pub trait PrettyDocument : Sized + Clone {
     fn children(&self) -> Vec<Self>;
     /// The node's notation.
     fn notation(&self) -> &Notation;
-    /// If the node contains text, that text. Otherwise `None`.
+    /// A compact notation to display instead of this node (e.g. `Expr(…)`)
+    /// if it is too wide to fit on the screen. `None` means the node will
+    /// never be summarized.
+    fn summary_notation(&self) -> Option<&Notation> {
+        None
+    }
+    /// If the node contains text, that text. Otherwise `None`. (If the
+    /// node is mixed, it may also have children.)
     fn text(&self) -> Option<&str>;
+    /// How tightly this node binds, which determines whether its parent
+    /// wraps it in parentheses (see `Notation::ChildParens`). `None`
+    /// means it never needs them.
+    fn precedence(&self) -> Option<Precedence> {
+        None
+    }
+    /// A style to draw on top of the styles of this node's own literals
+    /// and text (see `Style::overlay`), e.g. to mark it as an error. Its
+    /// children keep their own styles. `None` means no override.
+    fn style_override(&self) -> Option<Style> {
+        None
+    }
+    /// A hash of the contents of this subtree (e.g. from
+    /// `forest::TreeRef::structural_hash`). When several identical
+    /// subtrees need their Bounds refreshed at once, `refresh_bounds`
+    /// uses it to compute them only once, and likewise `pretty_print`
+    /// lays them out only once. `None` means never share.
+    fn structural_hash(&self) -> Option<u64> {
+        None
+    }
+    /// How to measure the width of this node's literals and text. The
+    /// same for every node in a document. By default, every character is
+    /// one column wide.
+    fn width_fn(&self) -> &dyn WidthFn {
+        &CharCount
+    }
 
     // TODO: have this return a reference instead?
     /// Get the Bounds within which this document node can be displayed,
     /// given information about its children. **For efficiency, you should
     /// cache the result of `Bounds::compute` every time the document changes.**
     fn bounds(&self) -> Bounds;
+    /// The Bounds of each of this node's children, which are used to
+    /// compute its own Bounds. By default, each child's cached `bounds()`.
+    /// Implementations that keep their cache elsewhere (e.g. in a
+    /// `BoundsCache`, keyed by node id) can override this.
+    /// `refresh_bounds` always refreshes dirty children before calling it.
+    fn child_bounds_cache(&self) -> Vec<Bounds> {
+        self.children().iter().map(|child| child.bounds()).collect()
+    }
+    /// Whether this node's cached Bounds are out of date, because it or
+    /// one of its descendants was edited since they were computed.
+    /// Editing a node should mark it and all of its ancestors dirty.
+    fn is_dirty(&self) -> bool {
+        false
+    }
+    /// Cache freshly computed Bounds for this node, and mark it clean.
+    /// Only called on dirty nodes, by `refresh_bounds`.
+    fn set_bounds(&self, _bounds: Bounds) {}
 
     /// Pretty-print entire document.
     fn pretty_print<Screen>(&self, screen: &mut Screen) -> Result<(), Screen::Error>
         where Screen: PrettyScreen
     {
-        // TODO: wrong
-        let lay = Layouts::compute(self).fit_bound(screen.size()?);
-        pp(self, screen, lay)
+        pretty_print_with_cache(self, screen, &mut LayoutCache::new())
+    }
+
+    /// Render the entire document as plain text at the given width, one
+    /// string per line, with trailing whitespace removed. The output
+    /// depends only on the document and the width, so it is suitable for
+    /// line-based diffing. There is always at least one line.
+    fn to_lines(&self, width: usize) -> Vec<String> {
+        let mut screen = PlainText::new(width);
+        self.pretty_print(&mut screen).expect("to_lines: failed to render");
+        format!("{}", screen).split('\n')
+            .map(|line| line.trim_end().to_string())
+            .collect()
+    }
+
+    /// Render this document and `other` side by side at the given width,
+    /// with the lines aligned by a line diff (see `to_lines`). Between
+    /// the columns is a marker: blank if the lines are the same, `|` if
+    /// they differ, `<` if the line is only in this document, and `>` if
+    /// it is only in `other`.
+    fn diff_render<Other: PrettyDocument>(&self, other: &Other, width: usize) -> String {
+        let left = self.to_lines(width);
+        let right = other.to_lines(width);
+        let column = left.iter().map(|line| line.chars().count()).max().unwrap_or(0).max(width);
+        let rows: Vec<String> = diff_lines(&left, &right).into_iter().map(|row| {
+            let (left, marker, right) = match row {
+                (Some(l), Some(r)) if l == r => (l, ' ', r),
+                (Some(l), Some(r)) => (l, '|', r),
+                (Some(l), None) => (l, '<', ""),
+                (None, Some(r)) => ("", '>', r),
+                (None, None) => unreachable!()
+            };
+            format!("{:width$} {} {}", left, marker, right, width = column)
+                .trim_end().to_string()
+        }).collect();
+        rows.join("\n")
+    }
+
+    /// If the document does not fit within `width` when pretty-printed,
+    /// the path (of child indices, from this node) to the node that
+    /// sticks out past the right edge. If several do, it is the first one
+    /// in document order, and the deepest one along that path: the node
+    /// itself overflows, but none of its children do.
+    fn first_overflow(&self, width: usize) -> Option<Vec<usize>> {
+        refresh_bounds(self);
+        let width = width as Col;
+        let lays = layouts_within(self, width);
+        // If no layout fits, the narrowest one is the best there is.
+        let fit_width = lays.min_width().map_or(width, |min_width| min_width.max(width));
+        let lay = lays.fit_bound(Bound::infinite_scroll(fit_width));
+        find_overflow(self, &lay, width, vec!(), &mut LayoutCache::new())
+    }
+
+    /// The number of rows the entire document will occupy, if
+    /// pretty-printed at the given width. Cheaper than rendering it.
+    fn rendered_height(&self, width: usize) -> usize {
+        refresh_bounds(self);
+        let width = width as Col;
+        let lay = layouts_within(self, width).fit_bound(Bound::infinite_scroll(width));
+        lay.region.bound.height as usize + 1
     }
 }
 
 
+/// Align two sequences of lines, using a longest common subsequence of
+/// them. Lines that are in neither the subsequence nor the other document
+/// are paired up where possible, as changed lines.
+fn diff_lines<'a>(left: &'a [String], right: &'a [String])
+                  -> Vec<(Option<&'a str>, Option<&'a str>)>
+{
+    // lcs[i][j] is the length of the LCS of left[i..] and right[j..].
+    let mut lcs = vec!(vec!(0; right.len() + 1); left.len() + 1);
+    for i in (0..left.len()).rev() {
+        for j in (0..right.len()).rev() {
+            lcs[i][j] = if left[i] == right[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut rows = vec!();
+    let (mut removed, mut added) = (vec!(), vec!());
+    let flush = |rows: &mut Vec<_>, removed: &mut Vec<&'a str>, added: &mut Vec<&'a str>| {
+        for k in 0..removed.len().max(added.len()) {
+            rows.push((removed.get(k).cloned(), added.get(k).cloned()));
+        }
+        removed.clear();
+        added.clear();
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < left.len() || j < right.len() {
+        if i < left.len() && j < right.len() && left[i] == right[j] {
+            flush(&mut rows, &mut removed, &mut added);
+            rows.push((Some(left[i].as_str()), Some(right[j].as_str())));
+            i += 1;
+            j += 1;
+        } else if j == right.len() || (i < left.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            removed.push(left[i].as_str());
+            i += 1;
+        } else {
+            added.push(right[j].as_str());
+            j += 1;
+        }
+    }
+    flush(&mut rows, &mut removed, &mut added);
+    rows
+}
+
 /// _Compute_ the possible bounds of this node. This is required in order to
 /// pretty-print it. Note that:
 ///
@@ -45,33 +205,431 @@ pub trait PrettyDocument : Sized + Clone {
 /// pretty-printed.
 impl Bounds {
     pub fn compute<Doc: PrettyDocument>(doc: &Doc) -> Bounds {
-        compute_bounds(&child_bounds(doc), &expanded_notation(doc))
+        compute_bounds_with(&child_bounds(doc), text_bounds_of(doc).as_ref(),
+                            &expanded_notation(doc), doc.width_fn())
     }
 }
 
 impl Layouts {
     pub fn compute<Doc: PrettyDocument>(doc: &Doc) -> Layouts {
-        compute_layouts(&child_bounds(doc), &expanded_notation(doc))
+        compute_layouts_with(&child_bounds(doc), text_bounds_of(doc).as_ref(),
+                             &expanded_notation(doc), doc.width_fn())
+    }
+}
+
+/// Pretty-print the entire document, sharing Layouts between identical
+/// subtrees through `cache` (see `PrettyDocument::pretty_print`).
+pub(crate) fn pretty_print_with_cache<Doc, Screen>(doc: &Doc, screen: &mut Screen,
+                                                   cache: &mut LayoutCache<Doc>)
+                                                   -> Result<(), Screen::Error>
+    where Screen: PrettyScreen, Doc: PrettyDocument
+{
+    refresh_bounds(doc);
+    // TODO: wrong
+    let size = screen.size()?;
+    let lay = layouts_within(doc, size.width).fit_bound(size);
+    pp(doc, screen, lay, size.width, &[], cache)
+}
+
+/// The Layouts computed during one layout pass, at one width, by
+/// structural hash (see `PrettyDocument::structural_hash`). Each subtree
+/// with a hash is laid out once, and identical subtrees reuse its
+/// Layouts. Hashes are confirmed by comparing the subtrees, so a
+/// collision only costs time.
+pub(crate) struct LayoutCache<Doc> {
+    layouts: HashMap<u64, Vec<(Doc, Layouts)>>,
+    #[cfg(test)]
+    hits: usize
+}
+
+impl<Doc: PrettyDocument> LayoutCache<Doc> {
+    pub(crate) fn new() -> LayoutCache<Doc> {
+        LayoutCache {
+            layouts: HashMap::new(),
+            #[cfg(test)]
+            hits: 0
+        }
+    }
+
+    /// The number of times a subtree's Layouts were reused.
+    #[cfg(test)]
+    pub(crate) fn hits(&self) -> usize {
+        self.hits
+    }
+
+    // `layouts_within`, reusing the Layouts of an identical subtree.
+    fn layouts_within(&mut self, doc: &Doc, width: Col) -> Layouts {
+        let hash = match doc.structural_hash() {
+            None => return layouts_within(doc, width),
+            Some(hash) => hash
+        };
+        let twin = self.layouts.get(&hash)
+            .and_then(|twins| twins.iter().find(|(twin, _)| deep_eq(twin, doc)));
+        if let Some((_, layouts)) = twin {
+            #[cfg(test)] (self.hits += 1);
+            return layouts.clone();
+        }
+        let layouts = layouts_within(doc, width);
+        self.layouts.entry(hash).or_default().push((doc.clone(), layouts.clone()));
+        layouts
+    }
+}
+
+/// Bounds kept outside of a document, keyed by node id, for documents
+/// whose nodes have nowhere to store their own. A `PrettyDocument` can
+/// implement `bounds`, `child_bounds_cache`, `is_dirty`, and `set_bounds`
+/// with it: a node is dirty if its Bounds are missing, and editing a node
+/// should `invalidate` it and its ancestors.
+pub struct BoundsCache<Id> {
+    bounds: RefCell<HashMap<Id, Bounds>>
+}
+
+impl<Id: Hash + Eq> BoundsCache<Id> {
+    pub fn new() -> BoundsCache<Id> {
+        BoundsCache {
+            bounds: RefCell::new(HashMap::new())
+        }
+    }
+
+    /// The cached Bounds of the node with the given id, or `None` if they
+    /// have not been computed since it was last invalidated.
+    pub fn get(&self, id: &Id) -> Option<Bounds> {
+        self.bounds.borrow().get(id).cloned()
+    }
+
+    /// Are the Bounds of the node with the given id cached?
+    pub fn contains(&self, id: &Id) -> bool {
+        self.bounds.borrow().contains_key(id)
+    }
+
+    /// Cache the Bounds of the node with the given id.
+    pub fn insert(&self, id: Id, bounds: Bounds) {
+        self.bounds.borrow_mut().insert(id, bounds);
+    }
+
+    /// Forget the Bounds of the node with the given id, so that it is
+    /// dirty.
+    pub fn invalidate(&self, id: &Id) {
+        self.bounds.borrow_mut().remove(id);
+    }
+
+    /// The cached Bounds of each of the nodes with the given ids, in
+    /// order. (This is what `child_bounds_cache` should return.)
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of them are missing.
+    pub fn get_all<'a, I>(&self, ids: I) -> Vec<Bounds>
+        where I: IntoIterator<Item = &'a Id>, Id: 'a
+    {
+        let bounds = self.bounds.borrow();
+        ids.into_iter()
+            .map(|id| bounds.get(id).cloned().expect("BoundsCache: missing bounds"))
+            .collect()
+    }
+}
+
+impl<Id: Hash + Eq> Default for BoundsCache<Id> {
+    fn default() -> BoundsCache<Id> {
+        BoundsCache::new()
+    }
+}
+
+/// Recompute the cached Bounds of every dirty node in this document,
+/// reusing the cached Bounds of clean nodes. Returns the number of nodes
+/// whose Bounds were recomputed.
+///
+/// A dirty subtree that is identical to one already refreshed (see
+/// `PrettyDocument::structural_hash`) copies its Bounds instead, and its
+/// nodes are not counted. Hashes are confirmed by comparing the subtrees,
+/// so a collision only costs time.
+pub fn refresh_bounds<Doc: PrettyDocument>(doc: &Doc) -> usize {
+    refresh_bounds_cached(doc, &mut HashMap::new())
+}
+
+// `cache` holds the subtrees refreshed so far, by structural hash.
+fn refresh_bounds_cached<Doc: PrettyDocument>(doc: &Doc, cache: &mut HashMap<u64, Vec<Doc>>)
+                                              -> usize
+{
+    if !doc.is_dirty() {
+        return 0;
+    }
+    let hash = doc.structural_hash();
+    if let Some(hash) = hash {
+        let twin = cache.get(&hash)
+            .and_then(|twins| twins.iter().find(|twin| deep_eq(*twin, doc)));
+        if let Some(twin) = twin {
+            copy_bounds(twin, doc);
+            return 0;
+        }
+    }
+    let mut count = 0;
+    if has_children(doc) {
+        for child in doc.children() {
+            count += refresh_bounds_cached(&child, cache);
+        }
+    }
+    doc.set_bounds(Bounds::compute(doc));
+    if let Some(hash) = hash {
+        cache.entry(hash).or_default().push(doc.clone());
+    }
+    count + 1
+}
+
+// Give every dirty node in `to` the Bounds of the corresponding node in
+// `from`, which has the same contents.
+fn copy_bounds<Doc: PrettyDocument>(from: &Doc, to: &Doc) {
+    if !to.is_dirty() {
+        return;
+    }
+    if has_children(to) {
+        for (from, to) in from.children().iter().zip(to.children().iter()) {
+            copy_bounds(from, to);
+        }
     }
+    to.set_bounds(from.bounds());
 }
 
+// Do the subtrees have the same contents, as far as laying them out is
+// concerned?
+fn deep_eq<Doc: PrettyDocument>(a: &Doc, b: &Doc) -> bool {
+    if a.text() != b.text()
+        || a.precedence() != b.precedence()
+        || a.notation() != b.notation()
+        || a.summary_notation() != b.summary_notation()
+    {
+        return false;
+    }
+    if !has_children(a) {
+        return true;
+    }
+    let (a_children, b_children) = (a.children(), b.children());
+    a_children.len() == b_children.len()
+        && a_children.iter().zip(b_children.iter()).all(|(a, b)| deep_eq(a, b))
+}
+
+// Whether this node may have children: it is not texty, or it is mixed.
+fn has_children<Doc: PrettyDocument>(doc: &Doc) -> bool {
+    doc.text().is_none() || doc.arity() > 0
+}
+
+// The Bounds of the node's children. (Those of its text, if it has any,
+// are kept separately: see `text_bounds_of`.)
 fn child_bounds<Doc: PrettyDocument>(doc: &Doc) -> Vec<Bounds> {
+    if has_children(doc) { doc.child_bounds_cache() } else { vec!() }
+}
+
+// The Bounds of the node's text, if it has any.
+fn text_bounds_of<Doc: PrettyDocument>(doc: &Doc) -> Option<Bounds> {
+    doc.text().map(|text| text_bounds_with(text, doc.width_fn()))
+}
+
+fn expanded_notation<Doc: PrettyDocument>(doc: &Doc) -> Notation {
+    expand(doc, doc.notation())
+}
+
+fn expand<Doc: PrettyDocument>(doc: &Doc, notation: &Notation) -> Notation {
+    if !has_children(doc) {
+        return notation.expand_text(doc.text().expect("expand: missing text"));
+    }
+    let children = doc.children();
+    let precedences: Vec<Option<Precedence>> =
+        children.iter().map(|child| child.precedence()).collect();
     match doc.text() {
-        None => doc.children().iter().map(|child| child.bounds()).collect(),
-        Some(text) => vec!(text_bounds(text))
+        None => notation.expand_streaming(children.len(), &precedences),
+        Some(text) => notation.expand_mixed(text, &precedences)
     }
 }
 
-fn expanded_notation<Doc: PrettyDocument>(doc: &Doc) -> Notation {
-    let len = match doc.text() {
-        None       => doc.children().len(),
-        Some(text) => text.chars().count()
+/// The Bounds of this node, if it must be displayed within `width`.
+/// Descendants that are too wide are replaced by their summary notation.
+fn bounds_within<Doc: PrettyDocument>(doc: &Doc, width: Col) -> Bounds {
+    let bounds = doc.bounds();
+    if bounds.fits_width(width) {
+        bounds
+    } else {
+        let (child_bounds, notation) = plan_within(doc, width);
+        compute_bounds_with(&child_bounds, text_bounds_of(doc).as_ref(), &notation,
+                            doc.width_fn())
+    }
+}
+
+/// The Layouts of this node, if it must be displayed within `width`.
+/// Agrees with `bounds_within`.
+fn layouts_within<Doc: PrettyDocument>(doc: &Doc, width: Col) -> Layouts {
+    let (child_bounds, notation) = plan_within(doc, width);
+    compute_layouts_with(&child_bounds, text_bounds_of(doc).as_ref(), &notation, doc.width_fn())
+}
+
+/// Pick the child bounds and notation with which to lay out this node,
+/// so that it fits within `width` if at all possible. In order of
+/// preference: the node's full notation; its full notation, with its
+/// widest children summarized one at a time until it fits; its own
+/// summary notation.
+fn plan_within<Doc: PrettyDocument>(doc: &Doc, width: Col) -> (Vec<Bounds>, Notation) {
+    let notation = expanded_notation(doc);
+    if doc.bounds().fits_width(width) {
+        return (child_bounds(doc), notation);
+    }
+    let mut child_bounds = child_bounds_within(doc, width);
+    let text_bounds = text_bounds_of(doc);
+    let fits = |child_bounds: &[Bounds]| {
+        compute_bounds_with(child_bounds, text_bounds.as_ref(), &notation, doc.width_fn())
+            .fits_width(width)
+    };
+    if has_children(doc) {
+        let children = doc.children();
+        let mut summarized = vec!(false; children.len());
+        while !fits(&child_bounds) {
+            let widest = (0..children.len())
+                .filter(|&i| !summarized[i] && children[i].summary_notation().is_some())
+                .max_by_key(|&i| child_bounds[i].min_width().unwrap_or(Col::MAX));
+            match widest {
+                None => break,
+                Some(i) => {
+                    let (bounds, summary) = summary_plan(&children[i], width)
+                        .expect("summary_plan: missing summary notation");
+                    child_bounds[i] = compute_bounds_with(&bounds,
+                                                          text_bounds_of(&children[i]).as_ref(),
+                                                          &summary, doc.width_fn());
+                    summarized[i] = true;
+                }
+            }
+        }
+        if fits(&child_bounds) {
+            return (child_bounds, notation);
+        }
+    }
+    match summary_plan(doc, width) {
+        Some(plan) => plan,
+        None => (child_bounds, notation)
+    }
+}
+
+/// The child bounds and summary notation with which to display this node
+/// in summary, if it has a summary notation.
+fn summary_plan<Doc: PrettyDocument>(doc: &Doc, width: Col) -> Option<(Vec<Bounds>, Notation)> {
+    doc.summary_notation().map(|summary| {
+        (child_bounds_within(doc, width), expand(doc, summary))
+    })
+}
+
+// Like `child_bounds`, but with each child's Bounds as in `bounds_within`.
+fn child_bounds_within<Doc: PrettyDocument>(doc: &Doc, width: Col) -> Vec<Bounds> {
+    if has_children(doc) {
+        doc.children().iter().map(|child| bounds_within(child, width)).collect()
+    } else {
+        vec!()
+    }
+}
+
+impl LayoutRegion {
+    /// Render this layout of `doc`, including its text and children,
+    /// overlaying each highlight's style (see `Style::overlay`) on the
+    /// cells inside of its region. Highlights are applied in order.
+    pub fn render_with_highlights<Doc, Screen>(&self, doc: &Doc,
+                                               highlights: &[(Region, Style)],
+                                               screen: &mut Screen)
+                                               -> Result<(), Screen::Error>
+        where Doc: PrettyDocument, Screen: PrettyScreen
+    {
+        refresh_bounds(doc);
+        let width = screen.size()?.width;
+        pp(doc, screen, self.clone(), width, highlights, &mut LayoutCache::new())
+    }
+}
+
+// The layout of `child`, positioned in `region` of its parent's layout.
+fn child_layout<Doc: PrettyDocument>(child: &Doc, region: Region, width: Col,
+                                     cache: &mut LayoutCache<Doc>)
+                                     -> LayoutRegion
+{
+    // TODO: shouldn't need to shift layout here?
+    // If the child was summarized, only its summary will fit.
+    let mut child_lay = match cache.layouts_within(child, width).try_fit_bound(region.bound) {
+        Some(child_lay) => child_lay,
+        None => {
+            let (child_bounds, summary) = summary_plan(child, width)
+                .expect("pretty_print: child does not fit and has no summary");
+            compute_layouts_with(&child_bounds, text_bounds_of(child).as_ref(), &summary,
+                                 child.width_fn())
+                .fit_bound(region.bound)
+        }
     };
-    doc.notation().expand(len)
+    child_lay.shift_by(region.pos);
+    child_lay
+}
+
+// The path from `doc` (which is at `path`) to its first overflowing
+// descendant, laid out as `lay`. See `PrettyDocument::first_overflow`.
+fn find_overflow<Doc: PrettyDocument>(doc: &Doc, lay: &LayoutRegion, width: Col,
+                                      path: Vec<usize>, cache: &mut LayoutCache<Doc>)
+                                      -> Option<Vec<usize>>
+{
+    if lay.region.pos.col + lay.region.bound.width <= width {
+        return None;
+    }
+    let mut children = vec!();
+    child_regions(&lay.layout, &mut children);
+    for (i, region) in children {
+        let child = doc.child(i);
+        let mut child_path = path.clone();
+        child_path.push(i);
+        let child_lay = child_layout(&child, region, width, cache);
+        if let Some(overflow) = find_overflow(&child, &child_lay, width, child_path, cache) {
+            return Some(overflow);
+        }
+    }
+    Some(path)
+}
+
+// The children in this layout, with their regions, in order.
+fn child_regions(layout: &Layout, children: &mut Vec<(usize, Region)>) {
+    match layout {
+        Empty | Literal(_, _) | Text(_) => (),
+        Child(i) => unreachable!("child_regions: child {} has no region", i),
+        Concat(lay1, lay2) | Horz(lay1, lay2) | Vert(lay1, lay2) => {
+            for lay in &[lay1, lay2] {
+                match &lay.layout {
+                    Child(i) => children.push((*i, lay.region)),
+                    layout => child_regions(layout, children)
+                }
+            }
+        }
+    }
+}
+
+fn overridden_style<Doc: PrettyDocument>(doc: &Doc, style: Style) -> Style {
+    match doc.style_override() {
+        Some(over) => style.overlay(over),
+        None => style
+    }
+}
+
+// Print `text` at `pos`, overlaying the style of each highlight on the
+// characters inside of its region.
+fn print_highlighted<Doc, Screen>(doc: &Doc, screen: &mut Screen, mut pos: Pos, text: &str,
+                                  style: Style, highlights: &[(Region, Style)])
+                                  -> Result<(), Screen::Error>
+    where Screen: PrettyScreen, Doc: PrettyDocument
+{
+    if highlights.is_empty() {
+        return screen.print(pos, text, style);
+    }
+    for ch in text.chars() {
+        let ch = ch.to_string();
+        let style = highlights.iter()
+            .filter(|(region, _)| region.contains(pos))
+            .fold(style, |style, (_, over)| style.overlay(*over));
+        screen.print(pos, &ch, style)?;
+        pos.col += doc.width_fn().width(&ch) as Col;
+    }
+    Ok(())
 }
 
-// TODO: shading and highlighting
-fn pp<Doc, Screen>(doc: &Doc, screen: &mut Screen, lay: LayoutRegion)
+// TODO: shading
+fn pp<Doc, Screen>(doc: &Doc, screen: &mut Screen, lay: LayoutRegion, width: Col,
+                   highlights: &[(Region, Style)], cache: &mut LayoutCache<Doc>)
                    -> Result<(), Screen::Error>
     where Screen: PrettyScreen, Doc: PrettyDocument
 {
@@ -80,31 +638,31 @@ fn pp<Doc, Screen>(doc: &Doc, screen: &mut Screen, lay: LayoutRegion)
             Ok(())
         }
         Literal(text, style) => {
-            screen.print(lay.region.pos, &text, style)
+            print_highlighted(doc, screen, lay.region.pos, &text,
+                              overridden_style(doc, style), highlights)
         }
         Text(style) => {
             let text = doc.text()
                 .expect("Expected text while transcribing; found branch node");
-            screen.print(lay.region.pos, text, style)
+            print_highlighted(doc, screen, lay.region.pos, text,
+                              overridden_style(doc, style), highlights)
         }
         Child(i) => {
             let child = &doc.child(i);
-            // TODO: shouldn't need to shift layout here?
-            let mut child_lay = Layouts::compute(child).fit_bound(lay.region.bound);
-            child_lay.shift_by(lay.region.pos);
-            pp(child, screen, child_lay)
+            let child_lay = child_layout(child, lay.region, width, cache);
+            pp(child, screen, child_lay, width, highlights, cache)
         }
         Concat(box lay1, box lay2) => {
-            pp(doc, screen, lay1)?;
-            pp(doc, screen, lay2)
+            pp(doc, screen, lay1, width, highlights, cache)?;
+            pp(doc, screen, lay2, width, highlights, cache)
         }
         Horz(box lay1, box lay2) => {
-            pp(doc, screen, lay1)?;
-            pp(doc, screen, lay2)
+            pp(doc, screen, lay1, width, highlights, cache)?;
+            pp(doc, screen, lay2, width, highlights, cache)
         }
         Vert(box lay1, box lay2) => {
-            pp(doc, screen, lay1)?;
-            pp(doc, screen, lay2)
+            pp(doc, screen, lay1, width, highlights, cache)?;
+            pp(doc, screen, lay2, width, highlights, cache)
         }
     }
 }
@@ -1,6 +1,6 @@
 use std::fmt;
 
-use crate::geometry::{Col, Pos, Bound, Region};
+use crate::geometry::{Col, Pos, Bound, Region, Direction};
 use crate::style::{Shade, Style};
 use super::pretty_screen::PrettyScreen;
 
@@ -8,7 +8,33 @@ use super::pretty_screen::PrettyScreen;
 /// Render a document in plain text.
 pub struct PlainText {
     width: usize,
-    lines: Vec<Vec<char>>
+    lines: Vec<Vec<char>>,
+    /// If set, lines wider than `width` are wrapped when displayed, and
+    /// this marker is drawn at the end of each wrapped segment.
+    wrap_marker: Option<char>,
+    indent_with: IndentStyle,
+    /// If set, lines wider than this are reported by `over_limit_lines`.
+    soft_limit: Option<usize>,
+    direction: Direction
+}
+
+/// How `PlainText` displays the indentation at the start of each line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndentStyle {
+    /// Indent with spaces.
+    Spaces,
+    /// Indent with as many tabs as fit, where a tab is the given number of
+    /// columns wide, followed by spaces for the remainder.
+    Tabs(usize)
+}
+
+/// Render a document as styled spans: for each line, a list of pieces
+/// of text, each with its style, in order from left to right. Adjacent
+/// characters with the same style are merged into one span. This is
+/// handy for handing off to a rich-text widget.
+pub struct SpanText {
+    width: usize,
+    lines: Vec<Vec<(char, Style)>>
 }
 
 const DEFAULT_WIDTH: Col = 80;
@@ -16,9 +42,17 @@ const DEFAULT_WIDTH: Col = 80;
 impl fmt::Display for PlainText {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for (i, line) in self.lines.iter().enumerate() {
-            for ch in line {
-                write!(f, "{}", ch)?;
+            let mut line = &line[..];
+            let mut first_segment = true;
+            if let Some(marker) = self.wrap_marker {
+                while self.width > 0 && line.len() > self.width {
+                    self.write_segment(f, &line[.. self.width - 1], first_segment)?;
+                    writeln!(f, "{}", marker)?;
+                    line = &line[self.width - 1 ..];
+                    first_segment = false;
+                }
             }
+            self.write_segment(f, line, first_segment)?;
             if i + 1 != self.lines.len() {
                 write!(f, "\n")?;
             }
@@ -31,10 +65,86 @@ impl PlainText {
     pub fn new(width: usize) -> PlainText {
         PlainText {
             width: width,
-            lines: vec!()
+            lines: vec!(),
+            wrap_marker: None,
+            indent_with: IndentStyle::Spaces,
+            soft_limit: None,
+            direction: Direction::Ltr
         }
     }
+
+    /// Wrap lines that are wider than the screen, drawing `marker` where
+    /// they were wrapped. This only affects how the text is displayed,
+    /// not how the document is laid out.
+    pub fn with_wrap_marker(mut self, marker: char) -> PlainText {
+        self.wrap_marker = Some(marker);
+        self
+    }
     
+    /// Choose how to display the indentation at the start of each line.
+    /// Like `with_wrap_marker`, this does not affect layout: it is still
+    /// measured in columns.
+    pub fn with_indent(mut self, indent_with: IndentStyle) -> PlainText {
+        self.indent_with = indent_with;
+        self
+    }
+
+    /// Display lines right-to-left: each line starts at the right edge of
+    /// the screen, and runs leftward. Like `with_wrap_marker`, this does not
+    /// affect layout. Text that sticks out past the left edge is cut off,
+    /// rather than drawn over the start of the line.
+    pub fn with_direction(mut self, direction: Direction) -> PlainText {
+        self.direction = direction;
+        self
+    }
+
+    /// Set a soft limit on line length. Unlike the screen width, this does
+    /// not affect layout: it only determines which lines are reported by
+    /// `over_limit_lines`.
+    pub fn set_soft_limit(&mut self, limit: usize) {
+        self.soft_limit = Some(limit);
+    }
+
+    /// The (0-indexed) rows of the lines printed so far that are wider than
+    /// the soft limit, not counting trailing spaces. Empty if no soft limit
+    /// has been set.
+    pub fn over_limit_lines(&self) -> Vec<usize> {
+        let limit = match self.soft_limit {
+            None => return vec!(),
+            Some(limit) => limit
+        };
+        self.lines.iter().enumerate()
+            .filter(|(_, line)| {
+                let len = line.len() - line.iter().rev().take_while(|ch| **ch == ' ').count();
+                len > limit
+            })
+            .map(|(row, _)| row)
+            .collect()
+    }
+
+    // Write part of a line. If it's the start of the line, its leading
+    // spaces are displayed according to `indent_with`.
+    fn write_segment(&self, f: &mut fmt::Formatter, segment: &[char], start_of_line: bool)
+                     -> fmt::Result
+    {
+        let indent = if start_of_line {
+            segment.iter().take_while(|ch| **ch == ' ').count()
+        } else {
+            0
+        };
+        match self.indent_with {
+            IndentStyle::Tabs(tab_width) if tab_width > 0 => {
+                write!(f, "{}", "\t".repeat(indent / tab_width))?;
+                write!(f, "{}", " ".repeat(indent % tab_width))?;
+            }
+            _ => write!(f, "{}", " ".repeat(indent))?
+        }
+        for ch in &segment[indent ..] {
+            write!(f, "{}", ch)?;
+        }
+        Ok(())
+    }
+
     fn get_mut_line(&mut self, row: usize) -> &mut Vec<char> {
         if self.lines.len() < row + 1 {
             self.lines.resize(row + 1, vec!());
@@ -61,12 +171,77 @@ impl PrettyScreen for PlainText {
     fn print(&mut self, pos: Pos, text: &str, _style: Style)
              -> Result<(), Self::Error>
     {
-        let slice = self.get_mut_slice(
-            pos.row as usize,
-            pos.col as usize,
-            text.chars().count());
+        let len = text.chars().count();
+        let overhang = self.direction.overhang(pos, len as Col, self.width as Col) as usize;
+        let pos = self.direction.place(pos, len as Col, self.width as Col);
+        let chars: Vec<char> = match self.direction {
+            Direction::Ltr => text.chars().collect(),
+            Direction::Rtl => text.chars().rev().skip(overhang).collect()
+        };
+        let len = len - overhang;
+        let slice = self.get_mut_slice(pos.row as usize, pos.col as usize, len);
+        slice.copy_from_slice(&chars);
+        Ok(())
+    }
+
+    fn shade(&mut self, _region: Region, _shade: Shade)
+             -> Result<(), Self::Error>
+    {
+        Ok(())
+    }
+
+    fn highlight(&mut self, _pos: Pos, _style: Style)
+                 -> Result<(), Self::Error>
+    {
+        Ok(())
+    }
+}
+
+impl SpanText {
+    pub fn new(width: usize) -> SpanText {
+        SpanText {
+            width,
+            lines: vec!()
+        }
+    }
+
+    /// The spans of each line. Gaps between printed text are filled with
+    /// spaces in the plain style.
+    pub fn lines(&self) -> Vec<Vec<(String, Style)>> {
+        self.lines.iter().map(|line| {
+            let mut spans: Vec<(String, Style)> = vec!();
+            for &(ch, style) in line {
+                match spans.last_mut() {
+                    Some((text, last_style)) if *last_style == style => text.push(ch),
+                    _ => spans.push((ch.to_string(), style))
+                }
+            }
+            spans
+        }).collect()
+    }
+}
+
+impl PrettyScreen for SpanText {
+    type Error = fmt::Error;
+
+    fn size(&self) -> Result<Bound, Self::Error> {
+        Ok(Bound::infinite_scroll(self.width as Col))
+    }
+
+    fn print(&mut self, pos: Pos, text: &str, style: Style)
+             -> Result<(), Self::Error>
+    {
+        let (row, col) = (pos.row as usize, pos.col as usize);
+        if self.lines.len() < row + 1 {
+            self.lines.resize(row + 1, vec!());
+        }
+        let line = &mut self.lines[row];
+        let len = text.chars().count();
+        if line.len() < col + len {
+            line.resize(col + len, (' ', Style::plain()));
+        }
         for (i, ch) in text.chars().enumerate() {
-            slice[i] = ch;
+            line[col + i] = (ch, style);
         }
         Ok(())
     }
@@ -101,6 +101,21 @@ impl Style {
         Style::new(color, Emph::plain(), Shade::background(), true)
     }
 
+    /// Draw `over` on top of this style, e.g. to highlight a selection.
+    /// The overlay's shade and reversal win, emphasis from either style
+    /// is kept, and the text keeps its own color.
+    pub fn overlay(self, over: Style) -> Style {
+        Style {
+            color: self.color,
+            emph: Emph {
+                bold: self.emph.bold || over.emph.bold,
+                underlined: self.emph.underlined || over.emph.underlined
+            },
+            shade: over.shade,
+            reversed: over.reversed
+        }
+    }
+
     /// Fully customized style.
     pub fn new(color: Color, emph: Emph, shade: Shade, reversed: bool) -> Style {
         Style{